@@ -1,13 +1,22 @@
 //! Program for making simple RPG maps. This is the Rust language implementation.
-use clap::{command, value_parser, Arg};
+use std::path::PathBuf;
+
+use clap::{command, error::ErrorKind, value_parser, Arg, ArgAction};
 use eframe::egui::{Style, Visuals};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 use rpgtools::error::Result;
+use rpgtools::map::cell::{Cell, Point as CornerPoint, Wall};
+use rpgtools::map::file::FILE_EXTENSION;
 use rpgtools::map::Area;
-use rpgtools::map::{Point, GridMap};
+use rpgtools::map::{
+    Connectivity, Dungeon, GridMap, MazeAlgorithm, MazeStyle, PathCosts, Point, RouteMethod,
+    TileSet,
+};
 
 fn main() -> Result<()> {
-    let cli = command!()
+    let mut command = command!()
         .author("Aaron Seilis <aaron.seilis@seilis.ca>")
         .about("A simple map generator for role playing games")
         .arg(
@@ -33,7 +42,7 @@ fn main() -> Result<()> {
                 .short('s')
                 .long("style")
                 .default_value("halls")
-                .value_parser(["halls", "cave"])
+                .value_parser(["halls", "cave", "bsp", "tunnels", "maze", "wfc"])
                 .help("The style of map to generate"),
         )
         .arg(
@@ -69,7 +78,133 @@ fn main() -> Result<()> {
                 .value_parser(value_parser!(u64).range(1..))
                 .help("The size of generated rooms"),
         )
-        .get_matches();
+        .arg(
+            Arg::new("route")
+                .long("route")
+                .default_value("manhattan")
+                .value_parser(["manhattan", "direct", "subway", "pathfind"])
+                .help("How hallways are routed between rooms; pathfind goes around rooms (halls and cave only)"),
+        )
+        .arg(
+            Arg::new("corridor_width")
+                .long("corridor-width")
+                .default_value("1")
+                .value_name("INT")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("The width of hallways in cells; doors are only placed across 1 wide hallways (halls and cave only)"),
+        )
+        .arg(
+            Arg::new("connectivity")
+                .long("connectivity")
+                .default_value("nearby")
+                .value_parser(["nearby", "tree"])
+                .help("Which rooms are joined; tree always joins every room (halls and cave only)"),
+        )
+        .arg(
+            Arg::new("loops")
+                .long("loops")
+                .default_value("0.2")
+                .value_name("SHARE")
+                .value_parser(parse_share)
+                .help("Extra hallways that make loops, as a share of the ones needed to join every room (tree only)"),
+        )
+        .arg(
+            Arg::new("min_leaf")
+                .long("min-leaf")
+                .default_value("6")
+                .value_name("INT")
+                .value_parser(value_parser!(u64).range(4..))
+                .help("The smallest area that a room is placed in (bsp only)"),
+        )
+        .arg(
+            Arg::new("max_leaf")
+                .long("max-leaf")
+                .default_value("15")
+                .value_name("INT")
+                .value_parser(value_parser!(u64).range(4..))
+                .help("The largest area that a room is placed in (bsp only)"),
+        )
+        .arg(
+            Arg::new("walkers")
+                .long("walkers")
+                .default_value("1")
+                .value_name("INT")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("The number of walkers that start digging (tunnels only)"),
+        )
+        .arg(
+            Arg::new("coverage")
+                .long("coverage")
+                .default_value("0.35")
+                .value_name("FRACTION")
+                .value_parser(parse_fraction)
+                .help("The fraction of the map to dig out, from 0 to 1 (tunnels only)"),
+        )
+        .arg(
+            Arg::new("turn_chance")
+                .long("turn-chance")
+                .default_value("0.2")
+                .value_name("FRACTION")
+                .value_parser(parse_fraction)
+                .help("The chance that a walker turns on each step (tunnels only)"),
+        )
+        .arg(
+            Arg::new("spawn_chance")
+                .long("spawn-chance")
+                .default_value("0.02")
+                .value_name("FRACTION")
+                .value_parser(parse_fraction)
+                .help("The chance that a walker spawns another walker on each step (tunnels only)"),
+        )
+        .arg(
+            Arg::new("maze_algorithm")
+                .long("maze-algorithm")
+                .default_value("backtracker")
+                .value_parser(["backtracker", "prim", "kruskal", "eller"])
+                .help("The algorithm used to carve the maze (maze only)"),
+        )
+        .arg(
+            Arg::new("braid")
+                .long("braid")
+                .default_value("0")
+                .value_name("FRACTION")
+                .value_parser(parse_fraction)
+                .help("The share of dead ends to remove, from 0 to 1 (maze only)"),
+        )
+        .arg(
+            Arg::new("maze_walls")
+                .long("maze-walls")
+                .action(ArgAction::SetTrue)
+                .help("Draw the maze with walls between cells instead of corridors (maze only)"),
+        )
+        .arg(
+            Arg::new("rules")
+                .long("rules")
+                .value_name("FILE")
+                .help("The tile rule file to fill the map from; defaults to winding corridors (wfc only)"),
+        )
+        .arg(
+            Arg::new("levels")
+                .long("levels")
+                .default_value("1")
+                .value_name("INT")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("The number of levels, linked by stairs"),
+        )
+        .arg(
+            Arg::new("pillars")
+                .long("pillars")
+                .action(ArgAction::SetTrue)
+                .help("Line large rooms with rows of pillars (halls only)"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("INT")
+                .value_parser(value_parser!(u64))
+                .help("The seed for the random generator; the same seed and options make the same map"),
+        );
+    let cli = command.get_matches_mut();
 
     // Unpack our arguments
     let style: String = cli
@@ -85,26 +220,122 @@ fn main() -> Result<()> {
     let num_rooms: usize = *cli
         .get_one::<u64>("num_rooms")
         .expect("failed to get num_rooms; this is a bug") as usize;
+    let route = match cli
+        .get_one::<String>("route")
+        .expect("failed to get route; this is a bug")
+        .as_str()
+    {
+        "manhattan" => RouteMethod::Manhattan,
+        "direct" => RouteMethod::Direct,
+        "subway" => RouteMethod::Subway,
+        "pathfind" => RouteMethod::Pathfind(PathCosts::default()),
+        _ => unreachable!(),
+    };
+    let corridor_width: usize = *cli
+        .get_one::<u64>("corridor_width")
+        .expect("failed to get corridor_width; this is a bug") as usize;
+    let loops: f64 = *cli
+        .get_one::<f64>("loops")
+        .expect("failed to get loops; this is a bug");
+    let connectivity = match cli
+        .get_one::<String>("connectivity")
+        .expect("failed to get connectivity; this is a bug")
+        .as_str()
+    {
+        "nearby" => Connectivity::Nearby,
+        "tree" => Connectivity::SpanningTree(loops),
+        _ => unreachable!(),
+    };
+    let min_leaf: usize = *cli
+        .get_one::<u64>("min_leaf")
+        .expect("failed to get min_leaf; this is a bug") as usize;
+    let max_leaf: usize = *cli
+        .get_one::<u64>("max_leaf")
+        .expect("failed to get max_leaf; this is a bug") as usize;
+    if min_leaf > max_leaf {
+        command
+            .error(ErrorKind::ArgumentConflict, "--min-leaf can't be larger than --max-leaf")
+            .exit();
+    }
+    let walkers: usize = *cli
+        .get_one::<u64>("walkers")
+        .expect("failed to get walkers; this is a bug") as usize;
+    let coverage: f64 = *cli
+        .get_one::<f64>("coverage")
+        .expect("failed to get coverage; this is a bug");
+    let turn_chance: f64 = *cli
+        .get_one::<f64>("turn_chance")
+        .expect("failed to get turn_chance; this is a bug");
+    let spawn_chance: f64 = *cli
+        .get_one::<f64>("spawn_chance")
+        .expect("failed to get spawn_chance; this is a bug");
+    let maze_algorithm = match cli
+        .get_one::<String>("maze_algorithm")
+        .expect("failed to get maze_algorithm; this is a bug")
+        .as_str()
+    {
+        "backtracker" => MazeAlgorithm::RecursiveBacktracker,
+        "prim" => MazeAlgorithm::Prim,
+        "kruskal" => MazeAlgorithm::Kruskal,
+        "eller" => MazeAlgorithm::Eller,
+        _ => unreachable!(),
+    };
+    let braid: f64 = *cli
+        .get_one::<f64>("braid")
+        .expect("failed to get braid; this is a bug");
+    let maze_style = if cli.get_flag("maze_walls") {
+        MazeStyle::Walls
+    } else {
+        MazeStyle::Cells
+    };
+    let tiles = match cli.get_one::<String>("rules") {
+        Some(path) => TileSet::load(path)?,
+        None => TileSet::corridors(),
+    };
+    let levels: usize = *cli
+        .get_one::<u64>("levels")
+        .expect("failed to get levels; this is a bug") as usize;
+    let pillars = cli.get_flag("pillars");
+    let seed: u64 = cli
+        .get_one::<u64>("seed")
+        .copied()
+        .unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
 
-    // Initialize our map
-    let mut map = GridMap::new(width, height);
+    let mut rng = StdRng::seed_from_u64(seed);
 
-    // Build map based on map type
-    match style.as_str() {
-        "halls" => {
-            map.generate_dungeon(num_rooms, 5);
-            let point: Point = (width / 2, height / 2).try_into().unwrap();
-            map.place_entrance_near(point)
-                .expect("width/height is outside of map");
+    // Build each level based on map type
+    let mut failure = None;
+    let dungeon = Dungeon::generate(width, height, levels, &mut rng, |map, rng| {
+        match style.as_str() {
+            "halls" => map.generate_dungeon(num_rooms, 5, pillars, route, corridor_width, connectivity, rng),
+            "cave" => map.generate_cave(4, 50, route, corridor_width, connectivity, rng),
+            "bsp" => map.generate_bsp(min_leaf, max_leaf, rng),
+            "tunnels" => map.generate_tunnels(walkers, coverage, turn_chance, spawn_chance, rng),
+            "maze" => map.generate_maze(maze_algorithm, braid, maze_style, rng),
+            "wfc" => {
+                if let Err(e) = map.generate_wfc(&tiles, rng) {
+                    failure.get_or_insert(e);
+                }
+            }
+            _ => unreachable!(),
         }
-        "cave" => {
-            map.generate_cave(4, 50);
-            let point: Point = (width / 2, height / 2).try_into().unwrap();
-            map.place_entrance_near(point)
-                .expect("width/height is outside of map");
+
+        // Double check that the spanning tree really did join everything up
+        if matches!(style.as_str(), "halls" | "cave") && connectivity != Connectivity::Nearby {
+            if let Err(e) = map.check_connected() {
+                failure.get_or_insert(e);
+            }
         }
-        _ => unreachable!(),
+    });
+    if let Some(e) = failure {
+        return Err(e);
     }
+    let mut dungeon = dungeon?;
+
+    // The entrance is on the top level
+    let point: Point = (width / 2, height / 2).try_into().unwrap();
+    dungeon[0].place_entrance_near(point, &mut rng)?;
 
     let options = eframe::NativeOptions::default();
     eframe::run_native(
@@ -116,7 +347,15 @@ fn main() -> Result<()> {
                 ..Style::default()
             };
             creation_context.egui_ctx.set_style(style);
-            Ok(Box::new(RpgMapGui::new(map)))
+            Ok(Box::new(RpgMapGui::new(
+                dungeon,
+                seed,
+                pillars,
+                route,
+                corridor_width,
+                connectivity,
+                tiles,
+            )))
         }),
     )?;
 
@@ -126,7 +365,9 @@ fn main() -> Result<()> {
 enum Tool {
     CellPainter(Area),
     // Move
-    // CellSelection
+    CellSelection,
+    // Show what can be seen from a cell
+    Sight,
     // ???
 }
 
@@ -141,33 +382,163 @@ type Color = egui::Color32;
 #[derive(Clone, Copy)]
 struct Pallet {
     room: Color,
+    corridor: Color,
     entrance: Color,
+    stairs: Color,
     nothing: Color,
     grid: Color,
     grid_highlight: Color,
+    selection: Color,
+    fog: Color,
+    wall: Color,
+    door: Color,
+    secret_door: Color,
+    feature: Color,
+    brazier: Color,
 }
 
 impl Default for Pallet {
     fn default() -> Self {
         Self {
             room: Color::LIGHT_GRAY,
+            corridor: Color::from_rgb(190, 170, 130),
             entrance: Color::RED,
+            stairs: Color::from_rgb(70, 130, 180),
             nothing: Color::DARK_GRAY,
             grid: Color::BLACK,
             grid_highlight: Color::YELLOW,
+            selection: Color::from_rgb(0, 160, 255),
+            fog: Color::from_black_alpha(180),
+            wall: Color::BLACK,
+            door: Color::from_rgb(139, 90, 43),
+            secret_door: Color::from_rgb(128, 64, 160),
+            feature: Color::from_rgb(90, 90, 90),
+            brazier: Color::from_rgb(255, 140, 0),
         }
     }
 }
 
+/// Read a share from 0 to 1, such as a chance or coverage
+fn parse_fraction(text: &str) -> std::result::Result<f64, String> {
+    match text.trim().parse::<f64>() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+        _ => Err(format!("{:?} is not a number from 0 to 1", text)),
+    }
+}
+
+/// Read a share that can be more than 1, such as the share of extra loops
+fn parse_share(text: &str) -> std::result::Result<f64, String> {
+    match text.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        _ => Err(format!("{:?} is not a number of 0 or more", text)),
+    }
+}
+
+/// Name of a route for the hallway picker
+fn route_name(route: &RouteMethod) -> &'static str {
+    match route {
+        RouteMethod::HorizontalFirst | RouteMethod::VerticalFirst | RouteMethod::Manhattan => "Manhattan",
+        RouteMethod::Direct => "Direct",
+        RouteMethod::Subway => "Subway",
+        RouteMethod::Pathfind(_) => "Pathfind",
+    }
+}
+
 enum Dialog {
     Save,
     Load,
     Quit,
 }
 
+/// State for the file picker used by the Save and Load dialogs
+struct FileBrowser {
+    // Directory that is currently being browsed
+    directory: PathBuf,
+    // Name of the file within the directory
+    filename: String,
+    // Error from the last save/load attempt
+    error: Option<String>,
+}
+
+impl FileBrowser {
+    /// Full path of the selected file
+    fn path(&self) -> PathBuf {
+        self.directory.join(&self.filename)
+    }
+
+    /// List the sub-directories and map files in the current directory
+    fn entries(&self) -> (Vec<String>, Vec<String>) {
+        let mut dirs = vec![];
+        let mut files = vec![];
+
+        if let Ok(read_dir) = std::fs::read_dir(&self.directory) {
+            for entry in read_dir.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let path = entry.path();
+                if path.is_dir() {
+                    dirs.push(name);
+                } else if path.extension().is_some_and(|ext| ext == FILE_EXTENSION) {
+                    files.push(name);
+                }
+            }
+        }
+
+        dirs.sort();
+        files.sort();
+        (dirs, files)
+    }
+
+    /// Draw the directory listing and filename box
+    fn show(&mut self, ui: &mut egui::Ui) {
+        ui.label(self.directory.display().to_string());
+
+        let (dirs, files) = self.entries();
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                if ui.button("..").clicked() {
+                    if let Some(parent) = self.directory.parent() {
+                        self.directory = parent.to_path_buf();
+                    }
+                }
+                for dir in dirs {
+                    if ui.button(format!("{}/", dir)).clicked() {
+                        self.directory.push(dir);
+                    }
+                }
+                for file in files {
+                    if ui.selectable_label(self.filename == file, &file).clicked() {
+                        self.filename = file;
+                    }
+                }
+            });
+
+        ui.horizontal(|ui| {
+            ui.label("File name:");
+            ui.text_edit_singleline(&mut self.filename);
+        });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(Color::RED, error);
+        }
+    }
+}
+
+impl Default for FileBrowser {
+    fn default() -> Self {
+        Self {
+            directory: std::env::current_dir().unwrap_or_default(),
+            filename: format!("map.{}", FILE_EXTENSION),
+            error: None,
+        }
+    }
+}
+
 struct RpgMapGui {
-    // Map state
-    map: GridMap,
+    // Map state; every level of the dungeon
+    dungeon: Dungeon,
+    // The level that is being shown
+    level: usize,
     // Current Tool selection
     tool: Tool,
     // Mouse state
@@ -176,22 +547,112 @@ struct RpgMapGui {
     colors: Pallet,
     // Open dialog boxes
     dialog: Option<Dialog>,
+    // File picker for saving/loading
+    files: FileBrowser,
+    // Seed used for the next generated map
+    seed: u64,
+    // Whether generated dungeons get pillars
+    pillars: bool,
+    // How generated hallways are routed
+    route: RouteMethod,
+    // Width of generated hallways, in cells
+    corridor_width: usize,
+    // Which rooms generated hallways join
+    connectivity: Connectivity,
+    // Selected rectangle of cells, if any
+    selection: Option<(Point, Point)>,
+    // Cell where the current selection drag started
+    select_anchor: Option<Point>,
+    // Cell that the Sight tool is looking from
+    viewer: Option<Point>,
+    // Tile rules for the WFC generator
+    tiles: TileSet,
+    // Error from the last thing that was generated, shown in the status bar
+    error: Option<String>,
 }
 
 impl RpgMapGui {
-    fn new(map: GridMap) -> Self {
+    fn new(
+        dungeon: Dungeon,
+        seed: u64,
+        pillars: bool,
+        route: RouteMethod,
+        corridor_width: usize,
+        connectivity: Connectivity,
+        tiles: TileSet,
+    ) -> Self {
         let tool = Tool::default();
         let dragging = false;
         let colors = Pallet::default();
 
         Self {
-            map,
+            dungeon,
+            level: 0,
             tool,
             dragging,
             colors,
             dialog: None,
+            files: FileBrowser::default(),
+            seed,
+            pillars,
+            route,
+            corridor_width,
+            connectivity,
+            selection: None,
+            select_anchor: None,
+            viewer: None,
+            tiles,
+            error: None,
         }
     }
+
+    /// The selected rectangle, or the whole level if nothing is selected
+    ///
+    /// The rectangle is cut down to fit the level, and is `None` if the
+    /// level has no cells at all.
+    fn selected_region(&self) -> Option<(Point, Point)> {
+        let (width, height) = self.dungeon[self.level].get_limits();
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        let last = Point::new(width as i64 - 1, height as i64 - 1);
+        let clamp = |point: Point| Point::new(point.x.min(last.x), point.y.min(last.y));
+        Some(match self.selection {
+            Some((min, max)) => (clamp(min), clamp(max)),
+            None => (Point::new(0, 0), last),
+        })
+    }
+
+    /// Show another level, or a level of a newly loaded dungeon
+    ///
+    /// The selection and the Sight tool's viewer were made on the old
+    /// level, so they are dropped.
+    fn show_level(&mut self, level: usize) {
+        self.level = level;
+        self.selection = None;
+        self.select_anchor = None;
+        self.viewer = None;
+    }
+
+    /// Tidy up after the current level has been regenerated
+    ///
+    /// Regenerating a level wipes out its stairs, so it is linked to the
+    /// levels above and below again. Only the top level has an entrance.
+    fn finish_level(&mut self, rng: &mut StdRng) {
+        let entrance = match self.level {
+            0 => self.dungeon[0]
+                .place_entrance_near((0, 0), rng)
+                .map_err(|e| format!("Could not place the entrance: {}", e)),
+            _ => Ok(()),
+        };
+        let link = self
+            .dungeon
+            .relink_level(self.level, rng)
+            .map_err(|e| format!("Could not link the level: {}", e));
+
+        self.error = entrance.and(link).err();
+    }
 }
 
 impl eframe::App for RpgMapGui {
@@ -203,10 +664,12 @@ impl eframe::App for RpgMapGui {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
                     if ui.button("Save").clicked() {
-                        // TODO: SAVE!
+                        self.files.error = None;
+                        self.dialog = Some(Dialog::Save);
                     }
                     if ui.button("Load").clicked() {
-                        // TODO: LOAD
+                        self.files.error = None;
+                        self.dialog = Some(Dialog::Load);
                     }
                     if ui.button("Quit").clicked() {
                         // Show a dialog box for "are you sure?"
@@ -214,20 +677,141 @@ impl eframe::App for RpgMapGui {
                     }
                 });
                 ui.menu_button("Generate", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Seed:");
+                        ui.add(egui::DragValue::new(&mut self.seed));
+                        if ui.button("Random").clicked() {
+                            self.seed = rand::random();
+                        }
+                    });
+                    ui.checkbox(&mut self.pillars, "Pillars");
+                    egui::ComboBox::from_label("Hallways")
+                        .selected_text(route_name(&self.route))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.route, RouteMethod::Manhattan, "Manhattan");
+                            ui.selectable_value(&mut self.route, RouteMethod::Direct, "Direct");
+                            ui.selectable_value(&mut self.route, RouteMethod::Subway, "Subway");
+                            ui.selectable_value(
+                                &mut self.route,
+                                RouteMethod::Pathfind(PathCosts::default()),
+                                "Pathfind",
+                            );
+                        });
+                    ui.horizontal(|ui| {
+                        ui.label("Hallway width:");
+                        ui.add(egui::DragValue::new(&mut self.corridor_width).range(1..=5));
+                    });
+                    let mut join_all = matches!(self.connectivity, Connectivity::SpanningTree(_));
+                    if ui.checkbox(&mut join_all, "Join every room").changed() {
+                        self.connectivity = if join_all {
+                            Connectivity::SpanningTree(0.2)
+                        } else {
+                            Connectivity::Nearby
+                        };
+                    }
+                    if let Connectivity::SpanningTree(loops) = &mut self.connectivity {
+                        ui.add(egui::Slider::new(loops, 0.0..=1.0).text("Loops"));
+                    }
+
                     if ui.button("Dungeon").clicked() {
                         // Generate a dungeon!
-                        self.map.generate_dungeon(10, 5);
-                        self.map
-                            .place_entrance_near((0, 0))
-                            .expect("failed to place entrance");
+                        let mut rng = StdRng::seed_from_u64(self.seed);
+                        self.dungeon[self.level].generate_dungeon(
+                            10,
+                            5,
+                            self.pillars,
+                            self.route,
+                            self.corridor_width,
+                            self.connectivity,
+                            &mut rng,
+                        );
+                        self.finish_level(&mut rng);
                     }
 
                     if ui.button("Cave").clicked() {
                         // Generate a cave!
-                        self.map.generate_cave(4, 50);
-                        self.map
-                            .place_entrance_near((0, 0))
-                            .expect("failed to place entrance");
+                        let mut rng = StdRng::seed_from_u64(self.seed);
+                        self.dungeon[self.level].generate_cave(
+                            4,
+                            50,
+                            self.route,
+                            self.corridor_width,
+                            self.connectivity,
+                            &mut rng,
+                        );
+                        self.finish_level(&mut rng);
+                    }
+
+                    if ui.button("BSP").clicked() {
+                        // Generate non-overlapping rooms and corridors
+                        let mut rng = StdRng::seed_from_u64(self.seed);
+                        self.dungeon[self.level].generate_bsp(6, 15, &mut rng);
+                        self.finish_level(&mut rng);
+                    }
+
+                    if ui.button("Tunnels").clicked() {
+                        // Let random walkers dig out some tunnels
+                        let mut rng = StdRng::seed_from_u64(self.seed);
+                        self.dungeon[self.level].generate_tunnels(1, 0.35, 0.2, 0.02, &mut rng);
+                        self.finish_level(&mut rng);
+                    }
+
+                    if ui.button("Maze").clicked() {
+                        // Generate a labyrinth with a few loops in it
+                        let mut rng = StdRng::seed_from_u64(self.seed);
+                        self.dungeon[self.level].generate_maze(
+                            MazeAlgorithm::RecursiveBacktracker,
+                            0.2,
+                            MazeStyle::Cells,
+                            &mut rng,
+                        );
+                        self.finish_level(&mut rng);
+                    }
+
+                    ui.separator();
+                    let region = self.selected_region();
+                    if ui
+                        .add_enabled(region.is_some(), egui::Button::new("WFC"))
+                        .on_hover_text(
+                            "Fill the selection from the tile rules; cells that aren't empty are kept if the rules have a tile for them",
+                        )
+                        .clicked()
+                    {
+                        if let Some((min, max)) = region {
+                            let mut rng = StdRng::seed_from_u64(self.seed);
+                            let map = &mut self.dungeon[self.level];
+                            // Cells that no tile could have made would always be a
+                            // contradiction, so they are filled over rather than kept
+                            let tiles = &self.tiles;
+                            let pinned: Vec<Point> = (min.x..=max.x)
+                                .flat_map(|x| (min.y..=max.y).map(move |y| Point::new(x, y)))
+                                .filter(|point| {
+                                    let area = map[*point].area();
+                                    area != &Area::Nothing && tiles.has_area(area)
+                                })
+                                .collect();
+                            self.error = map
+                                .fill_wfc(tiles, min, max, &pinned, &mut rng)
+                                .err()
+                                .map(|e| format!("Could not fill the selection: {}", e));
+                        }
+                    }
+                });
+                ui.menu_button("Level", |ui| {
+                    if ui.button("Add level").clicked() {
+                        let (width, height) = self.dungeon[self.level].get_limits();
+                        self.dungeon
+                            .add_level(GridMap::new(width, height))
+                            .expect("new level is the wrong size");
+                        self.show_level(self.dungeon.num_levels() - 1);
+                    }
+                    if ui.button("Link to level below").clicked() {
+                        let mut rng = StdRng::seed_from_u64(self.seed);
+                        self.error = self
+                            .dungeon
+                            .link_levels(self.level, &mut rng)
+                            .err()
+                            .map(|e| format!("Could not link levels: {}", e));
                     }
                 });
             });
@@ -246,8 +830,43 @@ impl eframe::App for RpgMapGui {
                 });
             }
             Some(Dialog::Save) => {
+                let _modal = egui::Modal::new("Save Map".into()).show(ctx, |ui| {
+                    ui.heading("Save map");
+                    self.files.show(ui);
+                    ui.horizontal(|ui| {
+                        if ui.button("Save").clicked() {
+                            match self.dungeon.save(self.files.path()) {
+                                Ok(_) => self.dialog = None,
+                                Err(e) => self.files.error = Some(e.to_string()),
+                            }
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.dialog = None;
+                        }
+                    });
+                });
             }
             Some(Dialog::Load) => {
+                let _modal = egui::Modal::new("Load Map".into()).show(ctx, |ui| {
+                    ui.heading("Load map");
+                    self.files.show(ui);
+                    ui.horizontal(|ui| {
+                        if ui.button("Load").clicked() {
+                            match Dungeon::load(self.files.path()) {
+                                Ok(dungeon) if dungeon.num_levels() > 0 => {
+                                    self.dungeon = dungeon;
+                                    self.show_level(0);
+                                    self.dialog = None;
+                                }
+                                Ok(_) => self.files.error = Some("file has no levels".to_string()),
+                                Err(e) => self.files.error = Some(e.to_string()),
+                            }
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.dialog = None;
+                        }
+                    });
+                });
             }
             _ => {}
         }
@@ -260,6 +879,10 @@ impl eframe::App for RpgMapGui {
                 self.tool = Tool::CellPainter(Area::Room);
             }
 
+            if ui.button("Corridor").clicked() {
+                self.tool = Tool::CellPainter(Area::Corridor);
+            }
+
             if ui.button("Nothing").clicked() {
                 // Set the tool type to nothing
                 self.tool = Tool::CellPainter(Area::Nothing);
@@ -269,13 +892,59 @@ impl eframe::App for RpgMapGui {
                 // Set the tool type to Entrance
                 self.tool = Tool::CellPainter(Area::Entrance);
             }
+
+            if ui.button("Stairs Up").clicked() {
+                self.tool = Tool::CellPainter(Area::StairsUp);
+            }
+
+            if ui.button("Stairs Down").clicked() {
+                self.tool = Tool::CellPainter(Area::StairsDown);
+            }
+
+            ui.separator();
+            if ui.button("Select").clicked() {
+                self.tool = Tool::CellSelection;
+            }
+            if ui.button("Sight").clicked() {
+                self.tool = Tool::Sight;
+            }
+            if ui
+                .add_enabled(self.selection.is_some(), egui::Button::new("Clear selection"))
+                .clicked()
+            {
+                if let Some((min, max)) = self.selected_region() {
+                    for x in min.x..=max.x {
+                        for y in min.y..=max.y {
+                            *self.dungeon[self.level].get_cell_mut((x, y)) = Cell::new();
+                        }
+                    }
+                }
+            }
+            if ui
+                .add_enabled(self.selection.is_some(), egui::Button::new("Select none"))
+                .clicked()
+            {
+                self.selection = None;
+            }
+
+            ui.separator();
+            ui.label(format!("Level {} of {}", self.level + 1, self.dungeon.num_levels()));
+            ui.horizontal(|ui| {
+                if ui.add_enabled(self.level > 0, egui::Button::new("Up")).clicked() {
+                    self.show_level(self.level - 1);
+                }
+                let has_below = self.level + 1 < self.dungeon.num_levels();
+                if ui.add_enabled(has_below, egui::Button::new("Down")).clicked() {
+                    self.show_level(self.level + 1);
+                }
+            });
         });
 
 
         egui::CentralPanel::default().show(ctx, |ui| {
             let cell_size = 10.0;
 
-            let (num_x, num_y) = self.map.get_limits();
+            let (num_x, num_y) = self.dungeon[self.level].get_limits();
 
             egui::ScrollArea::both().show(ui, |ui| {
                 let scroll_offset = ui.cursor().left_top();
@@ -287,9 +956,18 @@ impl eframe::App for RpgMapGui {
                     }
                     if input.pointer.primary_released() {
                         self.dragging = false;
+                        self.select_anchor = None;
                     }
                 });
 
+                // Cells that can be seen by the Sight tool's viewer
+                let seen = match (&self.tool, self.viewer) {
+                    (Tool::Sight, Some(viewer)) => {
+                        Some(self.dungeon[self.level].field_of_view(viewer, None))
+                    }
+                    _ => None,
+                };
+
                 for x in 0..num_x {
                     for y in 0..num_y {
                         let cell_x = scroll_offset.x + x as f32 * cell_size;
@@ -306,13 +984,18 @@ impl eframe::App for RpgMapGui {
                         );
 
                         // TODO: Refactor this into an into() call.
-                        let color = match self.map.get_cell_ref(point).area() {
+                        let color = match self.dungeon[self.level].get_cell_ref(point).area() {
                             Area::Room => self.colors.room,
+                            Area::Corridor => self.colors.corridor,
                             Area::Entrance => self.colors.entrance,
+                            Area::StairsUp | Area::StairsDown => self.colors.stairs,
                             Area::Nothing => self.colors.nothing,
                         };
 
                         ui.painter().rect_filled(cell.rect, 0.0, color);
+                        if seen.as_ref().is_some_and(|seen| !seen.contains(&point)) {
+                            ui.painter().rect_filled(cell.rect, 0.0, self.colors.fog);
+                        }
 
                         // Test if we're hovering over the cell
                         if cell
@@ -331,25 +1014,105 @@ impl eframe::App for RpgMapGui {
                             );
                         }
 
-                        // If the mouse main button is down then we may need to
-                        // set a cell.
-                        if self.dragging
+                        // If the mouse main button is down or the cell was
+                        // clicked then we may need to set a cell.
+                        let painting = self.dragging
                             && cell
                                 .rect
-                                .contains(ctx.pointer_hover_pos().unwrap_or_default())
-                        {
-                            let Tool::CellPainter(ref area) = &self.tool;
-                            self.map.get_cell_mut(point).set_area(area.to_owned());
-                        } else if ui
-                            .interact(cell.rect, egui::Id::new(point), egui::Sense::click())
-                            .clicked()
+                                .contains(ctx.pointer_hover_pos().unwrap_or_default());
+                        if painting
+                            || ui
+                                .interact(cell.rect, egui::Id::new(point), egui::Sense::click())
+                                .clicked()
                         {
-                            let Tool::CellPainter(ref area) = &self.tool;
-                            self.map.get_cell_mut(point).set_area(area.to_owned());
+                            match &self.tool {
+                                Tool::CellPainter(area) => {
+                                    self.dungeon[self.level]
+                                        .get_cell_mut(point)
+                                        .set_area(area.to_owned());
+                                }
+                                Tool::CellSelection => {
+                                    let anchor = *self.select_anchor.get_or_insert(point);
+                                    self.selection = Some((
+                                        Point::new(anchor.x.min(point.x), anchor.y.min(point.y)),
+                                        Point::new(anchor.x.max(point.x), anchor.y.max(point.y)),
+                                    ));
+                                }
+                                Tool::Sight => {
+                                    self.viewer = Some(point);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // Walls are drawn after all of the cells so that they aren't
+                // covered up by their neighbours.
+                for x in 0..num_x {
+                    for y in 0..num_y {
+                        let cell = self.dungeon[self.level].get_cell_ref((x as i64, y as i64));
+                        let corner = egui::pos2(
+                            scroll_offset.x + x as f32 * cell_size,
+                            scroll_offset.y + y as f32 * cell_size,
+                        );
+                        let edges = [
+                            (cell.vert_wall(), corner + egui::vec2(0.0, cell_size)),
+                            (cell.horiz_wall(), corner + egui::vec2(cell_size, 0.0)),
+                        ];
+                        for (wall, end) in edges {
+                            let color = match wall {
+                                Wall::Nothing => continue,
+                                Wall::Wall => self.colors.wall,
+                                Wall::Door => self.colors.door,
+                                Wall::SecretDoor => self.colors.secret_door,
+                            };
+                            ui.painter()
+                                .line_segment([corner, end], egui::Stroke::new(3.0, color));
+                        }
+
+                        let radius = cell_size / 4.0;
+                        match cell.point() {
+                            CornerPoint::Nothing => {}
+                            CornerPoint::Pillar => {
+                                ui.painter().rect_filled(
+                                    egui::Rect::from_center_size(corner, egui::vec2(radius, radius) * 2.0),
+                                    0.0,
+                                    self.colors.feature,
+                                );
+                            }
+                            CornerPoint::Statue => {
+                                // A diamond, like the renderer draws
+                                let points = [
+                                    corner + egui::vec2(0.0, -radius),
+                                    corner + egui::vec2(radius, 0.0),
+                                    corner + egui::vec2(0.0, radius),
+                                    corner + egui::vec2(-radius, 0.0),
+                                ];
+                                ui.painter().add(egui::Shape::convex_polygon(
+                                    points.to_vec(),
+                                    self.colors.feature,
+                                    egui::Stroke::NONE,
+                                ));
+                            }
+                            CornerPoint::Column => {
+                                ui.painter().circle_filled(corner, radius, self.colors.feature);
+                            }
+                            CornerPoint::Brazier => {
+                                ui.painter().circle_filled(corner, radius, self.colors.brazier);
+                            }
                         }
                     }
                 }
 
+                if let Some((min, max)) = self.selection {
+                    let rect = egui::Rect::from_min_max(
+                        scroll_offset + egui::vec2(min.x as f32, min.y as f32) * cell_size,
+                        scroll_offset + egui::vec2(max.x as f32 + 1.0, max.y as f32 + 1.0) * cell_size,
+                    );
+                    ui.painter()
+                        .rect_stroke(rect, 0.0, egui::Stroke::new(2.0, self.colors.selection));
+                }
+
                 if let Some((x, y)) = cursor_pos {
                     let cell_x = scroll_offset.x + x as f32 * cell_size;
                     let cell_y = scroll_offset.y + y as f32 * cell_size;
@@ -372,11 +1135,15 @@ impl eframe::App for RpgMapGui {
 
         egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                ui.label(format!("Seed: {}", self.seed));
                 if let Some((x, y)) = cursor_pos {
                     ui.label(format!("Cell: ({}, {})", x, y));
                 } else {
                     ui.label("Cell: (N/A)");
                 }
+                if let Some(error) = &self.error {
+                    ui.colored_label(Color::RED, error);
+                }
             });
         });
     }
@@ -1,11 +1,16 @@
 //! Program for making simple RPG maps. This is the Rust language implementation.
-use clap::{command, value_parser, Arg};
+use clap::{command, error::ErrorKind, value_parser, Arg, ArgAction};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 use rpgtools::error::Result;
-use rpgtools::map::{gridmap::Point, GridMap, Renderer};
+use rpgtools::map::{
+    gridmap::Point, Connectivity, Dungeon, Glyphs, MazeAlgorithm, MazeStyle, Paper, PathCosts, PrintLayout, Renderer, RouteMethod,
+    Label, RoomOrder, Theme, TileSet, View,
+};
 
 fn main() -> Result<()> {
-    let cli = command!()
+    let mut command = command!()
         .author("Aaron Seilis <aaron.seilis@seilis.ca>")
         .about("A simple map generator for role playing games")
         .arg(
@@ -31,7 +36,7 @@ fn main() -> Result<()> {
                 .short('s')
                 .long("style")
                 .default_value("halls")
-                .value_parser(["halls", "cave"])
+                .value_parser(["halls", "cave", "bsp", "tunnels", "maze", "wfc"])
                 .help("The style of map to generate"),
         )
         .arg(
@@ -49,7 +54,85 @@ fn main() -> Result<()> {
                 .long("output")
                 .default_value("rpgmap.png")
                 .value_name("NAME")
-                .help("The name of the output file"),
+                .help("The name of the output file; a name ending in .svg writes a vector image, .pdf a printable one and .txt a text picture"),
+        )
+        .arg(
+            Arg::new("theme")
+                .long("theme")
+                .value_name("DIR")
+                .help("A theme directory (or theme.txt manifest) with the sprites and colours to draw with"),
+        )
+        .arg(
+            Arg::new("glyphs")
+                .long("glyphs")
+                .default_value("ascii")
+                .value_parser(["ascii", "unicode"])
+                .help("The characters to draw with (txt only)"),
+        )
+        .arg(
+            Arg::new("view")
+                .long("view")
+                .default_value("gm")
+                .value_parser(["gm", "player"])
+                .help("Who the map is for; the player view leaves out secret doors and other secrets"),
+        )
+        .arg(
+            Arg::new("room_numbers")
+                .long("room-numbers")
+                .value_name("ORDER")
+                .value_parser(["distance", "reading"])
+                .help("Number the rooms, nearest to the entrance first or row by row (gm view only, not in txt)"),
+        )
+        .arg(
+            Arg::new("label")
+                .long("label")
+                .value_name("X,Y,TEXT")
+                .action(ArgAction::Append)
+                .value_parser(parse_label)
+                .help("Write TEXT over the cell at X,Y on every level (not in txt); may be given more than once"),
+        )
+        .arg(
+            Arg::new("note")
+                .long("note")
+                .value_name("X,Y,TEXT")
+                .action(ArgAction::Append)
+                .value_parser(parse_label)
+                .help("Like --label, but only in the gm view"),
+        )
+        .arg(
+            Arg::new("flat")
+                .long("flat")
+                .action(ArgAction::SetTrue)
+                .help("Draw plain rock, without wall faces along the floor or hatching"),
+        )
+        .arg(
+            Arg::new("print_scale")
+                .long("print-scale")
+                .default_value("1in")
+                .value_name("LENGTH")
+                .value_parser(parse_length)
+                .help("The printed size of each square, such as 1in, 25mm or 2.5cm (pdf only)"),
+        )
+        .arg(
+            Arg::new("paper")
+                .long("paper")
+                .default_value("a4")
+                .value_parser(["a4", "letter"])
+                .help("The paper size to print on (pdf only)"),
+        )
+        .arg(
+            Arg::new("overlap")
+                .long("overlap")
+                .default_value("10mm")
+                .value_name("LENGTH")
+                .value_parser(parse_length)
+                .help("How much of the map is printed on both of two neighbouring pages (pdf only)"),
+        )
+        .arg(
+            Arg::new("cover")
+                .long("cover")
+                .action(ArgAction::SetTrue)
+                .help("Start with a page showing the whole map and where each page goes (pdf only)"),
         )
         .arg(
             Arg::new("num_rooms")
@@ -67,7 +150,133 @@ fn main() -> Result<()> {
                 .value_parser(value_parser!(u64).range(1..))
                 .help("The size of generated rooms"),
         )
-        .get_matches();
+        .arg(
+            Arg::new("route")
+                .long("route")
+                .default_value("manhattan")
+                .value_parser(["manhattan", "direct", "subway", "pathfind"])
+                .help("How hallways are routed between rooms; pathfind goes around rooms (halls and cave only)"),
+        )
+        .arg(
+            Arg::new("corridor_width")
+                .long("corridor-width")
+                .default_value("1")
+                .value_name("INT")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("The width of hallways in cells; doors are only placed across 1 wide hallways (halls and cave only)"),
+        )
+        .arg(
+            Arg::new("connectivity")
+                .long("connectivity")
+                .default_value("nearby")
+                .value_parser(["nearby", "tree"])
+                .help("Which rooms are joined; tree always joins every room (halls and cave only)"),
+        )
+        .arg(
+            Arg::new("loops")
+                .long("loops")
+                .default_value("0.2")
+                .value_name("SHARE")
+                .value_parser(parse_share)
+                .help("Extra hallways that make loops, as a share of the ones needed to join every room (tree only)"),
+        )
+        .arg(
+            Arg::new("min_leaf")
+                .long("min-leaf")
+                .default_value("6")
+                .value_name("INT")
+                .value_parser(value_parser!(u64).range(4..))
+                .help("The smallest area that a room is placed in (bsp only)"),
+        )
+        .arg(
+            Arg::new("max_leaf")
+                .long("max-leaf")
+                .default_value("15")
+                .value_name("INT")
+                .value_parser(value_parser!(u64).range(4..))
+                .help("The largest area that a room is placed in (bsp only)"),
+        )
+        .arg(
+            Arg::new("walkers")
+                .long("walkers")
+                .default_value("1")
+                .value_name("INT")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("The number of walkers that start digging (tunnels only)"),
+        )
+        .arg(
+            Arg::new("coverage")
+                .long("coverage")
+                .default_value("0.35")
+                .value_name("FRACTION")
+                .value_parser(parse_fraction)
+                .help("The fraction of the map to dig out, from 0 to 1 (tunnels only)"),
+        )
+        .arg(
+            Arg::new("turn_chance")
+                .long("turn-chance")
+                .default_value("0.2")
+                .value_name("FRACTION")
+                .value_parser(parse_fraction)
+                .help("The chance that a walker turns on each step (tunnels only)"),
+        )
+        .arg(
+            Arg::new("spawn_chance")
+                .long("spawn-chance")
+                .default_value("0.02")
+                .value_name("FRACTION")
+                .value_parser(parse_fraction)
+                .help("The chance that a walker spawns another walker on each step (tunnels only)"),
+        )
+        .arg(
+            Arg::new("maze_algorithm")
+                .long("maze-algorithm")
+                .default_value("backtracker")
+                .value_parser(["backtracker", "prim", "kruskal", "eller"])
+                .help("The algorithm used to carve the maze (maze only)"),
+        )
+        .arg(
+            Arg::new("braid")
+                .long("braid")
+                .default_value("0")
+                .value_name("FRACTION")
+                .value_parser(parse_fraction)
+                .help("The share of dead ends to remove, from 0 to 1 (maze only)"),
+        )
+        .arg(
+            Arg::new("maze_walls")
+                .long("maze-walls")
+                .action(ArgAction::SetTrue)
+                .help("Draw the maze with walls between cells instead of corridors (maze only)"),
+        )
+        .arg(
+            Arg::new("rules")
+                .long("rules")
+                .value_name("FILE")
+                .help("The tile rule file to fill the map from; defaults to winding corridors (wfc only)"),
+        )
+        .arg(
+            Arg::new("levels")
+                .long("levels")
+                .default_value("1")
+                .value_name("INT")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("The number of levels, linked by stairs; each level is written to its own file"),
+        )
+        .arg(
+            Arg::new("pillars")
+                .long("pillars")
+                .action(ArgAction::SetTrue)
+                .help("Line large rooms with rows of pillars (halls only)"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("INT")
+                .value_parser(value_parser!(u64))
+                .help("The seed for the random generator; the same seed and options make the same map"),
+        );
+    let cli = command.get_matches_mut();
 
     // Unpack our arguments
     let style: String = cli
@@ -87,35 +296,295 @@ fn main() -> Result<()> {
         .get_one::<String>("output")
         .expect("failed to get filename; this is a bug")
         .to_string();
+    let theme = match cli.get_one::<String>("theme") {
+        Some(path) => Theme::load(path)?,
+        None => Theme::default(),
+    };
+    let autotile = !cli.get_flag("flat");
+    let glyphs = match cli
+        .get_one::<String>("glyphs")
+        .expect("failed to get glyphs; this is a bug")
+        .as_str()
+    {
+        "ascii" => Glyphs::ascii(),
+        "unicode" => Glyphs::unicode(),
+        _ => unreachable!(),
+    };
+    let room_numbers = cli
+        .get_one::<String>("room_numbers")
+        .map(|order| match order.as_str() {
+            "distance" => RoomOrder::Distance,
+            "reading" => RoomOrder::Reading,
+            _ => unreachable!(),
+        });
+    let mut labels: Vec<Label> = cli
+        .get_many::<Label>("label")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
+    labels.extend(cli.get_many::<Label>("note").into_iter().flatten().map(|label| Label {
+        gm_only: true,
+        ..label.clone()
+    }));
+    let view = match cli
+        .get_one::<String>("view")
+        .expect("failed to get view; this is a bug")
+        .as_str()
+    {
+        "gm" => View::Gm,
+        "player" => View::Player,
+        _ => unreachable!(),
+    };
+    let layout = PrintLayout {
+        paper: match cli
+            .get_one::<String>("paper")
+            .expect("failed to get paper; this is a bug")
+            .as_str()
+        {
+            "a4" => Paper::A4,
+            "letter" => Paper::Letter,
+            _ => unreachable!(),
+        },
+        square: *cli
+            .get_one::<f64>("print_scale")
+            .expect("failed to get print_scale; this is a bug"),
+        overlap: *cli
+            .get_one::<f64>("overlap")
+            .expect("failed to get overlap; this is a bug"),
+        cover: cli.get_flag("cover"),
+        ..Default::default()
+    };
     let num_rooms: usize = *cli
         .get_one::<u64>("num_rooms")
         .expect("failed to get num_rooms; this is a bug") as usize;
+    let route = match cli
+        .get_one::<String>("route")
+        .expect("failed to get route; this is a bug")
+        .as_str()
+    {
+        "manhattan" => RouteMethod::Manhattan,
+        "direct" => RouteMethod::Direct,
+        "subway" => RouteMethod::Subway,
+        "pathfind" => RouteMethod::Pathfind(PathCosts::default()),
+        _ => unreachable!(),
+    };
+    let corridor_width: usize = *cli
+        .get_one::<u64>("corridor_width")
+        .expect("failed to get corridor_width; this is a bug") as usize;
+    let loops: f64 = *cli
+        .get_one::<f64>("loops")
+        .expect("failed to get loops; this is a bug");
+    let connectivity = match cli
+        .get_one::<String>("connectivity")
+        .expect("failed to get connectivity; this is a bug")
+        .as_str()
+    {
+        "nearby" => Connectivity::Nearby,
+        "tree" => Connectivity::SpanningTree(loops),
+        _ => unreachable!(),
+    };
+    let min_leaf: usize = *cli
+        .get_one::<u64>("min_leaf")
+        .expect("failed to get min_leaf; this is a bug") as usize;
+    let max_leaf: usize = *cli
+        .get_one::<u64>("max_leaf")
+        .expect("failed to get max_leaf; this is a bug") as usize;
+    if min_leaf > max_leaf {
+        command
+            .error(ErrorKind::ArgumentConflict, "--min-leaf can't be larger than --max-leaf")
+            .exit();
+    }
+    let walkers: usize = *cli
+        .get_one::<u64>("walkers")
+        .expect("failed to get walkers; this is a bug") as usize;
+    let coverage: f64 = *cli
+        .get_one::<f64>("coverage")
+        .expect("failed to get coverage; this is a bug");
+    let turn_chance: f64 = *cli
+        .get_one::<f64>("turn_chance")
+        .expect("failed to get turn_chance; this is a bug");
+    let spawn_chance: f64 = *cli
+        .get_one::<f64>("spawn_chance")
+        .expect("failed to get spawn_chance; this is a bug");
+    let maze_algorithm = match cli
+        .get_one::<String>("maze_algorithm")
+        .expect("failed to get maze_algorithm; this is a bug")
+        .as_str()
+    {
+        "backtracker" => MazeAlgorithm::RecursiveBacktracker,
+        "prim" => MazeAlgorithm::Prim,
+        "kruskal" => MazeAlgorithm::Kruskal,
+        "eller" => MazeAlgorithm::Eller,
+        _ => unreachable!(),
+    };
+    let braid: f64 = *cli
+        .get_one::<f64>("braid")
+        .expect("failed to get braid; this is a bug");
+    let maze_style = if cli.get_flag("maze_walls") {
+        MazeStyle::Walls
+    } else {
+        MazeStyle::Cells
+    };
+    let tiles = match cli.get_one::<String>("rules") {
+        Some(path) => TileSet::load(path)?,
+        None => TileSet::corridors(),
+    };
+    let levels: usize = *cli
+        .get_one::<u64>("levels")
+        .expect("failed to get levels; this is a bug") as usize;
+    let pillars = cli.get_flag("pillars");
+    let seed: u64 = cli
+        .get_one::<u64>("seed")
+        .copied()
+        .unwrap_or_else(rand::random);
 
-    // Initialize our map
-    let mut map = GridMap::new(width, height);
+    let mut rng = StdRng::seed_from_u64(seed);
 
-    // Build map based on map type
-    match style.as_str() {
-        "halls" => {
-            map.generate_dungeon(num_rooms, 5);
-            let point: Point = (width / 2, height / 2).try_into().unwrap();
-            map.place_entrance_near(point)?;
+    // Build each level based on map type
+    let mut failure = None;
+    let dungeon = Dungeon::generate(width, height, levels, &mut rng, |map, rng| {
+        match style.as_str() {
+            "halls" => map.generate_dungeon(num_rooms, 5, pillars, route, corridor_width, connectivity, rng),
+            "cave" => map.generate_cave(4, 50, route, corridor_width, connectivity, rng),
+            "bsp" => map.generate_bsp(min_leaf, max_leaf, rng),
+            "tunnels" => map.generate_tunnels(walkers, coverage, turn_chance, spawn_chance, rng),
+            "maze" => map.generate_maze(maze_algorithm, braid, maze_style, rng),
+            "wfc" => {
+                if let Err(e) = map.generate_wfc(&tiles, rng) {
+                    failure.get_or_insert(e);
+                }
+            }
+            _ => unreachable!(),
         }
-        "cave" => {
-            map.generate_cave(4, 50);
-            let point: Point = (width / 2, height / 2).try_into().unwrap();
-            map.place_entrance_near(point)?;
+
+        // Double check that the spanning tree really did join everything up
+        if matches!(style.as_str(), "halls" | "cave") && connectivity != Connectivity::Nearby {
+            if let Err(e) = map.check_connected() {
+                failure.get_or_insert(e);
+            }
         }
-        _ => unreachable!(),
+    });
+    if let Some(e) = failure {
+        return Err(e);
     }
+    let mut dungeon = dungeon?;
+
+    // The entrance is on the top level
+    let point: Point = (width / 2, height / 2).try_into().unwrap();
+    dungeon[0].place_entrance_near(point, &mut rng)?;
 
-    let renderer = Renderer::new(&map, scale);
-    let result = renderer.draw_to_file(&filename);
+    for (level, map) in dungeon.iter_levels().enumerate() {
+        let filename = if levels > 1 {
+            level_filename(&filename, level)
+        } else {
+            filename.clone()
+        };
 
-    match result {
-        Ok(_) => println!("Map generated: {}", filename),
-        Err(e) => println!("Error: {}", e),
+        let extension = filename.to_lowercase();
+        let mut map_labels = labels.clone();
+        if let Some(order) = room_numbers {
+            map_labels.extend(map.number_rooms(order));
+        }
+        let renderer = |scale| {
+            Renderer::new(map, scale)
+                .with_seed(seed)
+                .with_theme(theme.clone())
+                .with_autotile(autotile)
+                .with_view(view)
+                .with_labels(map_labels.clone())
+        };
+        let result = if extension.ends_with(".svg") {
+            renderer(scale).draw_to_svg(&filename)
+        } else if extension.ends_with(".txt") {
+            // Text has no room for labels, so only the view applies
+            std::fs::write(&filename, map.to_text(&glyphs.clone().for_view(view)))
+        } else if extension.ends_with(".pdf") {
+            // Print at no less than 150 dots per inch
+            let scale = scale.max((layout.square / 25.4 * 150.0).ceil() as usize);
+            let (width, height) = map.get_limits();
+            PrintLayout::check_size(width, height, scale)
+                .and_then(|_| renderer(scale).draw_to_pdf(&filename, &layout))
+        } else {
+            renderer(scale).draw_to_file(&filename)
+        };
+
+        match result {
+            Ok(_) => println!("Map generated: {} (seed {})", filename, seed),
+            Err(e) => println!("Error: {}", e),
+        }
     }
 
     Ok(())
 }
+
+/// Read a share from 0 to 1, such as a chance or coverage
+fn parse_fraction(text: &str) -> std::result::Result<f64, String> {
+    match text.trim().parse::<f64>() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+        _ => Err(format!("{:?} is not a number from 0 to 1", text)),
+    }
+}
+
+/// Read a share that can be more than 1, such as the share of extra loops
+fn parse_share(text: &str) -> std::result::Result<f64, String> {
+    match text.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        _ => Err(format!("{:?} is not a number of 0 or more", text)),
+    }
+}
+
+/// Read a length such as "1in", "25mm" or "2.5cm" as millimetres
+fn parse_length(text: &str) -> std::result::Result<f64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("{:?} is not a length", text))?;
+    let millimetres = match unit {
+        "in" => number * 25.4,
+        "mm" | "" => number,
+        "cm" => number * 10.0,
+        _ => return Err(format!("unknown unit {:?}; use in, mm or cm", unit)),
+    };
+    if millimetres.is_finite() && millimetres >= 0.0 {
+        Ok(millimetres)
+    } else {
+        Err(format!("{:?} is not a length", text))
+    }
+}
+
+/// Read a label given as "X,Y,TEXT"; the text may have commas of its own
+fn parse_label(text: &str) -> std::result::Result<Label, String> {
+    let mut parts = text.splitn(3, ',');
+    let mut coordinate = || {
+        parts
+            .next()
+            .and_then(|part| part.trim().parse::<i64>().ok())
+            .ok_or_else(|| format!("{:?} is not of the form X,Y,TEXT", text))
+    };
+    let (x, y) = (coordinate()?, coordinate()?);
+    let words = parts
+        .next()
+        .ok_or_else(|| format!("{:?} is not of the form X,Y,TEXT", text))?;
+    Ok(Label::new(words, (x, y)))
+}
+
+/// Make the file name for one level of a dungeon by adding the level number
+/// before the extension, so "map.png" becomes "map-1.png" for the top level.
+fn level_filename(filename: &str, level: usize) -> String {
+    let path = std::path::Path::new(filename);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, level + 1, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, level + 1),
+    };
+    path.with_file_name(name).to_string_lossy().to_string()
+}
@@ -13,6 +13,30 @@ pub enum RpgError {
     #[error("unexpectedly empty: {0}")]
     Empty(String),
 
+    #[error("i/o error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("could not parse file at line {0}: {1}")]
+    Parse(usize, String),
+
+    #[error("unsupported map file version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("level {0} is not the same size as the rest of the dungeon")]
+    LevelMismatch(usize),
+
+    #[error("the tile rules can not be satisfied: {0}")]
+    Contradiction(String),
+
+    #[error("a tile set can have at most {0} tiles")]
+    TooManyTiles(usize),
+
+    #[error("the map is split into {0} areas that can not reach each other")]
+    Disconnected(usize),
+
+    #[error("could not use sprite: {0}")]
+    Sprite(String),
+
     #[error("error from eframe {0:?}")]
     Eframe(#[from] eframe::Error),
 }
@@ -1,8 +1,22 @@
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Area {
     Nothing,
     Entrance,
     Room,
-    //    Stairs,
+    /// A hallway between rooms
+    Corridor,
+    /// Stairs leading up to the level above
+    StairsUp,
+    /// Stairs leading down to the level below
+    StairsDown,
     //    Tested,
-}
\ No newline at end of file
+}
+
+impl Area {
+    /// Whether this area marks a special spot, such as the entrance or stairs, that generators
+    /// must not paint over.
+    pub fn is_landmark(&self) -> bool {
+        matches!(self, Area::Entrance | Area::StairsUp | Area::StairsDown)
+    }
+}
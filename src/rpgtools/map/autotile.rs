@@ -0,0 +1,225 @@
+//! Autotiling: the wall faces, corners and rock hatching of a cell, worked out from its neighbours
+//!
+//! Each cell gets an 8-bit mask of which of its neighbours are open (anything but
+//! `Area::Nothing`). The mask is reduced to the usual 47 "blob" tiles by ignoring a diagonal
+//! unless both of the sides next to it are open too, since a diagonal on its own doesn't change
+//! the shape of a cell's walls. Shapes are given in cell units with (0, 0) at the north-west
+//! corner of the cell, so that the raster and vector renderers draw exactly the same thing.
+use super::Area;
+use super::GridMap;
+
+/// Neighbour bits, clockwise from north
+pub(super) const NORTH: u8 = 1;
+pub(super) const NORTH_EAST: u8 = 2;
+pub(super) const EAST: u8 = 4;
+pub(super) const SOUTH_EAST: u8 = 8;
+pub(super) const SOUTH: u8 = 16;
+pub(super) const SOUTH_WEST: u8 = 32;
+pub(super) const WEST: u8 = 64;
+pub(super) const NORTH_WEST: u8 = 128;
+
+/// Offsets to the neighbours, in bit order
+const NEIGHBOURS: [(i64, i64); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+/// A rectangle as (x0, y0, x1, y1)
+pub(super) type Rect = [f64; 4];
+
+/// A line from (x0, y0) to (x1, y1)
+pub(super) type Line = [f64; 4];
+
+/// Whether a cell is open floor. Anything outside of the map is rock.
+fn is_open(map: &GridMap, x: i64, y: i64) -> bool {
+    let (xmax, ymax) = map.get_limits();
+    if x < 0 || y < 0 || x >= xmax as i64 || y >= ymax as i64 {
+        return false;
+    }
+    map.get_cell_ref((x as u32, y as u32)).area != Area::Nothing
+}
+
+/// Which of a cell's eight neighbours are open, one bit each
+pub(super) fn neighbour_mask(map: &GridMap, x: u32, y: u32) -> u8 {
+    NEIGHBOURS
+        .iter()
+        .enumerate()
+        .filter(|(_, (dx, dy))| is_open(map, x as i64 + dx, y as i64 + dy))
+        .fold(0, |mask, (bit, _)| mask | 1 << bit)
+}
+
+/// Reduce a neighbour mask to its blob tile by dropping diagonals that aren't next to two open
+/// sides
+pub(super) fn blob(mask: u8) -> u8 {
+    let mut blob = mask & (NORTH | EAST | SOUTH | WEST);
+    for (corner, side_a, side_b) in [
+        (NORTH_EAST, NORTH, EAST),
+        (SOUTH_EAST, SOUTH, EAST),
+        (SOUTH_WEST, SOUTH, WEST),
+        (NORTH_WEST, NORTH, WEST),
+    ] {
+        if mask & corner != 0 && mask & side_a != 0 && mask & side_b != 0 {
+            blob |= corner;
+        }
+    }
+    blob
+}
+
+/// The wall faces of an open cell with the given blob tile, as rectangles `thickness` wide
+/// centred on the cell's edges
+///
+/// There is a face on every side with rock beyond it. Where two faces meet at an inside corner
+/// they are run on to cover the corner, and where the rock only touches the cell at a corner (an
+/// outside corner) a square is put there to join up the faces of the cells on either side.
+pub(super) fn wall_faces(blob: u8, thickness: f64) -> Vec<Rect> {
+    let half = thickness / 2.0;
+    let rock = |side: u8| blob & side == 0;
+    let mut faces = Vec::new();
+
+    // Each side as its bit, its position across, whether it runs north-south and the sides at
+    // its two ends
+    for (side, across, vertical, start, end) in [
+        (NORTH, 0.0, false, WEST, EAST),
+        (SOUTH, 1.0, false, WEST, EAST),
+        (WEST, 0.0, true, NORTH, SOUTH),
+        (EAST, 1.0, true, NORTH, SOUTH),
+    ] {
+        if !rock(side) {
+            continue;
+        }
+        let from = if rock(start) { -half } else { 0.0 };
+        let to = if rock(end) { 1.0 + half } else { 1.0 };
+        faces.push(if vertical {
+            [across - half, from, across + half, to]
+        } else {
+            [from, across - half, to, across + half]
+        });
+    }
+
+    for (corner, side_a, side_b, (x, y)) in [
+        (NORTH_EAST, NORTH, EAST, (1.0, 0.0)),
+        (SOUTH_EAST, SOUTH, EAST, (1.0, 1.0)),
+        (SOUTH_WEST, SOUTH, WEST, (0.0, 1.0)),
+        (NORTH_WEST, NORTH, WEST, (0.0, 0.0)),
+    ] {
+        if rock(corner) && !rock(side_a) && !rock(side_b) {
+            faces.push([x - half, y - half, x + half, y + half]);
+        }
+    }
+
+    faces
+}
+
+/// The hatching strokes for a rock cell, picked by a hash
+///
+/// Each cell gets a few parallel strokes at an angle of its own, so that neighbouring cells look
+/// drawn by hand rather than stamped.
+pub(super) fn hatch_lines(hash: u64) -> Vec<Line> {
+    const STROKES: u64 = 4;
+
+    let angle = ((hash % 12) as f64 * 15.0).to_radians();
+    let (dx, dy) = (angle.cos(), angle.sin());
+    // Nudge the strokes across by up to a tenth of a cell
+    let shift = ((hash >> 8) % 21) as f64 / 100.0 - 0.1;
+
+    (0..STROKES)
+        .filter_map(|stroke| {
+            let offset = (stroke as f64 + 0.5) / STROKES as f64 - 0.5 + shift;
+            let centre = (0.5 - dy * offset, 0.5 + dx * offset);
+            clip_to_cell(centre, (dx, dy))
+        })
+        .collect()
+}
+
+/// The part of the line through `point` in direction `dir` that lies inside of the cell, if any
+fn clip_to_cell(point: (f64, f64), dir: (f64, f64)) -> Option<Line> {
+    let (mut low, mut high) = (f64::NEG_INFINITY, f64::INFINITY);
+    for (start, step) in [(point.0, dir.0), (point.1, dir.1)] {
+        if step.abs() < 1e-9 {
+            if !(0.0..=1.0).contains(&start) {
+                return None;
+            }
+            continue;
+        }
+        let (a, b) = ((0.0 - start) / step, (1.0 - start) / step);
+        low = low.max(a.min(b));
+        high = high.min(a.max(b));
+    }
+    // Leave out slivers across the very corner of the cell
+    if high - low < 0.2 {
+        return None;
+    }
+    Some([
+        point.0 + dir.0 * low,
+        point.1 + dir.1 * low,
+        point.0 + dir.0 * high,
+        point.1 + dir.1 * high,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blob_drops_lone_diagonals() {
+        assert_eq!(NORTH | EAST, blob(NORTH | EAST | SOUTH_EAST));
+        assert_eq!(NORTH | EAST | NORTH_EAST, blob(NORTH | EAST | NORTH_EAST));
+        assert_eq!(255, blob(255));
+
+        // Exactly 47 different tiles
+        let mut tiles: Vec<u8> = (0..=255).map(blob).collect();
+        tiles.sort();
+        tiles.dedup();
+        assert_eq!(47, tiles.len());
+    }
+
+    #[test]
+    fn masks_see_the_edge_of_the_map_as_rock() {
+        let mut map = GridMap::new(3, 3);
+        map.place_room((0, 0), (1, 2)).unwrap();
+        assert_eq!(SOUTH | SOUTH_EAST | EAST, neighbour_mask(&map, 0, 0));
+        assert_eq!(
+            NORTH | SOUTH | WEST | NORTH_WEST | SOUTH_WEST,
+            neighbour_mask(&map, 1, 1)
+        );
+        assert_eq!(NORTH_WEST | WEST | SOUTH_WEST, neighbour_mask(&map, 2, 1));
+    }
+
+    #[test]
+    fn faces_cover_inside_and_outside_corners() {
+        // A cell on its own is boxed in and its faces reach round the corners
+        let faces = wall_faces(0, 0.2);
+        assert_eq!(4, faces.len());
+        assert!(faces.contains(&[-0.1, -0.1, 1.1, 0.1]));
+        assert!(faces.contains(&[-0.1, -0.1, 0.1, 1.1]));
+
+        // Open all round but for the north-east corner
+        let faces = wall_faces(!NORTH_EAST, 0.2);
+        assert_eq!(vec![[0.9, -0.1, 1.1, 0.1]], faces);
+
+        // Open all round
+        assert!(wall_faces(255, 0.2).is_empty());
+    }
+
+    #[test]
+    fn hatching_stays_in_the_cell() {
+        for hash in 0..500 {
+            let lines = hatch_lines(hash * 0x9e37_79b9);
+            assert!(!lines.is_empty());
+            for line in lines {
+                assert!(
+                    line.iter().all(|v| (-1e-9..=1.0 + 1e-9).contains(v)),
+                    "{:?}",
+                    line
+                );
+            }
+        }
+    }
+}
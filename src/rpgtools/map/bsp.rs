@@ -0,0 +1,235 @@
+//! Binary space partitioning (BSP) dungeon generator
+use std::cmp;
+
+use itertools::Itertools;
+use rand::prelude::*;
+
+use super::{GridMap, Point, RouteMethod};
+
+/// A rectangle of cells
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Rect {
+    fn centre(&self) -> Point {
+        Point::new(
+            (self.x + self.width / 2) as i64,
+            (self.y + self.height / 2) as i64,
+        )
+    }
+}
+
+/// A node in the partition tree. Leaves hold the room that was carved into them.
+enum Node {
+    Leaf(Rect),
+    Split(Box<Node>, Box<Node>),
+}
+
+impl Node {
+    /// All of the rooms under this node
+    fn rooms(&self) -> Vec<Rect> {
+        match self {
+            Node::Leaf(room) => vec![*room],
+            Node::Split(first, second) => {
+                let mut rooms = first.rooms();
+                rooms.extend(second.rooms());
+                rooms
+            }
+        }
+    }
+}
+
+impl GridMap {
+    /// Generate a dungeon by binary space partitioning
+    ///
+    /// The map is split in two, again and again, until every piece (leaf) is no larger than
+    /// `max_leaf` cells across or can't be split without making a piece smaller than `min_leaf`.
+    /// One room is carved into every leaf, so rooms never overlap, and the two halves of every
+    /// split are joined with a hallway. Doors are placed where the hallways meet the rooms.
+    ///
+    /// Leaves need a wall on each side of their room, so `min_leaf` is at least 4, and `max_leaf`
+    /// is raised to `min_leaf` if it is smaller. This method will replace the existing map.
+    pub fn generate_bsp(&mut self, min_leaf: usize, max_leaf: usize, rng: &mut impl Rng) {
+        self.clear();
+
+        let min_leaf = cmp::max(min_leaf, 4);
+        let max_leaf = cmp::max(max_leaf, min_leaf);
+        let (xmax, ymax) = self.get_limits();
+        let whole = Rect {
+            x: 0,
+            y: 0,
+            width: xmax,
+            height: ymax,
+        };
+
+        let tree = self.partition(whole, min_leaf, max_leaf, rng);
+        let room_cells = self.room_mask();
+        self.join_siblings(&tree, &room_cells, rng);
+    }
+
+    /// Split a rectangle into a tree of leaves, carving a room into each leaf
+    fn partition(&mut self, area: Rect, min_leaf: usize, max_leaf: usize, rng: &mut impl Rng) -> Node {
+        let can_split_x = area.width >= 2 * min_leaf;
+        let can_split_y = area.height >= 2 * min_leaf;
+        let too_big = area.width > max_leaf || area.height > max_leaf;
+
+        if !too_big || !(can_split_x || can_split_y) {
+            return Node::Leaf(self.carve_leaf(area, rng));
+        }
+
+        // Prefer to cut across the long side so that leaves stay roughly square
+        let split_x = match (can_split_x, can_split_y) {
+            (true, false) => true,
+            (false, true) => false,
+            _ if area.width * 4 > area.height * 5 => true,
+            _ if area.height * 4 > area.width * 5 => false,
+            _ => rng.gen(),
+        };
+
+        let (first, second) = if split_x {
+            let cut = rng.gen_range(min_leaf..=area.width - min_leaf);
+            (
+                Rect { width: cut, ..area },
+                Rect {
+                    x: area.x + cut,
+                    width: area.width - cut,
+                    ..area
+                },
+            )
+        } else {
+            let cut = rng.gen_range(min_leaf..=area.height - min_leaf);
+            (
+                Rect { height: cut, ..area },
+                Rect {
+                    y: area.y + cut,
+                    height: area.height - cut,
+                    ..area
+                },
+            )
+        };
+
+        Node::Split(
+            Box::new(self.partition(first, min_leaf, max_leaf, rng)),
+            Box::new(self.partition(second, min_leaf, max_leaf, rng)),
+        )
+    }
+
+    /// Carve a randomly sized room into a leaf, leaving at least one cell of wall on every side
+    fn carve_leaf(&mut self, leaf: Rect, rng: &mut impl Rng) -> Rect {
+        let max_width = leaf.width.saturating_sub(2).max(1);
+        let max_height = leaf.height.saturating_sub(2).max(1);
+        let width = rng.gen_range(cmp::min(3, max_width)..=max_width);
+        let height = rng.gen_range(cmp::min(3, max_height)..=max_height);
+        let x = leaf.x + 1 + rng.gen_range(0..=max_width - width);
+        let y = leaf.y + 1 + rng.gen_range(0..=max_height - height);
+
+        // Very small maps may not have room for the wall
+        let x = cmp::min(x, self.get_limits().0 - width);
+        let y = cmp::min(y, self.get_limits().1 - height);
+
+        let room = Rect { x, y, width, height };
+        let min = Point::new(x as i64, y as i64);
+        let max = Point::new((x + width - 1) as i64, (y + height - 1) as i64);
+        self.place_room(min, max)
+            .expect("bug: BSP room is outside of the map");
+        room
+    }
+
+    /// Join the two halves of every split with a hallway between their closest rooms
+    fn join_siblings(&mut self, node: &Node, room_cells: &[Vec<bool>], rng: &mut impl Rng) {
+        let Node::Split(first, second) = node else {
+            return;
+        };
+
+        self.join_siblings(first, room_cells, rng);
+        self.join_siblings(second, room_cells, rng);
+
+        let (start, end) = first
+            .rooms()
+            .iter()
+            .cartesian_product(second.rooms())
+            .map(|(a, b)| (a.centre(), b.centre()))
+            .min_by_key(|(a, b)| a.distance2(b))
+            .expect("bug: BSP split has no rooms");
+
+        let path = self
+            .route_hallway(start, end, RouteMethod::Manhattan, rng)
+            .expect("bug: could not route BSP hallway");
+        self.carve_path(&path, 1);
+        self.place_doors_along(&path, room_cells)
+            .expect("bug: could not place doors");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+
+    use crate::map::Area;
+
+    /// Count the cells that can be reached from a room cell by walking through rooms
+    fn reachable(map: &GridMap) -> (usize, usize) {
+        let (xmax, ymax) = map.get_limits();
+        let rooms: Vec<Point> = (0..xmax as i64)
+            .flat_map(|x| (0..ymax as i64).map(move |y| Point::new(x, y)))
+            .filter(|p| map[*p].is_room())
+            .collect();
+
+        let mut seen = vec![rooms[0]];
+        let mut queue = vec![rooms[0]];
+        while let Some(point) = queue.pop() {
+            for offset in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
+                let next = point + offset.into();
+                if map.in_bounds(next) && map[next].is_room() && !seen.contains(&next) {
+                    seen.push(next);
+                    queue.push(next);
+                }
+            }
+        }
+
+        (seen.len(), rooms.len())
+    }
+
+    #[test]
+    fn bsp_is_connected() {
+        for seed in 0..10 {
+            let mut map = GridMap::new(50, 40);
+            map.generate_bsp(6, 15, &mut StdRng::seed_from_u64(seed));
+
+            let (reached, total) = reachable(&map);
+            assert!(total > 0);
+            assert_eq!(total, reached);
+        }
+    }
+
+    #[test]
+    fn bsp_rooms_do_not_touch_edges() {
+        let mut map = GridMap::new(40, 40);
+        map.generate_bsp(8, 12, &mut StdRng::seed_from_u64(1));
+
+        // Every leaf has a wall around its room, and hallways run between
+        // room centres, so nothing is carved on the border of the map.
+        for i in 0..40 {
+            for point in [(i, 0), (0, i), (i, 39), (39, i)] {
+                assert_eq!(&Area::Nothing, map.get_cell_ref(point).area());
+            }
+        }
+    }
+
+    #[test]
+    fn bsp_is_reproducible() {
+        let mut map0 = GridMap::new(40, 40);
+        let mut map1 = GridMap::new(40, 40);
+        map0.generate_bsp(5, 10, &mut StdRng::seed_from_u64(7));
+        map1.generate_bsp(5, 10, &mut StdRng::seed_from_u64(7));
+
+        assert_eq!(map0, map1);
+    }
+}
@@ -1,21 +1,101 @@
 use super::Area;
 
-#[derive(Clone, Debug)]
+/// A feature on the edge between two cells
+///
+/// Secret doors are doors: anyone who knows where they are can go through them, so they join
+/// cells for movement, routing and connectivity. They only differ from doors in how they are
+/// drawn for the players (see `View`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Wall {
     Nothing,
-    //    Wall,
-    //    Door,
-    //    SecretDoor,
+    Wall,
+    Door,
+    /// A door that looks like a wall to the players
+    SecretDoor,
 }
 
-#[derive(Clone, Debug)]
+impl Wall {
+    /// Whether this edge stops movement from one cell to the next. Only walls do.
+    pub fn blocks_movement(&self) -> bool {
+        matches!(self, Wall::Wall)
+    }
+
+    /// Whether this edge stops anyone seeing from one cell to the next. Doors are taken to be
+    /// closed.
+    pub fn blocks_sight(&self) -> bool {
+        !matches!(self, Wall::Nothing)
+    }
+}
+
+/// One of the four edges of a cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Side {
+    /// The edge towards y - 1
+    North,
+    /// The edge towards x + 1
+    East,
+    /// The edge towards y + 1
+    South,
+    /// The edge towards x - 1
+    West,
+}
+
+impl Side {
+    /// The offset to the neighbouring cell on this side
+    pub fn offset(&self) -> (i64, i64) {
+        match self {
+            Side::North => (0, -1),
+            Side::East => (1, 0),
+            Side::South => (0, 1),
+            Side::West => (-1, 0),
+        }
+    }
+
+    /// The side that a step to a neighbouring cell crosses, or `None` if the offset isn't one
+    /// straight step
+    pub fn from_offset(offset: (i64, i64)) -> Option<Side> {
+        match offset {
+            (0, -1) => Some(Side::North),
+            (1, 0) => Some(Side::East),
+            (0, 1) => Some(Side::South),
+            (-1, 0) => Some(Side::West),
+            _ => None,
+        }
+    }
+
+    /// The side that faces this one from the neighbouring cell
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::North => Side::South,
+            Side::East => Side::West,
+            Side::South => Side::North,
+            Side::West => Side::East,
+        }
+    }
+}
+
+/// A feature that stands on the corner where four cells meet
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Point {
     Nothing,
-    //    Pillar,
+    /// A plain, square pillar
+    Pillar,
+    /// A round column
+    Column,
+    Statue,
+    Brazier,
 }
 
 /// Representation of a GridCell, which is a single unit in a grid.
-#[derive(Clone, Debug)]
+///
+/// Each cell owns two of its edges: the vertical wall is on its west edge and the horizontal
+/// wall is on its north edge. The east and south edges belong to the neighbouring cells. In the
+/// same way, the point feature is on the cell's north-west corner.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cell {
     /// The type of area contained within this sell
     pub area: Area,
@@ -78,6 +158,36 @@ impl Cell {
     pub fn set_area(&mut self, area: Area) {
         self.area = area;
     }
+
+    /// Get the wall on the vertical (west) edge of this cell
+    pub fn vert_wall(&self) -> &Wall {
+        &self.vert_wall
+    }
+
+    /// Set the wall on the vertical (west) edge of this cell
+    pub fn set_vert_wall(&mut self, wall: Wall) {
+        self.vert_wall = wall;
+    }
+
+    /// Get the wall on the horizontal (north) edge of this cell
+    pub fn horiz_wall(&self) -> &Wall {
+        &self.horiz_wall
+    }
+
+    /// Set the wall on the horizontal (north) edge of this cell
+    pub fn set_horiz_wall(&mut self, wall: Wall) {
+        self.horiz_wall = wall;
+    }
+
+    /// Get the feature at the north-west corner of this cell
+    pub fn point(&self) -> &Point {
+        &self.point
+    }
+
+    /// Set the feature at the north-west corner of this cell
+    pub fn set_point(&mut self, point: Point) {
+        self.point = point;
+    }
 }
 
 impl Default for Cell {
@@ -119,4 +229,76 @@ mod tests {
         cell.area = Area::Room;
         assert!(cell.is_room());
     }
+
+    #[test]
+    fn walls() {
+        let mut cell = Cell::new();
+        cell.set_vert_wall(Wall::Door);
+        cell.set_horiz_wall(Wall::SecretDoor);
+
+        assert_eq!(&Wall::Door, cell.vert_wall());
+        assert_eq!(&Wall::SecretDoor, cell.horiz_wall());
+        assert!(!cell.is_empty());
+        assert!(!cell.vert_wall().blocks_movement());
+        assert!(!cell.horiz_wall().blocks_movement());
+        assert!(Wall::Wall.blocks_movement());
+    }
+
+    #[test]
+    fn side_offsets() {
+        for side in [Side::North, Side::East, Side::South, Side::West] {
+            assert_eq!(Some(side), Side::from_offset(side.offset()));
+        }
+        assert_eq!(None, Side::from_offset((1, 1)));
+        assert_eq!(None, Side::from_offset((0, 0)));
+    }
+
+    #[test]
+    fn point() {
+        let mut cell = Cell::new();
+        cell.set_point(Point::Pillar);
+
+        assert_eq!(&Point::Pillar, cell.point());
+        assert!(!cell.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        for area in [
+            Area::Nothing,
+            Area::Entrance,
+            Area::Room,
+            Area::Corridor,
+            Area::StairsUp,
+            Area::StairsDown,
+        ] {
+            let mut cell = Cell::new();
+            cell.set_area(area);
+
+            let json = serde_json::to_string(&cell).unwrap();
+            assert_eq!(cell, serde_json::from_str(&json).unwrap());
+        }
+
+        let walls = [Wall::Nothing, Wall::Wall, Wall::Door, Wall::SecretDoor];
+        let points = [
+            Point::Nothing,
+            Point::Pillar,
+            Point::Column,
+            Point::Statue,
+            Point::Brazier,
+        ];
+        for (i, point) in points.into_iter().enumerate() {
+            for (j, vert_wall) in walls.into_iter().enumerate() {
+                let mut cell = Cell::new();
+                cell.set_area(Area::Room);
+                cell.set_vert_wall(vert_wall);
+                cell.set_horiz_wall(walls[(i + j) % walls.len()]);
+                cell.set_point(point);
+
+                let json = serde_json::to_string(&cell).unwrap();
+                assert_eq!(cell, serde_json::from_str(&json).unwrap());
+            }
+        }
+    }
 }
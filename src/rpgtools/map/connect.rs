@@ -0,0 +1,136 @@
+//! Joining the rooms of a map with hallways so that every room can be reached
+use rand::prelude::*;
+
+use super::maze::DisjointSets;
+use super::{GridMap, Point, RouteMethod};
+use crate::error::{Result, RpgError};
+
+/// How a generator decides which rooms to join with hallways
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Connectivity {
+    /// Join rooms that are near each other. Rooms that are far from the rest may be left out.
+    Nearby,
+    /// Join every room with a minimum spanning tree of hallways, so that every room can be
+    /// reached, and then add extra hallways to make loops. The extra hallways are the given share
+    /// of the tree's hallways, shortest first.
+    SpanningTree(f64),
+}
+
+impl GridMap {
+    /// Join every room on the map with hallways
+    ///
+    /// Rooms are joined by the shortest hallways that connect all of them (a minimum spanning
+    /// tree), and then `loops` times as many hallways again are added, shortest first, to make
+    /// loops. A `loops` of 0 leaves exactly one way between any two rooms, unless the hallways
+    /// cross. Hallways follow `route` and are `width` cells wide.
+    ///
+    /// `RouteMethod::Pathfind` returns an error if walls cut two rooms off from each other.
+    pub fn connect_rooms(
+        &mut self,
+        route: RouteMethod,
+        width: usize,
+        loops: f64,
+        rng: &mut impl Rng,
+    ) -> Result<()> {
+        self.join_rooms(route, width, loops, rng)?;
+        Ok(())
+    }
+
+    /// Check that every room and corridor can be reached from every other one
+    ///
+    /// Walls cut rooms apart, while doors and secret doors join them. Returns
+    /// `RpgError::Disconnected` with the number of separate areas otherwise.
+    pub fn check_connected(&self) -> Result<()> {
+        match self.room_graph().components().len() {
+            0 | 1 => Ok(()),
+            areas => Err(RpgError::Disconnected(areas)),
+        }
+    }
+
+    /// Join every room with a spanning tree of hallways plus some loops
+    ///
+    /// Returns the path of every hallway that was carved.
+    pub(crate) fn join_rooms(
+        &mut self,
+        route: RouteMethod,
+        width: usize,
+        loops: f64,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<Vec<Point>>> {
+        let rooms = self.partition_rooms();
+
+        // Every possible hallway, shortest first
+        let mut candidates: Vec<(u64, usize, usize, Point, Point)> = vec![];
+        for i in 0..rooms.len() {
+            for j in i + 1..rooms.len() {
+                let (cell0, cell1) = rooms[i].nearest_cells(&rooms[j])?;
+                candidates.push((cell0.distance2(&cell1), i, j, cell0, cell1));
+            }
+        }
+        candidates.sort_by_key(|(distance, i, j, _, _)| (*distance, *i, *j));
+
+        // Kruskal's algorithm picks the tree, and the hallways that it skips can make loops
+        let mut sets = DisjointSets::new(rooms.len());
+        let (mut tree, mut extra) = (vec![], vec![]);
+        for (_, i, j, cell0, cell1) in candidates {
+            if sets.union(i, j) {
+                tree.push((cell0, cell1));
+            } else {
+                extra.push((cell0, cell1));
+            }
+        }
+        let num_loops = (loops.max(0.0) * tree.len() as f64).round() as usize;
+        tree.extend(extra.into_iter().take(num_loops));
+
+        let mut paths = vec![];
+        for (cell0, cell1) in tree {
+            let path = self.route_hallway(cell0, cell1, route, rng)?;
+            self.carve_path(&path, width);
+            paths.push(path);
+        }
+
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+
+    use crate::map::PathCosts;
+
+    #[test]
+    fn every_room_is_joined() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for route in [RouteMethod::Manhattan, RouteMethod::Pathfind(PathCosts::default())] {
+            let mut map = GridMap::new(60, 40);
+            for (x, y) in [(2, 2), (50, 3), (4, 33), (55, 34), (28, 18)] {
+                map.place_room((x, y), (x + 3, y + 3)).unwrap();
+            }
+            assert!(matches!(map.check_connected(), Err(RpgError::Disconnected(5))));
+
+            map.connect_rooms(route, 1, 0.0, &mut rng).unwrap();
+            map.check_connected().unwrap();
+        }
+    }
+
+    #[test]
+    fn loops_add_hallways() {
+        let mut map = GridMap::new(40, 40);
+        for (x, y) in [(2, 2), (30, 2), (2, 30), (30, 30)] {
+            map.place_room((x, y), (x + 5, y + 5)).unwrap();
+        }
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let tree = map.clone().join_rooms(RouteMethod::Manhattan, 1, 0.0, &mut rng).unwrap();
+        assert_eq!(3, tree.len());
+
+        let mut looped = map.clone();
+        let paths = looped.join_rooms(RouteMethod::Manhattan, 1, 0.5, &mut rng).unwrap();
+        assert_eq!(5, paths.len());
+        assert!(!looped.room_graph().cycles().is_empty());
+    }
+}
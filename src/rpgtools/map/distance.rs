@@ -0,0 +1,306 @@
+//! Distance maps, for working out how far it is to walk from one place to everywhere else
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap};
+
+use super::cell::Side;
+use super::{Area, GridMap, Point};
+
+/// Which steps can be taken from one cell to the next
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Movement {
+    /// Only north, east, south and west
+    FourWay,
+    /// Diagonal steps as well, costing the same as straight ones
+    EightWay,
+    /// Diagonal steps as well, with every second diagonal step costing double, like the
+    /// optional diagonal rule of 5th edition
+    Alternating,
+}
+
+/// The cost of stepping into each kind of area, or `None` if it can't be entered
+///
+/// With the default costs every open cell costs 1 to enter and empty cells can't be entered.
+/// Raise a cost to 2 to make an area difficult terrain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MoveCosts {
+    pub nothing: Option<u32>,
+    pub entrance: Option<u32>,
+    pub room: Option<u32>,
+    pub corridor: Option<u32>,
+    /// Stairs, both up and down
+    pub stairs: Option<u32>,
+}
+
+impl Default for MoveCosts {
+    fn default() -> Self {
+        Self {
+            nothing: None,
+            entrance: Some(1),
+            room: Some(1),
+            corridor: Some(1),
+            stairs: Some(1),
+        }
+    }
+}
+
+impl MoveCosts {
+    /// The cost of stepping into an area
+    pub fn cost(&self, area: &Area) -> Option<u32> {
+        match area {
+            Area::Nothing => self.nothing,
+            Area::Entrance => self.entrance,
+            Area::Room => self.room,
+            Area::Corridor => self.corridor,
+            Area::StairsUp | Area::StairsDown => self.stairs,
+        }
+    }
+}
+
+/// The cost of the cheapest walk to every cell of a map, made with `GridMap::distance_map`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DistanceMap {
+    /// Indexed by [x][y]; `None` for cells that can't be reached
+    distances: Vec<Vec<Option<u32>>>,
+}
+
+impl DistanceMap {
+    /// The distance to a cell, or `None` if it can't be reached or is off of the map
+    pub fn get(&self, point: impl Into<Point>) -> Option<u32> {
+        let point = point.into();
+        let x = usize::try_from(point.x).ok()?;
+        let y = usize::try_from(point.y).ok()?;
+        *self.distances.get(x)?.get(y)?
+    }
+
+    /// Every cell that is at most `limit` away, such as the cells that can be reached with one
+    /// move
+    pub fn within(&self, limit: u32) -> BTreeSet<Point> {
+        let mut points = BTreeSet::new();
+        for (x, column) in self.distances.iter().enumerate() {
+            for (y, distance) in column.iter().enumerate() {
+                if distance.is_some_and(|distance| distance <= limit) {
+                    points.insert(Point::new(x as i64, y as i64));
+                }
+            }
+        }
+        points
+    }
+
+    /// The distance to the nearest of some cells, such as the cells of a room
+    pub fn nearest<'a>(&self, points: impl IntoIterator<Item = &'a Point>) -> Option<u32> {
+        points
+            .into_iter()
+            .filter_map(|point| self.get(*point))
+            .min()
+    }
+}
+
+/// Straight steps
+const STRAIGHT: [(i64, i64); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+/// Diagonal steps
+const DIAGONAL: [(i64, i64); 4] = [(1, -1), (1, 1), (-1, 1), (-1, -1)];
+
+impl GridMap {
+    /// Work out how far it is from the nearest of `sources` to every cell of the map
+    ///
+    /// Stepping into a cell costs whatever `costs` gives for its area, and walls can't be crossed.
+    /// Diagonal steps, if `movement` allows them, can't cut corners: both of the straight routes
+    /// around the corner have to be open. The sources themselves are always 0 away.
+    pub fn distance_map(
+        &self,
+        sources: &[Point],
+        movement: Movement,
+        costs: &MoveCosts,
+    ) -> DistanceMap {
+        let (xmax, ymax) = self.get_limits();
+        // Alternating diagonals depend on whether an odd number of diagonal steps has been taken
+        // so far, so each cell is visited once for each
+        let index = |point: Point, odd: bool| {
+            ((point.x as usize * ymax) + point.y as usize) * 2 + odd as usize
+        };
+        let mut best = vec![u32::MAX; xmax * ymax * 2];
+        let mut queue = BinaryHeap::new();
+
+        for source in sources.iter().filter(|source| self.in_bounds(**source)) {
+            best[index(*source, false)] = 0;
+            queue.push(Reverse((0, *source, false)));
+        }
+
+        while let Some(Reverse((distance, point, odd))) = queue.pop() {
+            if distance > best[index(point, odd)] {
+                continue;
+            }
+
+            let mut steps = vec![];
+            for step in STRAIGHT {
+                if self.can_step(point, step) {
+                    steps.push((step, false));
+                }
+            }
+            if movement != Movement::FourWay {
+                for step in DIAGONAL {
+                    let corners = [(step.0, 0), (0, step.1)];
+                    let around = |first: (i64, i64), second: (i64, i64)| {
+                        self.can_step(point, first)
+                            && self.can_step(point + first.into(), second)
+                            && self.cost_at(point + first.into(), costs).is_some()
+                    };
+                    if around(corners[0], corners[1]) && around(corners[1], corners[0]) {
+                        steps.push((step, true));
+                    }
+                }
+            }
+
+            for (step, diagonal) in steps {
+                let next = point + step.into();
+                let Some(cost) = self.cost_at(next, costs) else {
+                    continue;
+                };
+                let (cost, next_odd) = match (movement, diagonal) {
+                    (Movement::Alternating, true) if odd => (cost * 2, false),
+                    (Movement::Alternating, true) => (cost, true),
+                    _ => (cost, odd),
+                };
+
+                let next_distance = distance.saturating_add(cost);
+                if next_distance < best[index(next, next_odd)] {
+                    best[index(next, next_odd)] = next_distance;
+                    queue.push(Reverse((next_distance, next, next_odd)));
+                }
+            }
+        }
+
+        let distances = (0..xmax)
+            .map(|x| {
+                (0..ymax)
+                    .map(|y| {
+                        let point = Point::new(x as i64, y as i64);
+                        let distance = best[index(point, false)].min(best[index(point, true)]);
+                        (distance != u32::MAX).then_some(distance)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        DistanceMap { distances }
+    }
+
+    /// The cost of stepping into a cell, or `None` if it can't be entered
+    fn cost_at(&self, point: Point, costs: &MoveCosts) -> Option<u32> {
+        if !self.in_bounds(point) {
+            return None;
+        }
+        costs.cost(self[point].area())
+    }
+
+    /// Whether a straight step stays on the map without crossing a wall
+    fn can_step(&self, point: Point, step: (i64, i64)) -> bool {
+        self.in_bounds(point + step.into())
+            && Side::from_offset(step)
+                .and_then(|side| self.get_wall(point, side).ok())
+                .is_some_and(|wall| !wall.blocks_movement())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::map::cell::Wall;
+
+    fn open_room() -> GridMap {
+        let mut map = GridMap::new(12, 12);
+        map.place_room((1, 1), (10, 10)).unwrap();
+        map
+    }
+
+    #[test]
+    fn movement_rules() {
+        let map = open_room();
+        let costs = MoveCosts::default();
+        let start = [Point::new(1, 1)];
+
+        let four = map.distance_map(&start, Movement::FourWay, &costs);
+        let eight = map.distance_map(&start, Movement::EightWay, &costs);
+        let alternating = map.distance_map(&start, Movement::Alternating, &costs);
+
+        assert_eq!(Some(0), four.get((1, 1)));
+        assert_eq!(Some(6), four.get((4, 4)));
+        assert_eq!(Some(3), eight.get((4, 4)));
+        assert_eq!(Some(4), alternating.get((4, 4)));
+        assert_eq!(Some(6), alternating.get((5, 5)));
+        assert_eq!(Some(4), alternating.get((5, 2)));
+        assert_eq!(Some(12), alternating.get((10, 7)));
+
+        // Rock can't be entered
+        assert_eq!(None, four.get((0, 1)));
+        assert_eq!(None, four.get((-1, 1)));
+    }
+
+    #[test]
+    fn several_sources() {
+        let map = open_room();
+        let sources = [Point::new(1, 5), Point::new(10, 5)];
+        let distances = map.distance_map(&sources, Movement::FourWay, &MoveCosts::default());
+
+        assert_eq!(Some(4), distances.get((5, 5)));
+        assert_eq!(Some(4), distances.get((6, 5)));
+        assert_eq!(Some(1), distances.get((9, 5)));
+    }
+
+    #[test]
+    fn difficult_terrain() {
+        let mut map = open_room();
+        for y in 1..=10 {
+            map.get_cell_mut((5, y)).set_area(Area::Corridor);
+        }
+        let costs = MoveCosts {
+            corridor: Some(2),
+            ..Default::default()
+        };
+        let distances = map.distance_map(&[Point::new(1, 1)], Movement::FourWay, &costs);
+
+        assert_eq!(Some(5), distances.get((5, 1)));
+        assert_eq!(Some(6), distances.get((6, 1)));
+    }
+
+    #[test]
+    fn walls_block_movement() {
+        let mut map = open_room();
+        for y in 1..=10 {
+            map.set_wall((6, y), Side::West, Wall::Wall).unwrap();
+        }
+        map.set_wall((6, 10), Side::West, Wall::Door).unwrap();
+        let costs = MoveCosts::default();
+
+        let four = map.distance_map(&[Point::new(5, 1)], Movement::FourWay, &costs);
+        assert_eq!(Some(9 + 1 + 9), four.get((6, 1)));
+
+        // Diagonals can't slip past the end of the wall either
+        let eight = map.distance_map(&[Point::new(5, 1)], Movement::EightWay, &costs);
+        assert_eq!(Some(9 + 1 + 9), eight.get((6, 1)));
+        assert_eq!(Some(9 + 1), eight.get((6, 10)));
+    }
+
+    #[test]
+    fn reach_with_a_budget() {
+        let map = open_room();
+        let distances = map.distance_map(
+            &[Point::new(5, 5)],
+            Movement::EightWay,
+            &MoveCosts::default(),
+        );
+
+        let reach = distances.within(2);
+        assert_eq!(25, reach.len());
+        assert!(reach.contains(&Point::new(3, 7)));
+        assert!(!reach.contains(&Point::new(2, 5)));
+
+        let cells = [Point::new(8, 8), Point::new(9, 9), Point::new(0, 0)];
+        assert_eq!(Some(3), distances.nearest(&cells));
+    }
+}
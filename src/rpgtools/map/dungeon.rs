@@ -0,0 +1,325 @@
+//! Dungeons made of several levels
+use std::ops::{Index, IndexMut};
+
+use rand::prelude::*;
+
+use super::{Area, GridMap, Point, RouteMethod};
+use crate::error::{Result, RpgError};
+
+/// A stack of map levels that are linked together by stairs
+///
+/// Level 0 is the top of the dungeon. Every level is the same size, so that a set of down stairs
+/// at a point on one level lands on the up stairs at the same point on the level below.
+///
+/// # Examples
+/// ```
+/// # use rpgtools::map::{Connectivity, Dungeon, RouteMethod};
+/// # use rand::SeedableRng;
+/// let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+/// let dungeon = Dungeon::generate(30, 30, 3, &mut rng, |map, rng| {
+///     map.generate_dungeon(8, 6, false, RouteMethod::Manhattan, 1, Connectivity::Nearby, rng);
+/// })
+/// .unwrap();
+/// assert_eq!(3, dungeon.num_levels());
+/// assert_eq!(1, dungeon.stairs_between(0).len());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dungeon {
+    levels: Vec<GridMap>,
+}
+
+impl Dungeon {
+    /// Make a new dungeon with no levels
+    pub fn new() -> Dungeon {
+        Dungeon { levels: vec![] }
+    }
+
+    /// Generate a dungeon with a number of levels
+    ///
+    /// Each level is filled in by calling `generator`, and then every level is linked to the one
+    /// below it by a pair of stairs. Fails if a level has no open cell for the stairs.
+    pub fn generate<R, F>(
+        width: usize,
+        height: usize,
+        levels: usize,
+        rng: &mut R,
+        mut generator: F,
+    ) -> Result<Dungeon>
+    where
+        R: Rng,
+        F: FnMut(&mut GridMap, &mut R),
+    {
+        let mut dungeon = Dungeon::new();
+
+        for _ in 0..levels {
+            let mut map = GridMap::new(width, height);
+            generator(&mut map, rng);
+            dungeon.add_level(map)?;
+        }
+
+        for upper in 0..levels.saturating_sub(1) {
+            dungeon.link_levels(upper, rng)?;
+        }
+
+        Ok(dungeon)
+    }
+
+    /// Add a level to the bottom of the dungeon
+    ///
+    /// The level must be the same size as the levels that are already in the dungeon.
+    pub fn add_level(&mut self, map: GridMap) -> Result<()> {
+        if let Some(first) = self.levels.first() {
+            if first.get_limits() != map.get_limits() {
+                return Err(RpgError::LevelMismatch(self.levels.len()));
+            }
+        }
+
+        self.levels.push(map);
+        Ok(())
+    }
+
+    /// The number of levels in the dungeon
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Get a level of the dungeon
+    pub fn level(&self, level: usize) -> Option<&GridMap> {
+        self.levels.get(level)
+    }
+
+    /// Get a mutable level of the dungeon
+    pub fn level_mut(&mut self, level: usize) -> Option<&mut GridMap> {
+        self.levels.get_mut(level)
+    }
+
+    /// Iterate over the levels, from the top down
+    pub fn iter_levels(&self) -> impl Iterator<Item = &GridMap> {
+        self.levels.iter()
+    }
+
+    /// Join a level to the one below it with a pair of stairs
+    ///
+    /// Down stairs are placed on `upper` and up stairs are placed at the same point on the level
+    /// below, on a room or corridor cell that isn't already a landmark. A point that is open on
+    /// both levels is preferred. If there isn't one then an open cell on the upper level is picked
+    /// and a hallway is dug on the lower level to meet it. Returns the point where the stairs were
+    /// placed.
+    pub fn link_levels(&mut self, upper: usize, rng: &mut impl Rng) -> Result<Point> {
+        if upper + 1 >= self.levels.len() {
+            return Err(RpgError::OutOfBounds);
+        }
+
+        let free = |area: &Area| *area != Area::Nothing && !area.is_landmark();
+        let (xmax, ymax) = self.levels[upper].get_limits();
+        let mut shared = vec![];
+        let mut upper_rooms = vec![];
+        for x in 0..xmax {
+            for y in 0..ymax {
+                let point: Point = (x, y).try_into()?;
+                if !free(self.levels[upper][point].area()) {
+                    continue;
+                }
+                upper_rooms.push(point);
+                if free(self.levels[upper + 1][point].area()) {
+                    shared.push(point);
+                }
+            }
+        }
+
+        let point = match shared.choose(rng) {
+            Some(point) => *point,
+            None => {
+                let point = *upper_rooms.choose(rng).ok_or_else(|| {
+                    RpgError::Empty(format!("level {} has no room for stairs", upper))
+                })?;
+
+                // Dig a way from the stairs to the rest of the lower level
+                let lower = &mut self.levels[upper + 1];
+                if let Some(nearest) = lower.find_nearest_connected(point, rng)? {
+                    lower.place_hallway(point, nearest, RouteMethod::Manhattan, 1, rng)?;
+                }
+                point
+            }
+        };
+
+        self.levels[upper].get_cell_mut(point).set_area(Area::StairsDown);
+        self.levels[upper + 1].get_cell_mut(point).set_area(Area::StairsUp);
+        Ok(point)
+    }
+
+    /// Link a level to the levels above and below it again, after it has been regenerated
+    ///
+    /// Every set of stairs on the level, and the stairs on its neighbours that lead to it, are
+    /// taken away first so that no stairs are left leading nowhere. Then new stairs are placed as
+    /// by [`Dungeon::link_levels`].
+    pub fn relink_level(&mut self, level: usize, rng: &mut impl Rng) -> Result<()> {
+        if level >= self.levels.len() {
+            return Err(RpgError::OutOfBounds);
+        }
+
+        clear_stairs(&mut self.levels[level], &[Area::StairsUp, Area::StairsDown]);
+        if level > 0 {
+            clear_stairs(&mut self.levels[level - 1], &[Area::StairsDown]);
+            self.link_levels(level - 1, rng)?;
+        }
+        if level + 1 < self.levels.len() {
+            clear_stairs(&mut self.levels[level + 1], &[Area::StairsUp]);
+            self.link_levels(level, rng)?;
+        }
+        Ok(())
+    }
+
+    /// Find the stairs that lead from a level to the one below it
+    ///
+    /// Only stairs that are linked are returned: down stairs on `upper` with up stairs at the
+    /// same point on the level below.
+    pub fn stairs_between(&self, upper: usize) -> Vec<Point> {
+        let (Some(top), Some(bottom)) = (self.level(upper), self.level(upper + 1)) else {
+            return vec![];
+        };
+
+        let (xmax, ymax) = top.get_limits();
+        let mut stairs = vec![];
+        for x in 0..xmax as i64 {
+            for y in 0..ymax as i64 {
+                let point = Point::new(x, y);
+                if top[point].area == Area::StairsDown && bottom[point].area == Area::StairsUp {
+                    stairs.push(point);
+                }
+            }
+        }
+
+        stairs
+    }
+}
+
+/// Turn stairs of the given kinds back into plain room
+fn clear_stairs(map: &mut GridMap, stairs: &[Area]) {
+    let (xmax, ymax) = map.get_limits();
+    for x in 0..xmax as i64 {
+        for y in 0..ymax as i64 {
+            let cell = map.get_cell_mut((x, y));
+            if stairs.contains(cell.area()) {
+                cell.set_area(Area::Room);
+            }
+        }
+    }
+}
+
+impl Index<usize> for Dungeon {
+    type Output = GridMap;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.levels[index]
+    }
+}
+
+impl IndexMut<usize> for Dungeon {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.levels[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+
+    use crate::map::{Connectivity, TileSet};
+
+    #[test]
+    fn levels_must_match() {
+        let mut dungeon = Dungeon::new();
+        dungeon.add_level(GridMap::new(10, 10)).unwrap();
+
+        assert!(dungeon.add_level(GridMap::new(10, 12)).is_err());
+        assert_eq!(1, dungeon.num_levels());
+    }
+
+    #[test]
+    fn stairs_line_up() {
+        for seed in 0..5 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let dungeon = Dungeon::generate(30, 30, 4, &mut rng, |map, rng| {
+                map.generate_cave(4, 50, RouteMethod::Manhattan, 1, Connectivity::Nearby, rng);
+            })
+            .unwrap();
+
+            for upper in 0..3 {
+                let stairs = dungeon.stairs_between(upper);
+                assert_eq!(1, stairs.len());
+            }
+            assert!(dungeon.stairs_between(3).is_empty());
+        }
+    }
+
+    #[test]
+    fn stairs_go_in_corridors() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let tiles = TileSet::corridors();
+        let dungeon = Dungeon::generate(20, 20, 2, &mut rng, |map, rng| {
+            map.generate_wfc(&tiles, rng).unwrap();
+        })
+        .unwrap();
+        assert_eq!(1, dungeon.stairs_between(0).len());
+
+        // With nowhere to put the stairs the error is passed on
+        let empty = Dungeon::generate(10, 10, 2, &mut rng, |_, _| {});
+        assert!(matches!(empty, Err(RpgError::Empty(_))));
+    }
+
+    #[test]
+    fn regenerated_level_is_relinked() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let generate = |map: &mut GridMap, rng: &mut StdRng| {
+            map.generate_cave(4, 50, RouteMethod::Manhattan, 1, Connectivity::Nearby, rng);
+        };
+        let mut dungeon = Dungeon::generate(30, 30, 3, &mut rng, generate).unwrap();
+
+        for _ in 0..3 {
+            generate(&mut dungeon[1], &mut rng);
+            dungeon.relink_level(1, &mut rng).unwrap();
+
+            // Exactly one set of stairs each way, and none left over that lead nowhere
+            for upper in 0..2 {
+                assert_eq!(1, dungeon.stairs_between(upper).len());
+            }
+            let count = |level: usize, area: Area| {
+                let map = &dungeon[level];
+                (0..30)
+                    .flat_map(|x| (0..30).map(move |y| Point::new(x, y)))
+                    .filter(|point| map[*point].area == area)
+                    .count()
+            };
+            assert_eq!(1, count(0, Area::StairsDown));
+            assert_eq!(1, count(1, Area::StairsUp));
+            assert_eq!(1, count(1, Area::StairsDown));
+            assert_eq!(1, count(2, Area::StairsUp));
+        }
+    }
+
+    #[test]
+    fn link_dug_into_empty_level() {
+        let mut upper = GridMap::new(10, 10);
+        upper.place_room((1, 1), (3, 3)).unwrap();
+        let mut lower = GridMap::new(10, 10);
+        lower.place_room((6, 6), (8, 8)).unwrap();
+
+        let mut dungeon = Dungeon::new();
+        dungeon.add_level(upper).unwrap();
+        dungeon.add_level(lower).unwrap();
+
+        let point = dungeon
+            .link_levels(0, &mut StdRng::seed_from_u64(0))
+            .unwrap();
+        assert_eq!(&Area::StairsDown, dungeon[0][point].area());
+        assert_eq!(&Area::StairsUp, dungeon[1][point].area());
+        assert_eq!(vec![point], dungeon.stairs_between(0));
+
+        // The hallway on the lower level reaches the existing room
+        assert!(dungeon[1][Point::new(6, point.y)].is_room() || dungeon[1][Point::new(point.x, 6)].is_room());
+    }
+}
@@ -0,0 +1,423 @@
+//! Native on-disk format for maps
+//!
+//! Maps are stored as plain text so that they are easy to inspect and diff. The first line is a
+//! header containing the format name and version, the second line is the size of the map and
+//! every line after that is one row of cells:
+//!
+//! ```text
+//! rpgmap 1
+//! 3 2
+//! .... R... R...
+//! .... E... R...
+//! ```
+//!
+//! Each cell is a four character token made up of the area, the vertical wall, the horizontal
+//! wall and the corner feature, in that order. A `.` means that the cell has nothing in that
+//! slot.
+//!
+//! A dungeon with several levels is stored as one map after another, from the top level down,
+//! so a file with a single map is also a dungeon with one level.
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use super::cell::{Point as CornerPoint, Wall};
+use super::{Area, Cell, Dungeon, GridMap};
+use crate::error::{Result, RpgError};
+
+/// Name at the start of every map file
+const FORMAT_NAME: &str = "rpgmap";
+
+/// Current version of the map file format. Bump this whenever the meaning of a token changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// File extension used for maps saved in the native format
+pub const FILE_EXTENSION: &str = "rpgmap";
+
+/// The most cells that a map read from a file can have along either side
+pub const MAX_SIDE: usize = 65536;
+
+impl GridMap {
+    /// Save the map to a file in the native map format
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = File::create(path)?;
+        self.write_to(BufWriter::new(file))
+    }
+
+    /// Load a map from a file in the native map format
+    ///
+    /// The file must hold a single map. Use [`Dungeon::load`] for files with several levels.
+    pub fn load(path: impl AsRef<Path>) -> Result<GridMap> {
+        let file = File::open(path)?;
+        read_only_level(BufReader::new(file))
+    }
+
+    /// Write the map in the native map format to any writer
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        let (xmax, ymax) = self.get_limits();
+
+        writeln!(writer, "{} {}", FORMAT_NAME, FORMAT_VERSION)?;
+        writeln!(writer, "{} {}", xmax, ymax)?;
+
+        for y in 0..ymax {
+            let row: Vec<String> = (0..xmax)
+                .map(|x| encode_cell(self.get_cell_ref((x as i64, y as i64))))
+                .collect();
+            writeln!(writer, "{}", row.join(" "))?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a map in the native map format from any buffered reader
+    pub fn read_from<R: BufRead>(reader: R) -> Result<GridMap> {
+        let mut lines = reader.lines().enumerate();
+
+        // Header: format name and version
+        let (_, header) = lines
+            .next()
+            .ok_or_else(|| RpgError::Parse(1, "missing header".to_string()))?;
+        let header = header?;
+        let mut header = header.split_whitespace();
+        if header.next() != Some(FORMAT_NAME) {
+            return Err(RpgError::Parse(1, "not an rpgmap file".to_string()));
+        }
+        let version: u32 = header
+            .next()
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| RpgError::Parse(1, "missing format version".to_string()))?;
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(RpgError::UnsupportedVersion(version));
+        }
+
+        // Size of the map
+        let (_, size) = lines
+            .next()
+            .ok_or_else(|| RpgError::Parse(2, "missing map size".to_string()))?;
+        let size = size?;
+        let size: Vec<usize> = size
+            .split_whitespace()
+            .map(|v| v.parse())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| RpgError::Parse(2, "map size is not a number".to_string()))?;
+        let (xmax, ymax) = match size[..] {
+            [x, y] => (x, y),
+            _ => return Err(RpgError::Parse(2, "expected width and height".to_string())),
+        };
+        if xmax == 0 || ymax == 0 {
+            return Err(RpgError::Parse(2, "a map needs at least one cell".to_string()));
+        }
+        if xmax > MAX_SIDE || ymax > MAX_SIDE {
+            return Err(RpgError::Parse(
+                2,
+                format!("maps can be at most {} cells across", MAX_SIDE),
+            ));
+        }
+
+        // One row of cells per line. The rows are all read before the map is made, so that a
+        // broken size line can't ask for more memory than the file itself takes up.
+        let mut rows = vec![];
+        for y in 0..ymax {
+            let (index, row) = lines
+                .next()
+                .ok_or_else(|| RpgError::Parse(y + 3, "missing row of cells".to_string()))?;
+            let row = row?;
+            let line = index + 1;
+
+            let tokens: Vec<&str> = row.split_whitespace().collect();
+            if tokens.len() != xmax {
+                return Err(RpgError::Parse(
+                    line,
+                    format!("expected {} cells, found {}", xmax, tokens.len()),
+                ));
+            }
+
+            let cells: Vec<Cell> = tokens
+                .into_iter()
+                .map(|token| decode_cell(token, line))
+                .collect::<Result<_>>()?;
+            rows.push(cells);
+        }
+
+        let mut map = GridMap::new(xmax, ymax);
+        for (y, row) in rows.into_iter().enumerate() {
+            for (x, cell) in row.into_iter().enumerate() {
+                *map.get_cell_mut((x as i64, y as i64)) = cell;
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+impl Dungeon {
+    /// Save every level of the dungeon to a file in the native map format
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = File::create(path)?;
+        self.write_to(BufWriter::new(file))
+    }
+
+    /// Load a dungeon from a file in the native map format
+    pub fn load(path: impl AsRef<Path>) -> Result<Dungeon> {
+        let file = File::open(path)?;
+        Dungeon::read_from(BufReader::new(file))
+    }
+
+    /// Write every level of the dungeon, from the top down, to any writer
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        for level in self.iter_levels() {
+            level.write_to(&mut writer)?;
+        }
+        Ok(())
+    }
+
+    /// Read all of the levels in the native map format from any buffered reader
+    pub fn read_from<R: BufRead>(mut reader: R) -> Result<Dungeon> {
+        let mut dungeon = Dungeon::new();
+
+        // Keep reading maps until we run out of file
+        loop {
+            skip_blank_lines(&mut reader)?;
+            if reader.fill_buf()?.is_empty() {
+                break;
+            }
+            dungeon.add_level(GridMap::read_from(&mut reader)?)?;
+        }
+
+        Ok(dungeon)
+    }
+}
+
+/// Read a map, making sure that nothing but blank lines come after it
+fn read_only_level<R: BufRead>(mut reader: R) -> Result<GridMap> {
+    let map = GridMap::read_from(&mut reader)?;
+    let blank = skip_blank_lines(&mut reader)?;
+    if !reader.fill_buf()?.is_empty() {
+        let (_, ymax) = map.get_limits();
+        return Err(RpgError::Parse(
+            ymax + 3 + blank,
+            "the file has more than one level".to_string(),
+        ));
+    }
+    Ok(map)
+}
+
+/// Skip past any blank lines, returning how many there were
+fn skip_blank_lines<R: BufRead>(reader: &mut R) -> Result<usize> {
+    let mut lines = 0;
+    loop {
+        let Some(&byte) = reader.fill_buf()?.first() else {
+            return Ok(lines);
+        };
+        if !byte.is_ascii_whitespace() {
+            return Ok(lines);
+        }
+        if byte == b'\n' {
+            lines += 1;
+        }
+        reader.consume(1);
+    }
+}
+
+/// Encode a cell into its four character token
+fn encode_cell(cell: &Cell) -> String {
+    let area = match cell.area() {
+        Area::Nothing => '.',
+        Area::Entrance => 'E',
+        Area::Room => 'R',
+        Area::Corridor => 'C',
+        Area::StairsUp => '<',
+        Area::StairsDown => '>',
+    };
+
+    [
+        area,
+        encode_wall(cell.vert_wall()),
+        encode_wall(cell.horiz_wall()),
+        encode_corner(cell.point()),
+    ]
+    .iter()
+    .collect()
+}
+
+fn encode_wall(wall: &Wall) -> char {
+    match wall {
+        Wall::Nothing => '.',
+        Wall::Wall => 'W',
+        Wall::Door => 'D',
+        Wall::SecretDoor => 'S',
+    }
+}
+
+fn encode_corner(point: &CornerPoint) -> char {
+    match point {
+        CornerPoint::Nothing => '.',
+        CornerPoint::Pillar => 'P',
+        CornerPoint::Column => 'C',
+        CornerPoint::Statue => 'S',
+        CornerPoint::Brazier => 'B',
+    }
+}
+
+/// Decode a four character token back into a cell
+fn decode_cell(token: &str, line: usize) -> Result<Cell> {
+    let chars: Vec<char> = token.chars().collect();
+    let [area, vert, horiz, point] = chars[..] else {
+        return Err(RpgError::Parse(line, format!("bad cell token '{}'", token)));
+    };
+
+    let mut cell = Cell::new();
+    cell.set_area(decode_area(area, line)?);
+    cell.set_vert_wall(decode_wall(vert, line)?);
+    cell.set_horiz_wall(decode_wall(horiz, line)?);
+    cell.set_point(match point {
+        '.' => CornerPoint::Nothing,
+        'P' => CornerPoint::Pillar,
+        'C' => CornerPoint::Column,
+        'S' => CornerPoint::Statue,
+        'B' => CornerPoint::Brazier,
+        _ => return Err(RpgError::Parse(line, format!("unknown feature '{}'", point))),
+    });
+
+    Ok(cell)
+}
+
+pub(crate) fn decode_area(area: char, line: usize) -> Result<Area> {
+    match area {
+        '.' => Ok(Area::Nothing),
+        'E' => Ok(Area::Entrance),
+        'R' => Ok(Area::Room),
+        'C' => Ok(Area::Corridor),
+        '<' => Ok(Area::StairsUp),
+        '>' => Ok(Area::StairsDown),
+        _ => Err(RpgError::Parse(line, format!("unknown area '{}'", area))),
+    }
+}
+
+pub(crate) fn decode_wall(wall: char, line: usize) -> Result<Wall> {
+    match wall {
+        '.' => Ok(Wall::Nothing),
+        'W' => Ok(Wall::Wall),
+        'D' => Ok(Wall::Door),
+        'S' => Ok(Wall::SecretDoor),
+        _ => Err(RpgError::Parse(line, format!("unknown wall '{}'", wall))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::cell::Side;
+
+    #[test]
+    fn round_trip() {
+        let mut map = GridMap::new(7, 5);
+        map.place_room((1, 1), (4, 3)).unwrap();
+        map.place_entrance((2, 2)).unwrap();
+        map.get_cell_mut((5, 2)).set_area(Area::Corridor);
+        map.set_wall((1, 1), Side::North, Wall::Wall).unwrap();
+        map.set_wall((4, 2), Side::East, Wall::Door).unwrap();
+        map.set_wall((3, 3), Side::South, Wall::SecretDoor).unwrap();
+        map.set_corner((2, 2), CornerPoint::Pillar).unwrap();
+        map.set_corner((3, 2), CornerPoint::Brazier).unwrap();
+
+        let mut buffer = Vec::new();
+        map.write_to(&mut buffer).unwrap();
+        let loaded = GridMap::read_from(buffer.as_slice()).unwrap();
+
+        assert_eq!(map.get_limits(), loaded.get_limits());
+        for x in 0..7 {
+            for y in 0..5 {
+                assert_eq!(map.get_cell_ref((x, y)), loaded.get_cell_ref((x, y)));
+            }
+        }
+    }
+
+    #[test]
+    fn dungeon_round_trip() {
+        let mut dungeon = Dungeon::new();
+        for level in 0..3 {
+            let mut map = GridMap::new(6, 4);
+            map.place_room((level, 0), (level + 2, 2)).unwrap();
+            dungeon.add_level(map).unwrap();
+        }
+        dungeon[0].get_cell_mut((1, 1)).set_area(Area::StairsDown);
+        dungeon[1].get_cell_mut((1, 1)).set_area(Area::StairsUp);
+
+        let mut buffer = Vec::new();
+        dungeon.write_to(&mut buffer).unwrap();
+        let loaded = Dungeon::read_from(buffer.as_slice()).unwrap();
+
+        assert_eq!(dungeon, loaded);
+
+        // Editors often leave blank lines at the end of a file
+        buffer.extend_from_slice(b"\n\n");
+        assert_eq!(dungeon, Dungeon::read_from(buffer.as_slice()).unwrap());
+
+        // A single map can't be read from a file with several levels
+        assert!(matches!(
+            read_only_level(buffer.as_slice()),
+            Err(RpgError::Parse(7, _))
+        ));
+        let text = "rpgmap 1\n1 1\n....\n\n";
+        assert!(read_only_level(text.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn read_known_file() {
+        let text = "rpgmap 1\n3 2\n.... R... R...\n.... E... R...\n";
+        let map = GridMap::read_from(text.as_bytes()).unwrap();
+
+        assert_eq!((3, 2), map.get_limits());
+        assert_eq!(&Area::Nothing, map.get_cell_ref((0, 0)).area());
+        assert_eq!(&Area::Room, map.get_cell_ref((1, 0)).area());
+        assert_eq!(&Area::Entrance, map.get_cell_ref((1, 1)).area());
+    }
+
+    #[test]
+    fn reject_newer_version() {
+        let text = "rpgmap 99\n1 1\n....\n";
+        assert!(matches!(
+            GridMap::read_from(text.as_bytes()),
+            Err(RpgError::UnsupportedVersion(99))
+        ));
+    }
+
+    #[test]
+    fn reject_bad_header() {
+        let text = "rpgmap 0\n1 1\n....\n";
+        assert!(matches!(
+            GridMap::read_from(text.as_bytes()),
+            Err(RpgError::UnsupportedVersion(0))
+        ));
+
+        // A huge size is turned down before any cells are made
+        let text = "rpgmap 1\n99999999 99999999\n....\n";
+        assert!(matches!(
+            GridMap::read_from(text.as_bytes()),
+            Err(RpgError::Parse(2, _))
+        ));
+        let text = "rpgmap 1\n2 60000\n.... ....\n";
+        assert!(matches!(
+            GridMap::read_from(text.as_bytes()),
+            Err(RpgError::Parse(4, _))
+        ));
+
+        for text in ["rpgmap 1\n0 0\n", "rpgmap 1\n0 1\n\n", "rpgmap 1\n1 0\n"] {
+            assert!(matches!(
+                GridMap::read_from(text.as_bytes()),
+                Err(RpgError::Parse(2, _))
+            ));
+        }
+    }
+
+    #[test]
+    fn reject_short_row() {
+        let text = "rpgmap 1\n3 1\n.... ....\n";
+        assert!(matches!(
+            GridMap::read_from(text.as_bytes()),
+            Err(RpgError::Parse(3, _))
+        ));
+    }
+}
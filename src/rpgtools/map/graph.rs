@@ -0,0 +1,528 @@
+//! Graph of the rooms and corridors on a map and how they are joined
+//!
+//! Each node of a [`RoomGraph`] is a connected space of room cells or of corridor cells, and each
+//! edge joins two spaces that touch across an open edge, a door or a secret door. Walls split
+//! spaces and never make an edge. Landmarks, such as the entrance and stairs, count as room cells.
+use std::collections::{BTreeMap, VecDeque};
+
+use super::cell::{Side, Wall};
+use super::room::Room;
+use super::{Area, GridMap, Point};
+
+/// What kind of space a node is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NodeKind {
+    Room,
+    Corridor,
+}
+
+/// How two nodes are joined
+///
+/// When two nodes touch in several places the most open connection is kept, so `Open` wins over
+/// `Door`, which wins over `SecretDoor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Connection {
+    /// The cells meet without anything between them
+    Open,
+    Door,
+    /// A door that looks like a wall to the players
+    SecretDoor,
+}
+
+/// A room or corridor in a `RoomGraph`
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Node {
+    pub kind: NodeKind,
+    /// The cells that make up the space
+    pub room: Room,
+}
+
+/// A connection between two nodes, given by their indexes in the graph
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Edge {
+    pub nodes: (usize, usize),
+    pub connection: Connection,
+}
+
+/// The rooms and corridors of a map and how they are joined, made with `GridMap::room_graph`
+///
+/// Nodes are numbered in the order that their first cell appears on the map, scanning each
+/// column from the top down and the columns from left to right.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoomGraph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    /// The neighbours of every node, in increasing order
+    adjacent: Vec<Vec<usize>>,
+    /// The node of every open cell, indexed by [x][y]
+    cells: Vec<Vec<Option<usize>>>,
+    entrance: Option<usize>,
+}
+
+const SIDES: [Side; 4] = [Side::North, Side::East, Side::South, Side::West];
+
+/// The kind of node that a cell belongs to, if any
+fn node_kind(area: &Area) -> Option<NodeKind> {
+    match area {
+        Area::Nothing => None,
+        Area::Corridor => Some(NodeKind::Corridor),
+        _ => Some(NodeKind::Room),
+    }
+}
+
+impl RoomGraph {
+    /// All of the nodes
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// All of the edges, ordered by the nodes that they join
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// The nodes that share an edge with a node
+    pub fn neighbours(&self, node: usize) -> &[usize] {
+        &self.adjacent[node]
+    }
+
+    /// The node that a cell belongs to, or `None` if the cell is empty
+    pub fn node_at(&self, point: impl Into<Point>) -> Option<usize> {
+        let point = point.into();
+        let x = usize::try_from(point.x).ok()?;
+        let y = usize::try_from(point.y).ok()?;
+        *self.cells.get(x)?.get(y)?
+    }
+
+    /// The node that holds the entrance, if the map has one
+    pub fn entrance(&self) -> Option<usize> {
+        self.entrance
+    }
+
+    /// Groups of nodes that can be reached from each other
+    ///
+    /// Each group is in increasing order, and the groups are ordered by their first node.
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut components = vec![];
+
+        for start in 0..self.nodes.len() {
+            if seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut component = vec![start];
+            let mut queue = VecDeque::from([start]);
+            while let Some(node) = queue.pop_front() {
+                for &next in &self.adjacent[node] {
+                    if !seen[next] {
+                        seen[next] = true;
+                        component.push(next);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            component.sort_unstable();
+            components.push(component);
+        }
+
+        components
+    }
+
+    /// Whether every node can be reached from every other node
+    pub fn is_connected(&self) -> bool {
+        self.components().len() <= 1
+    }
+
+    /// Nodes that cut the graph in two if they are removed, also known as articulation points
+    ///
+    /// Everything beyond a chokepoint can only be reached through it, which makes chokepoints
+    /// good spots for guards and locked doors.
+    pub fn chokepoints(&self) -> Vec<usize> {
+        let mut search = Chokepoints {
+            order: vec![None; self.nodes.len()],
+            low: vec![0; self.nodes.len()],
+            cut: vec![false; self.nodes.len()],
+            time: 0,
+        };
+        for node in 0..self.nodes.len() {
+            if search.order[node].is_none() {
+                search.visit(self, node, None);
+            }
+        }
+
+        (0..self.nodes.len())
+            .filter(|node| search.cut[*node])
+            .collect()
+    }
+
+    /// One list of nodes for each independent loop in the graph
+    ///
+    /// Each loop is given in the order that the nodes are walked around it. Every loop in the
+    /// graph can be made by combining these ones, so a graph without any has no way to go
+    /// around in a circle.
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+        // Spanning tree of every component, found breadth first
+        let mut parent: Vec<Option<usize>> = vec![None; self.nodes.len()];
+        let mut depth: Vec<Option<usize>> = vec![None; self.nodes.len()];
+        for start in 0..self.nodes.len() {
+            if depth[start].is_some() {
+                continue;
+            }
+            depth[start] = Some(0);
+            let mut queue = VecDeque::from([start]);
+            while let Some(node) = queue.pop_front() {
+                for &next in &self.adjacent[node] {
+                    if depth[next].is_none() {
+                        depth[next] = depth[node].map(|depth| depth + 1);
+                        parent[next] = Some(node);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        // Every edge that isn't part of the tree closes one loop
+        let mut cycles = vec![];
+        for edge in &self.edges {
+            let (a, b) = edge.nodes;
+            if parent[a] == Some(b) || parent[b] == Some(a) {
+                continue;
+            }
+
+            let (mut up, mut down) = (vec![a], vec![b]);
+            let (mut x, mut y) = (a, b);
+            while x != y {
+                if depth[x] >= depth[y] {
+                    x = parent[x].expect("bug: loop has no common node");
+                    up.push(x);
+                } else {
+                    y = parent[y].expect("bug: loop has no common node");
+                    down.push(y);
+                }
+            }
+            // Both halves end at the node where they meet
+            down.pop();
+            up.extend(down.into_iter().rev());
+            cycles.push(up);
+        }
+
+        cycles
+    }
+
+    /// The number of edges between a node and every other node, or `None` if it can't be reached
+    pub fn distances_from(&self, node: usize) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.nodes.len()];
+        distances[node] = Some(0);
+        let mut queue = VecDeque::from([node]);
+        while let Some(node) = queue.pop_front() {
+            for &next in &self.adjacent[node] {
+                if distances[next].is_none() {
+                    distances[next] = distances[node].map(|distance| distance + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        distances
+    }
+
+    /// The number of edges between the entrance and every node, if the map has an entrance
+    pub fn distances_from_entrance(&self) -> Option<Vec<Option<usize>>> {
+        self.entrance.map(|entrance| self.distances_from(entrance))
+    }
+}
+
+/// State for the depth first search that finds chokepoints
+struct Chokepoints {
+    /// The order that each node was first visited in
+    order: Vec<Option<usize>>,
+    /// The earliest visited node that each node's subtree has an edge back to
+    low: Vec<usize>,
+    cut: Vec<bool>,
+    time: usize,
+}
+
+impl Chokepoints {
+    fn visit(&mut self, graph: &RoomGraph, node: usize, parent: Option<usize>) {
+        self.order[node] = Some(self.time);
+        self.low[node] = self.time;
+        self.time += 1;
+
+        let mut children = 0;
+        for &next in &graph.adjacent[node] {
+            if Some(next) == parent {
+                continue;
+            }
+            match self.order[next] {
+                Some(order) => self.low[node] = self.low[node].min(order),
+                None => {
+                    children += 1;
+                    self.visit(graph, next, Some(node));
+                    self.low[node] = self.low[node].min(self.low[next]);
+                    if parent.is_some() && Some(self.low[next]) >= self.order[node] {
+                        self.cut[node] = true;
+                    }
+                }
+            }
+        }
+
+        // The first node only cuts the graph if the search had to leave it more than once
+        if parent.is_none() && children > 1 {
+            self.cut[node] = true;
+        }
+    }
+}
+
+impl GridMap {
+    /// Build the graph of the rooms and corridors on the map
+    ///
+    /// See [`RoomGraph`] for how the map is split into nodes and edges.
+    pub fn room_graph(&self) -> RoomGraph {
+        let (xmax, ymax) = self.get_limits();
+        let points = (0..xmax as i64).flat_map(|x| (0..ymax as i64).map(move |y| Point::new(x, y)));
+        let open = |point: Point, side: Side| {
+            self.get_wall(point, side)
+                .is_ok_and(|wall| *wall == Wall::Nothing)
+        };
+
+        let mut nodes = vec![];
+        let mut cells = vec![vec![None; ymax]; xmax];
+        let node_of = |cells: &Vec<Vec<Option<usize>>>, point: Point| {
+            cells[point.x as usize][point.y as usize]
+        };
+        for start in points.clone() {
+            let Some(kind) = node_kind(self[start].area()) else {
+                continue;
+            };
+            if node_of(&cells, start).is_some() {
+                continue;
+            }
+
+            // Flood out across open edges to cells of the same kind
+            let index = nodes.len();
+            let mut room = Room::new();
+            cells[start.x as usize][start.y as usize] = Some(index);
+            let mut queue = VecDeque::from([start]);
+            while let Some(point) = queue.pop_front() {
+                room.add_cell(point).expect("failed to add cell");
+                for side in SIDES {
+                    let next = point + side.offset().into();
+                    if self.in_bounds(next)
+                        && node_of(&cells, next).is_none()
+                        && node_kind(self[next].area()) == Some(kind)
+                        && open(point, side)
+                    {
+                        cells[next.x as usize][next.y as usize] = Some(index);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            nodes.push(Node { kind, room });
+        }
+
+        // Each pair of neighbouring cells is checked once, from the west or north cell
+        let mut connections = BTreeMap::new();
+        let mut entrance = None;
+        for point in points {
+            let Some(a) = node_of(&cells, point) else {
+                continue;
+            };
+            if self[point].area() == &Area::Entrance {
+                entrance.get_or_insert(a);
+            }
+            for side in [Side::East, Side::South] {
+                let next = point + side.offset().into();
+                let Some(b) = self
+                    .in_bounds(next)
+                    .then(|| node_of(&cells, next))
+                    .flatten()
+                else {
+                    continue;
+                };
+                let connection = match self.get_wall(point, side) {
+                    Ok(Wall::Nothing) => Connection::Open,
+                    Ok(Wall::Door) => Connection::Door,
+                    Ok(Wall::SecretDoor) => Connection::SecretDoor,
+                    _ => continue,
+                };
+                if a != b {
+                    let best = connections
+                        .entry((a.min(b), a.max(b)))
+                        .or_insert(connection);
+                    *best = (*best).min(connection);
+                }
+            }
+        }
+
+        let mut adjacent = vec![vec![]; nodes.len()];
+        let edges: Vec<Edge> = connections
+            .into_iter()
+            .map(|(nodes, connection)| {
+                adjacent[nodes.0].push(nodes.1);
+                adjacent[nodes.1].push(nodes.0);
+                Edge { nodes, connection }
+            })
+            .collect();
+        for neighbours in adjacent.iter_mut() {
+            neighbours.sort_unstable();
+        }
+
+        RoomGraph {
+            nodes,
+            edges,
+            adjacent,
+            cells,
+            entrance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::map::{Connectivity, RouteMethod};
+
+    /// Two rooms joined by a corridor, with a door at the west end
+    fn two_rooms() -> GridMap {
+        let mut map = GridMap::new(20, 10);
+        map.place_room((1, 1), (5, 5)).unwrap();
+        map.place_room((12, 1), (16, 5)).unwrap();
+        for x in 6..12 {
+            map.get_cell_mut((x, 3)).set_area(Area::Corridor);
+        }
+        map.set_wall((6, 3), Side::West, Wall::Door).unwrap();
+        map.place_entrance((2, 2)).unwrap();
+        map
+    }
+
+    #[test]
+    fn rooms_and_corridors() {
+        let graph = two_rooms().room_graph();
+
+        let kinds: Vec<NodeKind> = graph.nodes().iter().map(|node| node.kind).collect();
+        assert_eq!(
+            vec![NodeKind::Room, NodeKind::Corridor, NodeKind::Room],
+            kinds
+        );
+        assert_eq!(6, graph.nodes()[1].room.iter_cells().count());
+        assert_eq!(
+            vec![
+                Edge {
+                    nodes: (0, 1),
+                    connection: Connection::Door
+                },
+                Edge {
+                    nodes: (1, 2),
+                    connection: Connection::Open
+                },
+            ],
+            graph.edges()
+        );
+        assert_eq!(Some(1), graph.node_at((8, 3)));
+        assert_eq!(None, graph.node_at((8, 4)));
+
+        assert!(graph.is_connected());
+        assert_eq!(vec![1], graph.chokepoints());
+        assert!(graph.cycles().is_empty());
+        assert_eq!(Some(0), graph.entrance());
+        assert_eq!(
+            Some(vec![Some(0), Some(1), Some(2)]),
+            graph.distances_from_entrance()
+        );
+    }
+
+    #[test]
+    fn walls_split_rooms() {
+        let mut map = two_rooms();
+        for x in 6..12 {
+            map.get_cell_mut((x, 3)).set_area(Area::Nothing);
+        }
+        for y in 1..=5 {
+            map.set_wall((3, y), Side::West, Wall::Wall).unwrap();
+        }
+        let graph = map.room_graph();
+
+        assert_eq!(3, graph.nodes().len());
+        assert!(graph.edges().is_empty());
+        assert_eq!(vec![vec![0], vec![1], vec![2]], graph.components());
+        assert_eq!(
+            Some(vec![Some(0), None, None]),
+            graph.distances_from_entrance()
+        );
+    }
+
+    #[test]
+    fn loops_are_found() {
+        // Four rooms in a ring, with a fifth hanging off of one of them
+        let mut map = GridMap::new(30, 30);
+        map.place_room((2, 2), (5, 5)).unwrap();
+        map.place_room((20, 2), (23, 5)).unwrap();
+        map.place_room((2, 20), (5, 23)).unwrap();
+        map.place_room((20, 20), (23, 23)).unwrap();
+        map.place_room((26, 26), (28, 28)).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        for (a, b) in [
+            ((5, 3), (20, 3)),
+            ((3, 5), (3, 20)),
+            ((22, 5), (22, 20)),
+            ((5, 22), (20, 22)),
+        ] {
+            map.place_hallway(a, b, RouteMethod::HorizontalFirst, 1, &mut rng)
+                .unwrap();
+        }
+        map.place_hallway(
+            (23, 23),
+            (26, 26),
+            RouteMethod::HorizontalFirst,
+            1,
+            &mut rng,
+        )
+        .unwrap();
+        let graph = map.room_graph();
+
+        let cycles = graph.cycles();
+        assert_eq!(1, cycles.len());
+        assert_eq!(8, cycles[0].len());
+        // Consecutive nodes around the loop are neighbours
+        for (i, node) in cycles[0].iter().enumerate() {
+            let next = cycles[0][(i + 1) % cycles[0].len()];
+            assert!(graph.neighbours(*node).contains(&next));
+        }
+
+        // Only the room and the corridor leading to the fifth room are chokepoints
+        let bottom_right = graph.node_at((21, 21)).unwrap();
+        let corridor = graph.node_at((24, 23)).unwrap();
+        assert_eq!(vec![bottom_right, corridor], graph.chokepoints());
+    }
+
+    #[test]
+    fn generated_dungeon_is_connected() {
+        let mut map = GridMap::new(40, 40);
+        map.generate_dungeon(
+            10,
+            6,
+            false,
+            RouteMethod::Manhattan,
+            1,
+            Connectivity::Nearby,
+            &mut StdRng::seed_from_u64(2),
+        );
+        let graph = map.room_graph();
+
+        assert!(graph.is_connected());
+        assert!(graph
+            .nodes()
+            .iter()
+            .any(|node| node.kind == NodeKind::Corridor));
+    }
+}
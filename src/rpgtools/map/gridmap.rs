@@ -1,23 +1,23 @@
 // std library
 use std::cmp;
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 use std::collections::VecDeque;
 use std::ops::Index;
 
 // Extern crates
 use rand::prelude::*;
-use rand::{thread_rng, Rng};
 
 use itertools::Itertools;
 
 // Local modules
-use super::cell::Cell;
+use super::cell::{Cell, Point as CornerPoint, Side, Wall};
 use super::room::Room;
 pub use super::point::Point;
 use super::area::Area;
 
 // Need RouteMethod from rpgmap::route
-use super::route::RouteMethod;
+use super::route::{self, RouteMethod};
+use super::connect::Connectivity;
 
 use crate::error::{Result, RpgError};
 
@@ -29,13 +29,40 @@ use crate::error::{Result, RpgError};
 /// // Make a map that's 25x25 cells in size.
 /// let map = GridMap::new(25, 25);
 /// ```
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "GridMapData"))]
 pub struct GridMap {
     xmax: usize,
     ymax: usize,
     cells: Vec<Vec<Cell>>,
 }
 
+/// A map as it is deserialized, before its size has been checked against its cells
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct GridMapData {
+    xmax: usize,
+    ymax: usize,
+    cells: Vec<Vec<Cell>>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<GridMapData> for GridMap {
+    type Error = String;
+
+    fn try_from(data: GridMapData) -> std::result::Result<Self, Self::Error> {
+        if data.cells.len() != data.xmax || data.cells.iter().any(|column| column.len() != data.ymax) {
+            return Err(format!("cells don't fill a {} by {} map", data.xmax, data.ymax));
+        }
+        Ok(GridMap {
+            xmax: data.xmax,
+            ymax: data.ymax,
+            cells: data.cells,
+        })
+    }
+}
+
 impl GridMap {
     /// Make a new GridMap
     /// # Examples
@@ -90,17 +117,24 @@ impl GridMap {
     }
 
     /// Similar to place entrance, however it starts with the coordinates and
-    /// finds the nearest spot that is already a "room". This allows entrances
-    /// to be placed in non-deterministic generators, such as caves.
-    pub fn place_entrance_near(&mut self, point: impl Into<Point>) -> Result<()> {
+    /// finds the nearest spot that is already a "room" or corridor (and not a
+    /// landmark, such as stairs). This allows entrances
+    /// to be placed in non-deterministic generators, such as caves. If several
+    /// spots are equally near then one is picked using `rng`. Returns
+    /// `RpgError::Empty` if the map has no such spot.
+    pub fn place_entrance_near(&mut self, point: impl Into<Point>, rng: &mut impl Rng) -> Result<()> {
         let point = point.into();
         if !point.is_in_bounds(Point::new(0, 0), (self.xmax, self.ymax).try_into()?) {
             return Err(RpgError::OutOfBounds);
         }
 
         let point = self
-            .find_by(point, &|cell: &Cell| -> bool { cell.is_room() })?
-            .unwrap();
+            .find_by(
+                point,
+                &|cell: &Cell| -> bool { matches!(cell.area, Area::Room | Area::Corridor) },
+                rng,
+            )?
+            .ok_or_else(|| RpgError::Empty("no room or corridor for the entrance".to_string()))?;
 
         self.place_entrance(point)?;
         Ok(())
@@ -124,7 +158,7 @@ impl GridMap {
 
         for i in x_lower..x_upper + 1 {
             for j in y_lower..y_upper + 1 {
-                if self.cells[i][j].area != Area::Entrance {
+                if !self.cells[i][j].area.is_landmark() {
                     self.cells[i][j].area = Area::Room;
                 }
             }
@@ -156,54 +190,167 @@ impl GridMap {
     }
 
     /// Place a hallway between two points
+    ///
+    /// The hallway is `width` cells wide and is made of `Area::Corridor` cells. Routes that involve
+    /// a random choice, such as `RouteMethod::Manhattan`, draw from `rng`. `RouteMethod::Pathfind`
+    /// returns an error if walls cut the two points off from each other.
     pub fn place_hallway(
         &mut self,
         point0: impl Into<Point>,
         point1: impl Into<Point>,
         route: RouteMethod,
+        width: usize,
+        rng: &mut impl Rng,
     ) -> Result<()> {
-        let (x0, y0) = point0.into().into();
-        let (x1, y1) = point1.into().into();
-
-        let route_selected = match route {
-            RouteMethod::HorizontalFirst => RouteMethod::HorizontalFirst,
-            RouteMethod::VerticalFirst => RouteMethod::VerticalFirst,
-            RouteMethod::Manhattan => {
-                if rand::random::<bool>() {
-                    RouteMethod::HorizontalFirst
-                } else {
-                    RouteMethod::VerticalFirst
+        let path = self.route_hallway(point0, point1, route, rng)?;
+        self.carve_path(&path, width);
+
+        Ok(())
+    }
+
+    /// Turn every empty cell along a path into a corridor
+    ///
+    /// Rooms, corridors and landmarks such as the entrance that the path crosses are left alone.
+    /// Paths wider than one cell are centred on the path, as far as the edges of the map allow.
+    pub(crate) fn carve_path(&mut self, path: &[Point], width: usize) {
+        let width = width.max(1) as i64;
+        let (low, high) = (-(width - 1) / 2, width / 2);
+
+        for point in path {
+            for dx in low..=high {
+                for dy in low..=high {
+                    let point = Point::new(point.x + dx, point.y + dy);
+                    if !self.in_bounds(point) {
+                        continue;
+                    }
+                    let cell = self.get_cell_mut(point);
+                    if cell.area == Area::Nothing {
+                        cell.area = Area::Corridor;
+                    }
                 }
             }
+        }
+    }
+
+    /// Work out the cells of a hallway between two points
+    ///
+    /// The cells are returned in order, starting at `point0` and ending at `point1`, so that
+    /// consecutive cells are always neighbours.
+    pub(crate) fn route_hallway(
+        &self,
+        point0: impl Into<Point>,
+        point1: impl Into<Point>,
+        route: RouteMethod,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<Point>> {
+        let start = point0.into();
+        let end = point1.into();
+        if !self.in_bounds(start) || !self.in_bounds(end) {
+            return Err(RpgError::OutOfBounds);
+        }
+
+        let path = match route {
+            RouteMethod::HorizontalFirst => route::manhattan_path(start, end, true),
+            RouteMethod::VerticalFirst => route::manhattan_path(start, end, false),
+            RouteMethod::Manhattan => route::manhattan_path(start, end, rng.gen()),
+            RouteMethod::Direct => route::direct_path(start, end),
+            RouteMethod::Subway => route::subway_path(start, end, rng.gen()),
+            RouteMethod::Pathfind(costs) => self.find_path(start, end, &costs)?,
         };
 
-        match route_selected {
-            RouteMethod::HorizontalFirst => {
-                self.place_room((x0, y0), (x1, y0))?;
-                self.place_room((x1, y0), (x1, y1))?;
-            }
-            RouteMethod::VerticalFirst => {
-                self.place_room((x0, y0), (x0, y1))?;
-                self.place_room((x0, y1), (x1, y1))?;
+        Ok(path)
+    }
+
+    /// Place doors where a path crosses from inside a room to outside of it
+    ///
+    /// `rooms` marks which cells were rooms before the path was carved. Every time two
+    /// consecutive cells of the path are on different sides of that boundary a door is placed on
+    /// the edge between them.
+    pub(crate) fn place_doors_along(&mut self, path: &[Point], rooms: &[Vec<bool>]) -> Result<()> {
+        for (from, to) in path.iter().tuple_windows() {
+            let (fx, fy): (usize, usize) = (*from).try_into()?;
+            let (tx, ty): (usize, usize) = (*to).try_into()?;
+            if rooms[fx][fy] == rooms[tx][ty] {
+                continue;
             }
-            _ => panic!("Found unsupported route!"),
+
+            let Some(side) = Side::from_offset((to.x - from.x, to.y - from.y)) else {
+                continue;
+            };
+            self.set_wall(*from, side, Wall::Door)?;
+        }
+
+        Ok(())
+    }
+
+    /// Find the cell and the slot that store the wall on one side of a cell
+    ///
+    /// Returns the coordinates of the owning cell and whether the wall is the vertical one.
+    fn wall_owner(&self, point: impl Into<Point>, side: Side) -> Result<((usize, usize), bool)> {
+        let point = point.into();
+        let owner = match side {
+            Side::North | Side::West => point,
+            Side::East | Side::South => point + side.offset().into(),
         };
 
+        let (x, y): (usize, usize) = owner.try_into()?;
+        if x >= self.xmax || y >= self.ymax {
+            return Err(RpgError::OutOfBounds);
+        }
+        let (px, py): (usize, usize) = point.try_into()?;
+        if px >= self.xmax || py >= self.ymax {
+            return Err(RpgError::OutOfBounds);
+        }
+
+        Ok(((x, y), matches!(side, Side::East | Side::West)))
+    }
+
+    /// Get the wall on one side of a cell
+    ///
+    /// Walls are shared between neighbouring cells, so the east side of one cell is the same
+    /// wall as the west side of the cell to its east. The east and south sides of the cells on
+    /// the far edges of the map have no storage and are reported as out of bounds.
+    pub fn get_wall(&self, point: impl Into<Point>, side: Side) -> Result<&Wall> {
+        let ((x, y), vertical) = self.wall_owner(point, side)?;
+        let cell = &self.cells[x][y];
+        Ok(if vertical { cell.vert_wall() } else { cell.horiz_wall() })
+    }
+
+    /// Set the wall on one side of a cell
+    ///
+    /// See `get_wall` for how walls are shared between cells.
+    pub fn set_wall(&mut self, point: impl Into<Point>, side: Side, wall: Wall) -> Result<()> {
+        let ((x, y), vertical) = self.wall_owner(point, side)?;
+        let cell = &mut self.cells[x][y];
+        if vertical {
+            cell.set_vert_wall(wall);
+        } else {
+            cell.set_horiz_wall(wall);
+        }
         Ok(())
     }
 
     /// Find the nearest connected cell to the cell specified
-    fn find_nearest_connected(&self, point: impl Into<Point>) -> Result<Option<Point>> {
+    pub(crate) fn find_nearest_connected(
+        &self,
+        point: impl Into<Point>,
+        rng: &mut impl Rng,
+    ) -> Result<Option<Point>> {
         let (x, y) = point.into().into();
-        self.find_by((x, y), &|cell: &Cell| -> bool { cell.is_room() })
+        self.find_by((x, y), &|cell: &Cell| -> bool { cell.is_room() }, rng)
     }
 
     /// Find a cell with an arbitrary condition. This function takes a starting
     /// point and searches for nearby cells that satisfy condition 'cond'. The
     /// condition is passed in in the form of a function that takes a gridcell
     /// and outputs a result containing a boolean stating whether the match has
-    /// been made or not.
-    fn find_by<F>(&self, point: impl Into<Point>, cond: &F) -> Result<Option<Point>>
+    /// been made or not. Ties between equally near cells are broken using `rng`.
+    fn find_by<F>(
+        &self,
+        point: impl Into<Point>,
+        cond: &F,
+        rng: &mut impl Rng,
+    ) -> Result<Option<Point>>
     where
         F: Fn(&Cell) -> bool,
     {
@@ -251,19 +398,16 @@ impl GridMap {
             }
         }
 
-        // Now pick a random room
-        let mut rng = thread_rng();
-        // If we found a room then we need to make a copy of the value
-        // that's found there. x.choose() returns a reference and not
-        // the value itself.
-        Ok(rooms.choose(&mut rng).copied())
+        // Now pick a random room. If we found a room then we need to make a
+        // copy of the value that's found there. x.choose() returns a reference
+        // and not the value itself.
+        Ok(rooms.choose(rng).copied())
     }
 
     /// Generate random cells with a biasing towards more/less rooms. Limit is a value
     /// between 1 and 100. This limit sets the chance that the cells are a room.
     /// Higher limit means fewer rooms.
-    pub fn generate_random_cells(&mut self, limit: i64) {
-        let mut rng = thread_rng();
+    pub fn generate_random_cells(&mut self, limit: i64, rng: &mut impl Rng) {
         for i in 0..self.xmax {
             for j in 0..self.ymax {
                 let val = rng.gen_range(1..100);
@@ -276,9 +420,9 @@ impl GridMap {
         }
     }
 
-    pub fn generate_annealed_random_cells(&mut self) {
+    pub fn generate_annealed_random_cells(&mut self, rng: &mut impl Rng) {
         // Start by generating a random grid
-        self.generate_random_cells(80);
+        self.generate_random_cells(80, rng);
 
         // Anneal by removing stragglers
         for i in 1..self.xmax {
@@ -295,10 +439,9 @@ impl GridMap {
     }
 
     /// Place a randomly sized room of up to scale length or width.
-    pub fn place_random_room(&mut self, scale: usize, connect: bool) {
-        // Initialize a random number generator
-        let mut rng = rand::thread_rng();
-
+    ///
+    /// Returns the two opposite corners of the room that was placed.
+    pub fn place_random_room(&mut self, scale: usize, connect: bool, rng: &mut impl Rng) -> (Point, Point) {
         // Generate size of the room
         let width = rng.gen_range(2..scale);
         let height = rng.gen_range(2..scale);
@@ -313,12 +456,12 @@ impl GridMap {
             // Find the nearest connected location and return
             // the coordinates.
             let p1 = self
-                .find_nearest_connected(point0)
+                .find_nearest_connected(point0, rng)
                 .expect("no existing rooms to connect").unwrap();
             // Drow the hallway; some of this will be overwritten by
             // the room placement below.
             let p0: Point = (x0, y0).try_into().unwrap();
-            self.place_hallway(p0, p1, RouteMethod::Manhattan).expect("bug: could not place hallway");
+            self.place_hallway(p0, p1, RouteMethod::Manhattan, 1, rng).expect("bug: could not place hallway");
         }
 
         // Set x/y min/max while checking for overflows on either
@@ -331,13 +474,137 @@ impl GridMap {
         let min: Point = (xmin, ymin).try_into().unwrap();
         let max: Point = (xmax, ymax).try_into().unwrap();
         self.place_room(min, max).expect("bug: could not place room");
+        (min, max)
     }
 
-    pub fn generate_dungeon(&mut self, num_rooms: usize, room_size: usize) {
+    /// Get the feature on the corner at a grid intersection
+    ///
+    /// Intersection (x, y) is the north-west corner of cell (x, y), so the intersections along
+    /// the far east and south edges of the map are out of bounds.
+    pub fn get_corner(&self, point: impl Into<Point>) -> Result<&CornerPoint> {
+        let (x, y): (usize, usize) = point.into().try_into()?;
+        if x >= self.xmax || y >= self.ymax {
+            return Err(RpgError::OutOfBounds);
+        }
+        Ok(self.cells[x][y].point())
+    }
+
+    /// Place a feature, such as a pillar, on the corner at a grid intersection
+    ///
+    /// See `get_corner` for which intersections can hold features.
+    pub fn set_corner(&mut self, point: impl Into<Point>, feature: CornerPoint) -> Result<()> {
+        let (x, y): (usize, usize) = point.into().try_into()?;
+        if x >= self.xmax || y >= self.ymax {
+            return Err(RpgError::OutOfBounds);
+        }
+        self.cells[x][y].set_point(feature);
+        Ok(())
+    }
+
+    /// Line a rectangular room with two rows of pillars
+    ///
+    /// The room is given by two opposite corner cells, like `place_room`. The rows run along the
+    /// long side of the room, one cell in from the walls, with a pillar on every second
+    /// intersection. Pillars are only placed where all four surrounding cells are rooms and
+    /// rooms that are less than 5 cells across get no pillars at all.
+    pub fn place_pillar_rows(&mut self, point0: impl Into<Point>, point1: impl Into<Point>) -> Result<()> {
+        let (point0, point1) = (point0.into(), point1.into());
+        let min = Point::new(cmp::min(point0.x, point1.x), cmp::min(point0.y, point1.y));
+        let max = Point::new(cmp::max(point0.x, point1.x), cmp::max(point0.y, point1.y));
+        let (width, height) = (max.x - min.x + 1, max.y - min.y + 1);
+        if width < 5 || height < 5 {
+            return Ok(());
+        }
+
+        // Intersections one cell in from the walls. Intersection (x, y) is
+        // the north-west corner of cell (x, y) so the far rows sit on the
+        // last row/column of cells.
+        let rows = if width >= height {
+            (min.x + 1..=max.x)
+                .step_by(2)
+                .flat_map(|x| [Point::new(x, min.y + 1), Point::new(x, max.y)])
+                .collect::<Vec<_>>()
+        } else {
+            (min.y + 1..=max.y)
+                .step_by(2)
+                .flat_map(|y| [Point::new(min.x + 1, y), Point::new(max.x, y)])
+                .collect::<Vec<_>>()
+        };
+
+        for point in rows {
+            let surrounded = [(-1, -1), (0, -1), (-1, 0), (0, 0)].iter().all(|offset| {
+                let cell = point + (*offset).into();
+                self.in_bounds(cell) && self[cell].is_room()
+            });
+            if surrounded {
+                self.set_corner(point, CornerPoint::Pillar)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Mark which cells are currently rooms, rather than corridors, indexed by [x][y]
+    pub(crate) fn room_mask(&self) -> Vec<Vec<bool>> {
+        self.cells
+            .iter()
+            .map(|column| {
+                column
+                    .iter()
+                    .map(|cell| cell.is_room() && cell.area != Area::Corridor)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Whether a point is inside of the map
+    pub(crate) fn in_bounds(&self, point: Point) -> bool {
+        point.x >= 0 && point.y >= 0 && (point.x as usize) < self.xmax && (point.y as usize) < self.ymax
+    }
+
+    /// Generate a dungeon of randomly placed rooms joined by hallways
+    ///
+    /// This method will replace the existing map. The same `rng` state always produces the same
+    /// dungeon. Rooms are joined by hallways that follow `route` and are `width` cells wide, and
+    /// `connectivity` picks which rooms are joined. Doors are placed where single-width hallways
+    /// enter the rooms. If `pillars` is set then large rooms are lined with rows of pillars.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_dungeon(
+        &mut self,
+        num_rooms: usize,
+        room_size: usize,
+        pillars: bool,
+        route: RouteMethod,
+        width: usize,
+        connectivity: Connectivity,
+        rng: &mut impl Rng,
+    ) {
         self.clear();
 
         for _ in 0..num_rooms {
-            self.place_random_room(room_size, false);
+            let (min, max) = self.place_random_room(room_size, false, rng);
+            if pillars {
+                self.place_pillar_rows(min, max)
+                    .expect("bug: could not place pillars");
+            }
+        }
+
+        // Remember where the rooms are so that we can tell where the hallways
+        // meet them.
+        let room_cells = self.room_mask();
+
+        if let Connectivity::SpanningTree(loops) = connectivity {
+            let paths = self
+                .join_rooms(route, width, loops, rng)
+                .expect("bug: could not join rooms");
+            // Doors only fit across single-width hallways
+            if width <= 1 {
+                for path in paths {
+                    self.place_doors_along(&path, &room_cells)
+                        .expect("bug: could not place doors");
+                }
+            }
+            return;
         }
 
         let mut rooms = self.partition_rooms();
@@ -357,7 +624,15 @@ impl GridMap {
                     .expect("finding nearest cells failed");
 
                 if cell0.distance2(&cell1) < distance {
-                    self.place_hallway(cell0, cell1, RouteMethod::Manhattan).expect("bug: could not place hallway");
+                    let path = self
+                        .route_hallway(cell0, cell1, route, rng)
+                        .expect("bug: could not route hallway");
+                    self.carve_path(&path, width);
+                    // Doors only fit across single-width hallways
+                    if width <= 1 {
+                        self.place_doors_along(&path, &room_cells)
+                            .expect("bug: could not place doors");
+                    }
                 }
             }
 
@@ -510,10 +785,22 @@ impl GridMap {
 
     /// Generate a cave-like map
     ///
-    /// This method will replace the existing map.
-    pub fn generate_cave(&mut self, iter: i64, seed_limit: i64) {
+    /// This method will replace the existing map. The same `rng` state always produces the same
+    /// cave. Caves are joined by hallways that follow `route` and are `width` cells wide. With
+    /// `Connectivity::Nearby` only caves that are close together are joined.
+    pub fn generate_cave(
+        &mut self,
+        iter: i64,
+        seed_limit: i64,
+        route: RouteMethod,
+        width: usize,
+        connectivity: Connectivity,
+        rng: &mut impl Rng,
+    ) {
+        self.clear();
+
         // Makes a random selection of cells
-        self.generate_random_cells(seed_limit);
+        self.generate_random_cells(seed_limit, rng);
 
         // Anneal the cells into blobs
         for _ in 0..iter {
@@ -523,6 +810,12 @@ impl GridMap {
         // Get rid of small caves; reduces visual noise
         self.remove_orphans();
 
+        if let Connectivity::SpanningTree(loops) = connectivity {
+            self.connect_rooms(route, width, loops, rng)
+                .expect("bug: could not join caves");
+            return;
+        }
+
         // Connect caves together
         let caves = self.partition_rooms();
 
@@ -536,26 +829,28 @@ impl GridMap {
                 .expect("finding nearest cells failed");
 
             if cell1.distance2(&cell2) < 36 {
-                self.place_hallway(cell1, cell2, RouteMethod::Manhattan).expect("bug: could not place hallway");
+                self.place_hallway(cell1, cell2, route, width, rng)
+                    .expect("bug: could not place hallway");
             }
         }
     }
 
-    fn partition_rooms(&self) -> Vec<Room> {
+    pub(crate) fn partition_rooms(&self) -> Vec<Room> {
         self.partition_spaces(false)
     }
 
     /// Partition the map into groups of cells, called Rooms.
     ///
-    /// The 'rooms' are just collections of cells of the same type, so there is
-    /// at least one room that contains the walls/Nothing cells. These rooms
-    /// can then be used for path processing or connectivity testing.
+    /// The 'rooms' are just collections of connected open cells, such as rooms
+    /// and the corridors between them, or of connected Nothing cells. These
+    /// rooms can then be used for path processing or connectivity testing.
     fn partition_spaces(&self, include_nothing: bool) -> Vec<Room> {
         let mut out = Vec::new();
 
         // Make an set of the unvisited cells. Use this for finding new
-        // locations
-        let mut unvisited = HashSet::<(usize, usize)>::with_capacity(self.xmax * self.ymax);
+        // locations. The set is ordered so that rooms are always found in
+        // the same order, which keeps seeded generation reproducible.
+        let mut unvisited = BTreeSet::<(usize, usize)>::new();
         for i in 0..self.xmax {
             for j in 0..self.ymax {
                 unvisited.insert((i, j));
@@ -565,12 +860,11 @@ impl GridMap {
         // Now keep looping until we've covered ever cell in the map and found
         // all of the rooms
         while !unvisited.is_empty() {
-            // Each time, we start with an index; we don't really care what
-            // it is so the unordered hashset works well here.
+            // Each time, we start with the lowest unvisited index.
             let first_index = unvisited.iter().next().unwrap();
             let mut x = first_index.0;
             let mut y = first_index.1;
-            let this_area_type = &self.cells[x][y].area;
+            let this_is_open = self.cells[x][y].is_room();
 
             // This is going to be a 'room' (which includes contiguous AreaType::Nothing
             // spaces). Make a new one here that we're going to build up.
@@ -605,7 +899,7 @@ impl GridMap {
                     continue;
                 }
 
-                if self.cells[x][y].area != *this_area_type {
+                if self.cells[x][y].is_room() != this_is_open {
                     // Check that the cell is the correct type. If it is, then continue
                     // with processing it, otherwise don't remove it from the unvisited
                     // list (since we still might need to visit it).
@@ -613,7 +907,7 @@ impl GridMap {
                 }
 
                 if !unvisited.remove(&index) {
-                    // HashSet.remove() returns a bool that's true if the value
+                    // BTreeSet.remove() returns a bool that's true if the value
                     // was in the set. In this case that tells us if we've been
                     // here before. If we have, then don't do any further processing.
                     continue;
@@ -632,7 +926,7 @@ impl GridMap {
             }
             // The room is now complete; add it to our output vector and forget
             // about this particular room.
-            if *this_area_type != Area::Nothing || include_nothing {
+            if this_is_open || include_nothing {
                 out.push(room);
             }
         }
@@ -642,10 +936,10 @@ impl GridMap {
     }
 
     /// Delete everything in this map and reset to nothing
-    fn clear(&mut self) {
+    pub(crate) fn clear(&mut self) {
         for x in 0..self.xmax {
             for y in 0..self.ymax {
-                self.cells[x][y].area = Area::Nothing;
+                self.cells[x][y] = Cell::new();
             }
         }
     }
@@ -665,14 +959,236 @@ mod tests {
 
     use super::*;
 
+    use rand::rngs::StdRng;
+
+    use crate::map::PathCosts;
+
     /// Ensure that regenerating halls multiple times doesn't hang
     #[test]
     fn regenerate_dungeon() {
         let mut map = GridMap::new(25, 25);
+        let mut rng = thread_rng();
 
         // This used to fail due to an infinite loop in the halls algorithm.
         for _ in 0..10 {
-            map.generate_dungeon(10, 10);
+            map.generate_dungeon(10, 10, false, RouteMethod::Manhattan, 1, Connectivity::Nearby, &mut rng);
+        }
+    }
+
+    /// The same seed must always produce the same dungeon
+    #[test]
+    fn seeded_dungeon_is_reproducible() {
+        let mut map0 = GridMap::new(40, 40);
+        let mut map1 = GridMap::new(40, 40);
+
+        for seed in 0..5 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            map0.generate_dungeon(15, 6, true, RouteMethod::Manhattan, 1, Connectivity::Nearby, &mut rng);
+            map0.place_entrance_near((20, 20), &mut rng).unwrap();
+
+            let mut rng = StdRng::seed_from_u64(seed);
+            map1.generate_dungeon(15, 6, true, RouteMethod::Manhattan, 1, Connectivity::Nearby, &mut rng);
+            map1.place_entrance_near((20, 20), &mut rng).unwrap();
+
+            assert_eq!(map0, map1);
         }
     }
+
+    /// A map without any open cells has nowhere for the entrance
+    #[test]
+    fn no_entrance_on_empty_maps() {
+        let mut map = GridMap::new(3, 3);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(matches!(
+            map.place_entrance_near((1, 1), &mut rng),
+            Err(RpgError::Empty(_))
+        ));
+    }
+
+    /// Pathfound hallways join every room of a dungeon, and still work in caves
+    #[test]
+    fn pathfind_dungeon_is_connected() {
+        let route = RouteMethod::Pathfind(PathCosts::default());
+        for seed in 0..5 {
+            let mut map = GridMap::new(40, 40);
+            let mut rng = StdRng::seed_from_u64(seed);
+            map.generate_dungeon(12, 6, false, route, 1, Connectivity::Nearby, &mut rng);
+            assert_eq!(1, map.partition_rooms().len());
+
+            map.generate_cave(4, 50, route, 1, Connectivity::Nearby, &mut rng);
+            assert!(!map.partition_rooms().is_empty());
+        }
+    }
+
+    /// Spanning tree hallways always reach every room and cave
+    #[test]
+    fn spanning_tree_is_connected() {
+        let mut map = GridMap::new(50, 50);
+        for seed in 0..5 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let connectivity = Connectivity::SpanningTree(0.2);
+            map.generate_dungeon(20, 6, false, RouteMethod::Manhattan, 1, connectivity, &mut rng);
+            map.check_connected().unwrap();
+
+            map.generate_cave(4, 45, RouteMethod::Manhattan, 2, connectivity, &mut rng);
+            map.check_connected().unwrap();
+        }
+    }
+
+    /// The same seed must always produce the same cave
+    #[test]
+    fn seeded_cave_is_reproducible() {
+        let mut map0 = GridMap::new(40, 40);
+        let mut map1 = GridMap::new(40, 40);
+
+        for seed in 0..5 {
+            let (route, connectivity) = (RouteMethod::Manhattan, Connectivity::Nearby);
+            map0.generate_cave(4, 50, route, 1, connectivity, &mut StdRng::seed_from_u64(seed));
+            map1.generate_cave(4, 50, route, 1, connectivity, &mut StdRng::seed_from_u64(seed));
+
+            assert_eq!(map0, map1);
+        }
+    }
+
+    #[test]
+    fn walls_are_shared_between_cells() {
+        let mut map = GridMap::new(5, 5);
+        map.set_wall((2, 2), Side::East, Wall::Door).unwrap();
+        map.set_wall((2, 2), Side::North, Wall::Wall).unwrap();
+
+        assert_eq!(&Wall::Door, map.get_wall((3, 2), Side::West).unwrap());
+        assert_eq!(&Wall::Wall, map.get_wall((2, 1), Side::South).unwrap());
+        assert_eq!(&Wall::Nothing, map.get_wall((2, 2), Side::South).unwrap());
+        assert!(map.get_wall((4, 2), Side::East).is_err());
+        assert!(map.get_wall((2, 4), Side::South).is_err());
+    }
+
+    #[test]
+    fn no_hallways_on_empty_maps() {
+        let map = GridMap::new(0, 5);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(matches!(
+            map.route_hallway((0, 0), (0, 2), RouteMethod::Manhattan, &mut rng),
+            Err(RpgError::OutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn pillar_rows() {
+        let mut map = GridMap::new(12, 12);
+        map.place_room((1, 1), (8, 5)).unwrap();
+        map.place_pillar_rows((1, 1), (8, 5)).unwrap();
+
+        // Rows run along the long (x) side, one cell in from the walls
+        for x in [2, 4, 6, 8] {
+            assert_eq!(&CornerPoint::Pillar, map.get_corner((x, 2)).unwrap());
+            assert_eq!(&CornerPoint::Pillar, map.get_corner((x, 5)).unwrap());
+        }
+        assert_eq!(&CornerPoint::Nothing, map.get_corner((3, 2)).unwrap());
+        assert_eq!(&CornerPoint::Nothing, map.get_corner((1, 1)).unwrap());
+
+        // Small rooms don't get pillars
+        let mut map = GridMap::new(12, 12);
+        map.place_room((1, 1), (4, 8)).unwrap();
+        map.place_pillar_rows((1, 1), (4, 8)).unwrap();
+        for x in 0..12 {
+            for y in 0..12 {
+                assert_eq!(&CornerPoint::Nothing, map.get_corner((x, y)).unwrap());
+            }
+        }
+    }
+
+    /// Every door placed by the dungeon generator must be between two room cells
+    #[test]
+    fn dungeon_doors_join_rooms() {
+        let mut map = GridMap::new(40, 40);
+        let mut doors = 0;
+
+        for seed in 0..5 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            map.generate_dungeon(10, 6, false, RouteMethod::Manhattan, 1, Connectivity::Nearby, &mut rng);
+            for x in 1..40 {
+                for y in 1..40 {
+                    for side in [Side::North, Side::West] {
+                        if map.get_wall((x, y), side).unwrap() == &Wall::Door {
+                            let (dx, dy) = side.offset();
+                            assert!(map.get_cell_ref((x, y)).is_room());
+                            assert!(map.get_cell_ref((x + dx, y + dy)).is_room());
+                            doors += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        assert!(doors > 0);
+    }
+
+    /// Wide hallways are corridors that still join every room, without any doors across them
+    #[test]
+    fn wide_dungeon_hallways() {
+        let mut map = GridMap::new(40, 40);
+        map.generate_dungeon(
+            10,
+            6,
+            false,
+            RouteMethod::Manhattan,
+            3,
+            Connectivity::Nearby,
+            &mut StdRng::seed_from_u64(4),
+        );
+        assert_eq!(1, map.partition_rooms().len());
+
+        let mut corridors = 0;
+        for x in 0..40 {
+            for y in 0..40 {
+                if map.get_cell_ref((x, y)).area == Area::Corridor {
+                    corridors += 1;
+                }
+                for side in [Side::North, Side::West] {
+                    assert_ne!(&Wall::Door, map.get_wall((x, y), side).unwrap());
+                }
+            }
+        }
+        assert!(corridors > 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut map = GridMap::new(25, 25);
+        let mut rng = StdRng::seed_from_u64(0);
+        map.generate_dungeon(10, 5, true, RouteMethod::Manhattan, 1, Connectivity::Nearby, &mut rng);
+        map.place_entrance_near((12, 12), &mut rng).unwrap();
+
+        let json = serde_json::to_string(&map).unwrap();
+        let loaded: GridMap = serde_json::from_str(&json).unwrap();
+        assert_eq!(map, loaded);
+
+        for route in [
+            RouteMethod::HorizontalFirst,
+            RouteMethod::VerticalFirst,
+            RouteMethod::Manhattan,
+            RouteMethod::Direct,
+            RouteMethod::Subway,
+            RouteMethod::Pathfind(PathCosts::default()),
+            RouteMethod::Pathfind(PathCosts {
+                dig: 7,
+                corridor: 2,
+                room: 30,
+                beside_wall: 0,
+            }),
+        ] {
+            let json = serde_json::to_string(&route).unwrap();
+            assert_eq!(route, serde_json::from_str(&json).unwrap());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_checks_size() {
+        let json = serde_json::to_string(&GridMap::new(3, 2)).unwrap();
+        let json = json.replace("\"ymax\":2", "\"ymax\":5");
+        assert!(serde_json::from_str::<GridMap>(&json).is_err());
+    }
 }
@@ -0,0 +1,284 @@
+//! Labels: room numbers and other text written on a map
+//!
+//! Labels are drawn in Hack, a font that is built into the crate (see `fonts/Hack-Regular.txt`
+//! for its licence), so that maps look the same on every computer. The raster renderer fills in
+//! the glyphs and the SVG renderer writes them as paths, so the SVG doesn't need the font either.
+use std::fmt::Write;
+
+use ab_glyph::{point, Font, FontRef, Glyph, OutlineCurve, ScaleFont};
+
+use super::graph::NodeKind;
+use super::{GridMap, Point};
+
+/// The font that labels are written in
+const FONT: &[u8] = include_bytes!("fonts/Hack-Regular.ttf");
+
+/// The height of a label's text, as a share of a cell
+pub(super) const LABEL_HEIGHT: f32 = 0.6;
+
+/// The order that rooms are numbered in
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RoomOrder {
+    /// Nearest to the entrance first, counting the rooms and corridors on the way. Rooms that
+    /// can't be reached from the entrance come last.
+    #[default]
+    Distance,
+    /// Row by row from the top left, like reading a page
+    Reading,
+}
+
+/// A line of text written over the middle of a cell
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub text: String,
+    /// The cell that the text is centred on
+    pub at: Point,
+    /// Whether only the GM sees the label
+    pub gm_only: bool,
+}
+
+impl Label {
+    /// A label that everyone sees
+    pub fn new(text: impl Into<String>, at: impl Into<Point>) -> Label {
+        Label {
+            text: text.into(),
+            at: at.into(),
+            gm_only: false,
+        }
+    }
+
+    /// A note that is only drawn in the GM's view of the map
+    pub fn note(text: impl Into<String>, at: impl Into<Point>) -> Label {
+        Label {
+            gm_only: true,
+            ..Label::new(text, at)
+        }
+    }
+}
+
+impl GridMap {
+    /// Number the rooms of the map, 1 and up, for a key to refer to
+    ///
+    /// The rooms are the room spaces of [`GridMap::room_graph`], so corridors aren't numbered and
+    /// rooms split by walls get a number each. Each number is put on the cell of its room nearest
+    /// to the room's middle. The numbers are notes, for the GM's copy of the map.
+    pub fn number_rooms(&self, order: RoomOrder) -> Vec<Label> {
+        let graph = self.room_graph();
+        let distances = graph.distances_from_entrance();
+
+        let mut rooms: Vec<(usize, Point)> = graph
+            .nodes()
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.kind == NodeKind::Room)
+            .map(|(index, node)| {
+                let cells: Vec<Point> = node.room.iter_cells().copied().collect();
+                let count = cells.len() as i64;
+                let (sum_x, sum_y) = cells
+                    .iter()
+                    .fold((0, 0), |(x, y), cell| (x + cell.x, y + cell.y));
+                // Compare at `count` times the size to stay in whole numbers
+                let anchor = cells
+                    .iter()
+                    .min_by_key(|cell| {
+                        let (dx, dy) = (cell.x * count - sum_x, cell.y * count - sum_y);
+                        (dx * dx + dy * dy, cell.y, cell.x)
+                    })
+                    .copied()
+                    .expect("rooms have at least one cell");
+                (index, anchor)
+            })
+            .collect();
+
+        rooms.sort_by_key(|&(index, anchor)| {
+            let distance = match order {
+                RoomOrder::Distance => distances
+                    .as_ref()
+                    .and_then(|distances| distances[index])
+                    .unwrap_or(usize::MAX),
+                RoomOrder::Reading => 0,
+            };
+            (distance, anchor.y, anchor.x)
+        });
+
+        rooms
+            .into_iter()
+            .enumerate()
+            .map(|(number, (_, anchor))| Label::note((number + 1).to_string(), anchor))
+            .collect()
+    }
+}
+
+/// The built in font
+pub(super) fn font() -> FontRef<'static> {
+    FontRef::try_from_slice(FONT).expect("the built in font is valid")
+}
+
+/// Lay out a line of text `height` high and centred on (0, 0)
+pub(super) fn layout(text: &str, height: f32) -> Vec<Glyph> {
+    let font = font();
+    let scaled = font.as_scaled(height);
+
+    let mut glyphs = Vec::new();
+    let mut x = 0.0;
+    let mut last = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(last) = last {
+            x += scaled.kern(last, id);
+        }
+        glyphs.push(id.with_scale_and_position(height, point(x, 0.0)));
+        x += scaled.h_advance(id);
+        last = Some(id);
+    }
+
+    // Centre the line across and from the top of the tallest letter to the bottom of the lowest
+    let baseline = (scaled.ascent() + scaled.descent()) / 2.0;
+    for glyph in glyphs.iter_mut() {
+        glyph.position.x -= x / 2.0;
+        glyph.position.y += baseline;
+    }
+    glyphs
+}
+
+/// The outline of a line of text as SVG path data, `height` high and centred on `centre`
+pub(super) fn path_data(text: &str, height: f32, centre: (f64, f64)) -> String {
+    let font = font();
+    let scaled = font.as_scaled(height);
+    let (h_factor, v_factor) = (
+        scaled.h_scale_factor() as f64,
+        scaled.v_scale_factor() as f64,
+    );
+
+    let mut data = String::new();
+    for glyph in layout(text, height) {
+        let Some(outline) = font.outline(glyph.id) else {
+            continue;
+        };
+        // Font units go up from the baseline
+        let place = |p: ab_glyph::Point| {
+            (
+                centre.0 + glyph.position.x as f64 + p.x as f64 * h_factor,
+                centre.1 + glyph.position.y as f64 - p.y as f64 * v_factor,
+            )
+        };
+
+        let mut end = None;
+        for curve in outline.curves {
+            let (start, points) = match curve {
+                OutlineCurve::Line(p0, p1) => (p0, vec![p1]),
+                OutlineCurve::Quad(p0, p1, p2) => (p0, vec![p1, p2]),
+                OutlineCurve::Cubic(p0, p1, p2, p3) => (p0, vec![p1, p2, p3]),
+            };
+            if end != Some(start) {
+                if end.is_some() {
+                    data.push('Z');
+                }
+                let (x, y) = place(start);
+                let _ = write!(data, "M{:.4} {:.4}", x, y);
+            }
+            let command = ["L", "Q", "C"][points.len() - 1];
+            data.push_str(command);
+            let coordinates: Vec<String> = points
+                .iter()
+                .map(|p| {
+                    let (x, y) = place(*p);
+                    format!("{:.4} {:.4}", x, y)
+                })
+                .collect();
+            data.push_str(&coordinates.join(" "));
+            end = points.last().copied();
+        }
+        if end.is_some() {
+            data.push('Z');
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::map::cell::{Side, Wall};
+    use crate::map::Area;
+
+    /// Three rooms in a row, joined by corridors, with the entrance in the one on the right
+    fn three_rooms() -> GridMap {
+        let mut map = GridMap::new(20, 8);
+        map.place_room((0, 0), (2, 2)).unwrap();
+        map.place_room((6, 4), (8, 6)).unwrap();
+        map.place_room((12, 0), (14, 2)).unwrap();
+        for x in 3..6 {
+            map.get_cell_mut((x, 1)).set_area(Area::Corridor);
+        }
+        for y in 2..4 {
+            map.get_cell_mut((4, y)).set_area(Area::Corridor);
+        }
+        map.get_cell_mut((5, 5)).set_area(Area::Corridor);
+        map.get_cell_mut((4, 4)).set_area(Area::Corridor);
+        map.get_cell_mut((4, 5)).set_area(Area::Corridor);
+        for x in 9..14 {
+            map.get_cell_mut((x, 5)).set_area(Area::Corridor);
+        }
+        for y in 3..5 {
+            map.get_cell_mut((13, y)).set_area(Area::Corridor);
+        }
+        map.place_entrance((13, 1)).unwrap();
+        map
+    }
+
+    #[test]
+    fn rooms_are_numbered_in_order() {
+        let map = three_rooms();
+
+        let reading = map.number_rooms(RoomOrder::Reading);
+        let anchors: Vec<(String, Point)> = reading.into_iter().map(|l| (l.text, l.at)).collect();
+        assert_eq!(
+            vec![
+                ("1".to_string(), Point::new(1, 1)),
+                ("2".to_string(), Point::new(13, 1)),
+                ("3".to_string(), Point::new(7, 5)),
+            ],
+            anchors
+        );
+
+        // The entrance is in the right hand room, which is next to the middle one
+        let distance = map.number_rooms(RoomOrder::Distance);
+        let anchors: Vec<Point> = distance.iter().map(|label| label.at).collect();
+        assert_eq!(
+            vec![Point::new(13, 1), Point::new(7, 5), Point::new(1, 1)],
+            anchors
+        );
+        assert!(distance.iter().all(|label| label.gm_only));
+    }
+
+    #[test]
+    fn walls_split_rooms() {
+        let mut map = GridMap::new(6, 3);
+        map.place_room((0, 0), (5, 2)).unwrap();
+        for y in 0..3 {
+            map.set_wall((3, y), Side::West, Wall::Wall).unwrap();
+        }
+        assert_eq!(2, map.number_rooms(RoomOrder::Reading).len());
+    }
+
+    #[test]
+    fn text_is_centred() {
+        let glyphs = layout("12", 10.0);
+        assert_eq!(2, glyphs.len());
+        assert!(glyphs[0].position.x < 0.0);
+        assert!(glyphs[1].position.x.abs() < 1e-4);
+
+        let data = path_data("8", 1.0, (3.5, 2.5));
+        assert!(data.starts_with('M') && data.ends_with('Z'));
+        let tree = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"10\" height=\"10\"><path d=\"{}\"/></svg>",
+            data
+        );
+        let tree = usvg::Tree::from_str(&tree, &usvg::Options::default()).unwrap();
+        let bounds = tree.root().bounding_box();
+        assert!((bounds.left() + bounds.right() - 7.0).abs() < 0.05);
+        assert!(bounds.top() > 2.0 && bounds.bottom() < 3.0);
+    }
+}
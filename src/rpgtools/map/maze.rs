@@ -0,0 +1,483 @@
+//! Maze generators
+use std::collections::BTreeMap;
+
+use rand::prelude::*;
+
+use super::cell::{Side, Wall};
+use super::{Area, GridMap, Point};
+
+/// The algorithm used to carve a maze
+///
+/// Every algorithm makes a perfect maze, where there is exactly one path between any two cells,
+/// but each one has its own texture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MazeAlgorithm {
+    /// Depth-first search; long, winding passages with few dead ends.
+    RecursiveBacktracker,
+    /// Randomized Prim's algorithm; short passages with many dead ends.
+    Prim,
+    /// Randomized Kruskal's algorithm; an even mix of short passages.
+    Kruskal,
+    /// Eller's algorithm; built one row at a time, so it tends to have long horizontal runs.
+    Eller,
+}
+
+/// Where the passages of a maze are drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MazeStyle {
+    /// Passages are corridors of room cells with solid rock between them.
+    Cells,
+    /// Every cell is a room and the maze is made of walls on the edges between cells.
+    Walls,
+}
+
+/// A grid of maze cells that keeps track of which edges are open
+struct Maze {
+    width: usize,
+    height: usize,
+    /// Whether the edge to the east of each cell is open, indexed by [x][y]
+    east: Vec<Vec<bool>>,
+    /// Whether the edge to the south of each cell is open, indexed by [x][y]
+    south: Vec<Vec<bool>>,
+}
+
+impl Maze {
+    fn new(width: usize, height: usize) -> Maze {
+        Maze {
+            width,
+            height,
+            east: vec![vec![false; height]; width],
+            south: vec![vec![false; height]; width],
+        }
+    }
+
+    /// The cell on a side of another cell, if it is inside the maze
+    fn neighbour(&self, (x, y): (usize, usize), side: Side) -> Option<(usize, usize)> {
+        let (dx, dy) = side.offset();
+        let x = x.checked_add_signed(dx as isize)?;
+        let y = y.checked_add_signed(dy as isize)?;
+        (x < self.width && y < self.height).then_some((x, y))
+    }
+
+    /// Find the flag for an edge, from the cell that stores it
+    fn edge(&mut self, cell: (usize, usize), side: Side) -> Option<&mut bool> {
+        let (x, y) = match side {
+            Side::East | Side::South => cell,
+            Side::North | Side::West => self.neighbour(cell, side)?,
+        };
+        match side {
+            Side::East | Side::West if x + 1 < self.width => Some(&mut self.east[x][y]),
+            Side::North | Side::South if y + 1 < self.height => Some(&mut self.south[x][y]),
+            _ => None,
+        }
+    }
+
+    fn is_open(&self, (x, y): (usize, usize), side: Side) -> bool {
+        match side {
+            Side::East => x + 1 < self.width && self.east[x][y],
+            Side::South => y + 1 < self.height && self.south[x][y],
+            Side::West => x > 0 && self.east[x - 1][y],
+            Side::North => y > 0 && self.south[x][y - 1],
+        }
+    }
+
+    fn open(&mut self, cell: (usize, usize), side: Side) {
+        if let Some(edge) = self.edge(cell, side) {
+            *edge = true;
+        }
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> {
+        let height = self.height;
+        (0..self.width).flat_map(move |x| (0..height).map(move |y| (x, y)))
+    }
+
+    /// Cells with only one way out
+    fn dead_ends(&self) -> Vec<(usize, usize)> {
+        self.cells()
+            .filter(|cell| SIDES.iter().filter(|side| self.is_open(*cell, **side)).count() == 1)
+            .collect()
+    }
+
+    fn recursive_backtracker(&mut self, rng: &mut impl Rng) {
+        let mut visited = vec![vec![false; self.height]; self.width];
+        let start = (rng.gen_range(0..self.width), rng.gen_range(0..self.height));
+        visited[start.0][start.1] = true;
+        let mut stack = vec![start];
+
+        while let Some(&cell) = stack.last() {
+            let unvisited: Vec<(Side, (usize, usize))> = SIDES
+                .iter()
+                .filter_map(|side| self.neighbour(cell, *side).map(|next| (*side, next)))
+                .filter(|(_, (x, y))| !visited[*x][*y])
+                .collect();
+
+            match unvisited.choose(rng) {
+                Some(&(side, next)) => {
+                    self.open(cell, side);
+                    visited[next.0][next.1] = true;
+                    stack.push(next);
+                }
+                None => {
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    fn prim(&mut self, rng: &mut impl Rng) {
+        let mut visited = vec![vec![false; self.height]; self.width];
+        let start = (rng.gen_range(0..self.width), rng.gen_range(0..self.height));
+        visited[start.0][start.1] = true;
+        let mut frontier: Vec<((usize, usize), Side)> = SIDES.iter().map(|side| (start, *side)).collect();
+
+        while !frontier.is_empty() {
+            let (cell, side) = frontier.swap_remove(rng.gen_range(0..frontier.len()));
+            let Some(next) = self.neighbour(cell, side) else {
+                continue;
+            };
+            if visited[next.0][next.1] {
+                continue;
+            }
+
+            self.open(cell, side);
+            visited[next.0][next.1] = true;
+            frontier.extend(SIDES.iter().map(|side| (next, *side)));
+        }
+    }
+
+    fn kruskal(&mut self, rng: &mut impl Rng) {
+        let mut sets = DisjointSets::new(self.width * self.height);
+        let mut edges: Vec<((usize, usize), Side)> = self
+            .cells()
+            .flat_map(|cell| [(cell, Side::East), (cell, Side::South)])
+            .collect();
+        edges.shuffle(rng);
+
+        for (cell, side) in edges {
+            let Some(next) = self.neighbour(cell, side) else {
+                continue;
+            };
+            if sets.union(self.index(cell), self.index(next)) {
+                self.open(cell, side);
+            }
+        }
+    }
+
+    fn eller(&mut self, rng: &mut impl Rng) {
+        let mut sets = DisjointSets::new(self.width * self.height);
+
+        for y in 0..self.height {
+            let last_row = y + 1 == self.height;
+
+            // Randomly join neighbours in the row, but always join everything on the last row
+            for x in 0..self.width - 1 {
+                let (here, east) = (self.index((x, y)), self.index((x + 1, y)));
+                if sets.find(here) != sets.find(east) && (last_row || rng.gen_bool(0.5)) {
+                    sets.union(here, east);
+                    self.open((x, y), Side::East);
+                }
+            }
+
+            if last_row {
+                break;
+            }
+
+            // Every set must carry on into the next row at least once
+            let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+            for x in 0..self.width {
+                groups.entry(sets.find(self.index((x, y)))).or_default().push(x);
+            }
+            for columns in groups.values() {
+                let down = rng.gen_range(1..=columns.len());
+                for &x in columns.choose_multiple(rng, down) {
+                    sets.union(self.index((x, y)), self.index((x, y + 1)));
+                    self.open((x, y), Side::South);
+                }
+            }
+        }
+    }
+
+    /// Remove a share of the dead ends by knocking out one of their walls
+    fn braid(&mut self, share: f64, rng: &mut impl Rng) {
+        let mut dead_ends = self.dead_ends();
+        dead_ends.shuffle(rng);
+        let count = (share.clamp(0.0, 1.0) * dead_ends.len() as f64).round() as usize;
+
+        for cell in dead_ends.into_iter().take(count) {
+            // An earlier cell may have already opened this one up
+            if SIDES.iter().filter(|side| self.is_open(cell, **side)).count() != 1 {
+                continue;
+            }
+
+            let closed: Vec<Side> = SIDES
+                .iter()
+                .copied()
+                .filter(|side| !self.is_open(cell, *side) && self.neighbour(cell, *side).is_some())
+                .collect();
+
+            // Opening into another dead end removes two at once
+            let into_dead_end: Vec<Side> = closed
+                .iter()
+                .copied()
+                .filter(|side| {
+                    let next = self.neighbour(cell, *side).unwrap();
+                    SIDES.iter().filter(|s| self.is_open(next, **s)).count() == 1
+                })
+                .collect();
+
+            let choices = if into_dead_end.is_empty() {
+                &closed
+            } else {
+                &into_dead_end
+            };
+            if let Some(side) = choices.choose(rng) {
+                self.open(cell, *side);
+            }
+        }
+    }
+
+    fn index(&self, (x, y): (usize, usize)) -> usize {
+        y * self.width + x
+    }
+}
+
+const SIDES: [Side; 4] = [Side::North, Side::East, Side::South, Side::West];
+
+/// Union-find over indices, such as cells or rooms
+pub(crate) struct DisjointSets {
+    parent: Vec<usize>,
+}
+
+impl DisjointSets {
+    pub(crate) fn new(size: usize) -> DisjointSets {
+        DisjointSets {
+            parent: (0..size).collect(),
+        }
+    }
+
+    pub(crate) fn find(&mut self, mut item: usize) -> usize {
+        while self.parent[item] != item {
+            self.parent[item] = self.parent[self.parent[item]];
+            item = self.parent[item];
+        }
+        item
+    }
+
+    /// Join the sets of two items. Returns false if they were already in the same set.
+    pub(crate) fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        self.parent[b] = a;
+        true
+    }
+}
+
+impl GridMap {
+    /// Generate a maze
+    ///
+    /// The maze is carved with `algorithm` and then `braid` (between 0 and 1) is the share of
+    /// dead ends that are removed by knocking through one of their walls. A braid of 0 leaves a
+    /// perfect maze and a braid of 1 leaves a maze with no dead ends at all.
+    ///
+    /// With [`MazeStyle::Cells`] the passages are single-width corridors of room cells, so the
+    /// maze has about half as many cells across as the map. With [`MazeStyle::Walls`] every cell
+    /// inside a one cell border is a room and the maze is drawn with walls on the cell edges.
+    ///
+    /// This method will replace the existing map.
+    pub fn generate_maze(
+        &mut self,
+        algorithm: MazeAlgorithm,
+        braid: f64,
+        style: MazeStyle,
+        rng: &mut impl Rng,
+    ) {
+        self.clear();
+
+        let (xmax, ymax) = self.get_limits();
+        let (width, height) = match style {
+            MazeStyle::Cells => ((xmax.saturating_sub(1)) / 2, (ymax.saturating_sub(1)) / 2),
+            MazeStyle::Walls => (xmax.saturating_sub(2), ymax.saturating_sub(2)),
+        };
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let mut maze = Maze::new(width, height);
+        match algorithm {
+            MazeAlgorithm::RecursiveBacktracker => maze.recursive_backtracker(rng),
+            MazeAlgorithm::Prim => maze.prim(rng),
+            MazeAlgorithm::Kruskal => maze.kruskal(rng),
+            MazeAlgorithm::Eller => maze.eller(rng),
+        }
+        maze.braid(braid, rng);
+
+        match style {
+            MazeStyle::Cells => self.draw_maze_cells(&maze),
+            MazeStyle::Walls => self.draw_maze_walls(&maze),
+        }
+    }
+
+    /// Maze cells sit on odd coordinates with the passages between them
+    fn draw_maze_cells(&mut self, maze: &Maze) {
+        for (x, y) in maze.cells() {
+            let point = Point::new(2 * x as i64 + 1, 2 * y as i64 + 1);
+            self.get_cell_mut(point).set_area(Area::Room);
+            for side in [Side::East, Side::South] {
+                if maze.is_open((x, y), side) {
+                    self.get_cell_mut(point + side.offset().into())
+                        .set_area(Area::Room);
+                }
+            }
+        }
+    }
+
+    /// Maze cells are map cells, offset by one so that the outer walls can be stored
+    fn draw_maze_walls(&mut self, maze: &Maze) {
+        for (x, y) in maze.cells() {
+            let point = Point::new(x as i64 + 1, y as i64 + 1);
+            self.get_cell_mut(point).set_area(Area::Room);
+            for side in SIDES {
+                if !maze.is_open((x, y), side) {
+                    self.set_wall(point, side, Wall::Wall)
+                        .expect("bug: maze wall is outside of the map");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+
+    const ALGORITHMS: [MazeAlgorithm; 4] = [
+        MazeAlgorithm::RecursiveBacktracker,
+        MazeAlgorithm::Prim,
+        MazeAlgorithm::Kruskal,
+        MazeAlgorithm::Eller,
+    ];
+
+    /// Count the maze cells that can be reached from the first one
+    fn reachable(maze: &Maze) -> usize {
+        let mut seen = vec![(0, 0)];
+        let mut queue = vec![(0, 0)];
+        while let Some(cell) = queue.pop() {
+            for side in SIDES {
+                if !maze.is_open(cell, side) {
+                    continue;
+                }
+                let next = maze.neighbour(cell, side).unwrap();
+                if !seen.contains(&next) {
+                    seen.push(next);
+                    queue.push(next);
+                }
+            }
+        }
+        seen.len()
+    }
+
+    fn passages(maze: &Maze) -> usize {
+        maze.cells()
+            .map(|cell| [Side::East, Side::South].iter().filter(|s| maze.is_open(cell, **s)).count())
+            .sum()
+    }
+
+    #[test]
+    fn mazes_are_perfect() {
+        for algorithm in ALGORITHMS {
+            for seed in 0..5 {
+                let mut maze = Maze::new(12, 9);
+                let mut rng = StdRng::seed_from_u64(seed);
+                match algorithm {
+                    MazeAlgorithm::RecursiveBacktracker => maze.recursive_backtracker(&mut rng),
+                    MazeAlgorithm::Prim => maze.prim(&mut rng),
+                    MazeAlgorithm::Kruskal => maze.kruskal(&mut rng),
+                    MazeAlgorithm::Eller => maze.eller(&mut rng),
+                }
+
+                // A spanning tree: everything is reachable and there are no loops
+                assert_eq!(12 * 9, reachable(&maze), "{:?}", algorithm);
+                assert_eq!(12 * 9 - 1, passages(&maze), "{:?}", algorithm);
+            }
+        }
+    }
+
+    #[test]
+    fn full_braid_removes_dead_ends() {
+        let mut maze = Maze::new(15, 15);
+        let mut rng = StdRng::seed_from_u64(2);
+        maze.prim(&mut rng);
+        assert!(!maze.dead_ends().is_empty());
+
+        maze.braid(1.0, &mut rng);
+        assert!(maze.dead_ends().is_empty());
+    }
+
+    #[test]
+    fn maze_cells() {
+        let mut map = GridMap::new(21, 11);
+        map.generate_maze(MazeAlgorithm::Kruskal, 0.0, MazeStyle::Cells, &mut StdRng::seed_from_u64(0));
+
+        // Maze cells are rooms and the corners between them are always rock
+        assert!(map[Point::new(1, 1)].is_room());
+        assert!(map[Point::new(19, 9)].is_room());
+        assert!(!map[Point::new(2, 2)].is_room());
+        assert!(!map[Point::new(20, 10)].is_room());
+
+        // 10 x 5 maze cells and one passage cell for each of the 49 passages
+        let rooms = (0..21)
+            .flat_map(|x| (0..11).map(move |y| Point::new(x, y)))
+            .filter(|p| map[*p].is_room())
+            .count();
+        assert_eq!(50 + 49, rooms);
+    }
+
+    #[test]
+    fn maze_walls() {
+        let mut map = GridMap::new(10, 8);
+        map.generate_maze(
+            MazeAlgorithm::RecursiveBacktracker,
+            0.0,
+            MazeStyle::Walls,
+            &mut StdRng::seed_from_u64(0),
+        );
+
+        // The outside of the maze is walled in
+        for x in 1..9 {
+            assert_eq!(&Wall::Wall, map.get_wall((x, 1), Side::North).unwrap());
+            assert_eq!(&Wall::Wall, map.get_wall((x, 6), Side::South).unwrap());
+        }
+        for y in 1..7 {
+            assert_eq!(&Wall::Wall, map.get_wall((1, y), Side::West).unwrap());
+            assert_eq!(&Wall::Wall, map.get_wall((8, y), Side::East).unwrap());
+            assert!(map[Point::new(1, y)].is_room());
+        }
+
+        // 8 x 6 cells have 82 inner edges, and a perfect maze opens 47 of them
+        let walls = (1..9)
+            .flat_map(|x| (1..7).map(move |y| (x, y)))
+            .flat_map(|p| [(p, Side::East), (p, Side::South)])
+            .filter(|(p, side)| (side == &Side::East && p.0 < 8) || (side == &Side::South && p.1 < 6))
+            .filter(|(p, side)| map.get_wall(*p, *side).unwrap() == &Wall::Wall)
+            .count();
+        assert_eq!(82 - 47, walls);
+    }
+
+    #[test]
+    fn mazes_are_reproducible() {
+        for algorithm in ALGORITHMS {
+            let mut map0 = GridMap::new(25, 25);
+            let mut map1 = GridMap::new(25, 25);
+            map0.generate_maze(algorithm, 0.5, MazeStyle::Cells, &mut StdRng::seed_from_u64(4));
+            map1.generate_maze(algorithm, 0.5, MazeStyle::Cells, &mut StdRng::seed_from_u64(4));
+            assert_eq!(map0, map1);
+        }
+    }
+}
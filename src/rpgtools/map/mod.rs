@@ -4,15 +4,46 @@ pub use gridmap::GridMap;
 
 pub mod area;
 pub mod cell;
+pub mod dungeon;
+pub mod file;
+pub mod graph;
 pub mod point;
 pub mod room;
+pub mod wfc;
 
 pub use area::Area;
 pub use cell::Cell;
+pub use dungeon::Dungeon;
+pub use graph::{Connection, Edge, Node, NodeKind, RoomGraph};
 pub use point::Point;
+pub use wfc::{Tile, TileSet};
 
+mod autotile;
+mod bsp;
+mod connect;
+pub use connect::Connectivity;
+mod distance;
+pub use distance::{DistanceMap, MoveCosts, Movement};
+mod label;
+pub use label::{Label, RoomOrder};
+mod maze;
+pub use maze::{MazeAlgorithm, MazeStyle};
+mod tunnels;
+
+mod pdf;
+pub use pdf::{Paper, PrintLayout, MAX_PRINT_PIXELS};
 mod renderer;
-pub use renderer::Renderer;
+pub use renderer::{Renderer, View};
 
 mod route;
-pub use route::RouteMethod;
+pub use route::{PathCosts, RouteMethod};
+
+mod sight;
+
+mod svg;
+
+mod text;
+pub use text::Glyphs;
+
+mod theme;
+pub use theme::{Sprite, Theme, ThemeSlot};
@@ -0,0 +1,673 @@
+//! Printing maps at an exact physical scale, tiled across the pages of a PDF
+//!
+//! Each page gets its own picture of the part of the map that it shows, drawn with the raster
+//! renderer, so that a big map never has to be drawn all at once. Lengths in the layout are in
+//! millimetres; the PDF itself works in points (1/72 inch).
+use std::fmt::Write as _;
+use std::io::{Error, ErrorKind, Write as _};
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::imageops::crop_imm;
+use image::RgbaImage;
+
+use super::renderer::Renderer;
+
+const POINTS_PER_MM: f64 = 72.0 / 25.4;
+
+/// The most pixels that a whole map can be printed with, about 16000 by 16000
+pub const MAX_PRINT_PIXELS: u64 = 1 << 28;
+
+/// Paper sizes for printing
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Paper {
+    /// 210 x 297mm
+    #[default]
+    A4,
+    /// 8.5 x 11in
+    Letter,
+}
+
+impl Paper {
+    /// The width and height of the paper in points, upright
+    fn size(&self) -> (f64, f64) {
+        match self {
+            Paper::A4 => (210.0 * POINTS_PER_MM, 297.0 * POINTS_PER_MM),
+            Paper::Letter => (612.0, 792.0),
+        }
+    }
+}
+
+/// How a map is split across printed pages
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PrintLayout {
+    pub paper: Paper,
+    /// The printed size of one cell, in millimetres
+    pub square: f64,
+    /// The blank border around each page that printers can't print on, in millimetres
+    pub margin: f64,
+    /// How much of the map is printed on both of two neighbouring pages, in millimetres
+    pub overlap: f64,
+    /// Whether the first page shows the whole map and how it is split into pages
+    pub cover: bool,
+}
+
+impl Default for PrintLayout {
+    fn default() -> Self {
+        Self {
+            paper: Paper::A4,
+            square: 25.4,
+            margin: 10.0,
+            overlap: 10.0,
+            cover: false,
+        }
+    }
+}
+
+impl PrintLayout {
+    /// Check that a map of `width` by `height` cells can be printed with `scale` pixels for each
+    /// cell, which it can't if that takes more than `MAX_PRINT_PIXELS`
+    ///
+    /// Do this before making the `Renderer`, since it draws the theme's sprites at the scale as
+    /// soon as it is made.
+    pub fn check_size(width: usize, height: usize, scale: usize) -> Result<(), Error> {
+        let scale = scale as u64;
+        let pixels = (width as u64)
+            .saturating_mul(height as u64)
+            .saturating_mul(scale.saturating_mul(scale));
+        if pixels > MAX_PRINT_PIXELS {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "printing {} x {} cells at {} pixels each takes {} pixels, more than the {} allowed",
+                    width, height, scale, pixels, MAX_PRINT_PIXELS
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Where the pages fall over the map, all in points
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Tiling {
+    /// The size of the paper, turned whichever way needs fewer pages
+    page: (f64, f64),
+    margin: f64,
+    overlap: f64,
+    columns: usize,
+    rows: usize,
+}
+
+impl Tiling {
+    /// Work out how many pages a map of `size` points needs
+    ///
+    /// The paper is turned on its side if that takes fewer pages.
+    fn new(size: (f64, f64), layout: &PrintLayout) -> Result<Tiling, Error> {
+        let (width, height) = layout.paper.size();
+        let margin = layout.margin * POINTS_PER_MM;
+        let overlap = layout.overlap * POINTS_PER_MM;
+
+        let count = |length: f64, printable: f64| {
+            if length <= printable {
+                1
+            } else {
+                ((length - overlap) / (printable - overlap)).ceil() as usize
+            }
+        };
+
+        [(width, height), (height, width)]
+            .into_iter()
+            .filter(|(width, height)| width.min(*height) - 2.0 * margin > overlap)
+            .map(|page| Tiling {
+                page,
+                margin,
+                overlap,
+                columns: count(size.0, page.0 - 2.0 * margin),
+                rows: count(size.1, page.1 - 2.0 * margin),
+            })
+            .min_by_key(|tiling| tiling.columns * tiling.rows)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    "the margins and overlap don't fit on the page",
+                )
+            })
+    }
+
+    /// The size of the part of a page that the map is printed on
+    fn printable(&self) -> (f64, f64) {
+        (
+            self.page.0 - 2.0 * self.margin,
+            self.page.1 - 2.0 * self.margin,
+        )
+    }
+
+    /// How far the map moves from one page to the next
+    fn step(&self) -> (f64, f64) {
+        let (width, height) = self.printable();
+        (width - self.overlap, height - self.overlap)
+    }
+}
+
+/// The name of a column of pages: A, B, ..., Z, AA, AB, ...
+fn column_name(mut column: usize) -> String {
+    let mut name = vec![];
+    loop {
+        name.push(b'A' + (column % 26) as u8);
+        if column < 26 {
+            break;
+        }
+        column = column / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+/// The name of a page, such as "B3" for the second column and third row
+fn page_name(column: usize, row: usize) -> String {
+    format!("{}{}", column_name(column), row + 1)
+}
+
+/// Write some text at a point, in 8 point Helvetica
+fn text(content: &mut String, x: f64, y: f64, text: &str) {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('(', "\\(")
+        .replace(')', "\\)");
+    let _ = writeln!(
+        content,
+        "BT /F1 8 Tf {:.2} {:.2} Td ({}) Tj ET",
+        x, y, escaped
+    );
+}
+
+/// Draw a straight line
+fn line(content: &mut String, from: (f64, f64), to: (f64, f64)) {
+    let _ = writeln!(
+        content,
+        "{:.2} {:.2} m {:.2} {:.2} l S",
+        from.0, from.1, to.0, to.1
+    );
+}
+
+/// The dictionary and compressed RGB pixels of an image object
+fn image_object(image: &RgbaImage) -> Result<(String, Vec<u8>), Error> {
+    let rgb: Vec<u8> = image
+        .pixels()
+        .flat_map(|pixel| &pixel.0[..3])
+        .copied()
+        .collect();
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    encoder.write_all(&rgb)?;
+    let pixels = encoder.finish()?;
+    let dictionary = format!(
+        "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB \
+         /BitsPerComponent 8 /Filter /FlateDecode /Length {} >>",
+        image.width(),
+        image.height(),
+        pixels.len()
+    );
+    Ok((dictionary, pixels))
+}
+
+/// The objects of a PDF file, numbered from 1 in the order that they are added
+#[derive(Default)]
+struct Pdf {
+    bytes: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl Pdf {
+    fn object(&mut self, dictionary: &str, stream: Option<&[u8]>) {
+        if self.bytes.is_empty() {
+            self.bytes
+                .extend_from_slice(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n");
+        }
+        self.offsets.push(self.bytes.len());
+        let _ = write!(self.bytes, "{} 0 obj\n{}\n", self.offsets.len(), dictionary);
+        if let Some(stream) = stream {
+            self.bytes.extend_from_slice(b"stream\n");
+            self.bytes.extend_from_slice(stream);
+            self.bytes.extend_from_slice(b"\nendstream\n");
+        }
+        self.bytes.extend_from_slice(b"endobj\n");
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.bytes.len();
+        let _ = write!(
+            self.bytes,
+            "xref\n0 {}\n0000000000 65535 f \n",
+            self.offsets.len() + 1
+        );
+        for offset in &self.offsets {
+            let _ = writeln!(self.bytes, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            self.bytes,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            xref
+        );
+        self.bytes
+    }
+}
+
+impl Renderer {
+    /// Draw the map to a PDF that prints it at the size given by `layout`
+    ///
+    /// The map is tiled across as many pages as it needs. Dashed lines mark where each page
+    /// overlaps its neighbours, marks in the corners show where to trim, and every page is named
+    /// by its column and row (A1, B1, ...) with the names of its neighbours on each side. The
+    /// picture has `scale` pixels for each cell, so pick a scale that gives enough dots per inch
+    /// for the printer. Maps of more than `MAX_PRINT_PIXELS` at that scale are turned down with
+    /// `ErrorKind::InvalidInput`.
+    pub fn to_pdf(&self, layout: &PrintLayout) -> Result<Vec<u8>, Error> {
+        if layout.square <= 0.0 || layout.margin < 0.0 || layout.overlap < 0.0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "print lengths must be positive",
+            ));
+        }
+
+        let (xmax, ymax) = self.map.get_limits();
+        PrintLayout::check_size(xmax, ymax, self.scale as usize)?;
+
+        let square = layout.square * POINTS_PER_MM;
+        let size = (xmax as f64 * square, ymax as f64 * square);
+        let tiling = Tiling::new(size, layout)?;
+        let (page_width, page_height) = tiling.page;
+        let (printable_width, printable_height) = tiling.printable();
+        let (step_x, step_y) = tiling.step();
+        let margin = tiling.margin;
+        let num_pages = tiling.columns * tiling.rows + layout.cover as usize;
+
+        let mut pdf = Pdf::default();
+        // Pages come after the catalog, page tree and font, each followed by its contents and its
+        // picture
+        let kids: Vec<String> = (0..num_pages)
+            .map(|page| format!("{} 0 R", 4 + 3 * page))
+            .collect();
+        pdf.object("<< /Type /Catalog /Pages 2 0 R >>", None);
+        pdf.object(
+            &format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                num_pages
+            ),
+            None,
+        );
+        pdf.object(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>",
+            None,
+        );
+
+        let add_page = |pdf: &mut Pdf, content: String, image: RgbaImage| -> Result<(), Error> {
+            let page = pdf.offsets.len() + 1;
+            pdf.object(
+                &format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+                     /Resources << /Font << /F1 3 0 R >> /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                    page_width,
+                    page_height,
+                    page + 2,
+                    page + 1
+                ),
+                None,
+            );
+            pdf.object(
+                &format!("<< /Length {} >>", content.len()),
+                Some(content.as_bytes()),
+            );
+            let (dictionary, pixels) = image_object(&image)?;
+            pdf.object(&dictionary, Some(&pixels));
+            Ok(())
+        };
+
+        if layout.cover {
+            // The whole map, shrunk to fit below a title, with the outline of every page
+            let top = page_height - margin - 20.0;
+            let fit = (printable_width / size.0).min((top - margin) / size.1);
+            let (left, bottom) = (margin, top - size.1 * fit);
+            // Drawn at about the same dots per inch as the other pages
+            let cover_scale = ((self.scale as f64 * fit).ceil() as u32).clamp(1, self.scale);
+            let image = self
+                .window((0, 0), (xmax as u32, ymax as u32), cover_scale)
+                .render_to_image();
+            let mut content = String::new();
+            let _ = writeln!(
+                content,
+                "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im0 Do Q",
+                size.0 * fit,
+                size.1 * fit,
+                left,
+                bottom
+            );
+            let _ = writeln!(content, "1 0 0 RG 1 w");
+            for row in 0..tiling.rows {
+                for column in 0..tiling.columns {
+                    let x = column as f64 * step_x;
+                    let y = row as f64 * step_y;
+                    let width = printable_width.min(size.0 - x);
+                    let height = printable_height.min(size.1 - y);
+                    let _ = writeln!(
+                        content,
+                        "{:.2} {:.2} {:.2} {:.2} re S",
+                        left + x * fit,
+                        top - (y + height) * fit,
+                        width * fit,
+                        height * fit
+                    );
+                    let _ = writeln!(content, "1 0 0 rg");
+                    text(
+                        &mut content,
+                        left + x * fit + 3.0,
+                        top - y * fit - 10.0,
+                        &page_name(column, row),
+                    );
+                }
+            }
+            let _ = writeln!(content, "0 g");
+            text(
+                &mut content,
+                margin,
+                page_height - margin - 10.0,
+                &format!(
+                    "{} x {} cells on {} pages ({} columns, {} rows); 1 cell = {} mm",
+                    xmax,
+                    ymax,
+                    num_pages - 1,
+                    tiling.columns,
+                    tiling.rows,
+                    layout.square
+                ),
+            );
+            add_page(&mut pdf, content, image)?;
+        }
+
+        for row in 0..tiling.rows {
+            for column in 0..tiling.columns {
+                let mut content = String::new();
+
+                // This page's window onto the map, drawn from just the cells that it shows
+                let (x, y) = (column as f64 * step_x, row as f64 * step_y);
+                let cells = |start: f64, length: f64, limit: usize| {
+                    let first = ((start / square).floor() as usize).min(limit - 1);
+                    let last =
+                        (((start + length) / square).ceil() as usize).clamp(first + 1, limit);
+                    (first as u32, last as u32)
+                };
+                let (x0, x1) = cells(x, printable_width, xmax);
+                let (y0, y1) = cells(y, printable_height, ymax);
+                let image = self.render_cells((x0, y0), (x1, y1));
+                let left = margin - x + x0 as f64 * square;
+                let top = page_height - margin + y - y0 as f64 * square;
+                let (width, height) = ((x1 - x0) as f64 * square, (y1 - y0) as f64 * square);
+                let _ = writeln!(
+                    content,
+                    "q {:.2} {:.2} {:.2} {:.2} re W n {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im0 Do Q",
+                    margin,
+                    margin,
+                    printable_width,
+                    printable_height,
+                    width,
+                    height,
+                    left,
+                    top - height
+                );
+
+                // Dashed lines where the neighbouring pages start
+                let (low_x, high_x) = (margin, page_width - margin);
+                let (low_y, high_y) = (margin, page_height - margin);
+                let _ = writeln!(content, "0.5 G 0.5 w [4 3] 0 d");
+                if column > 0 {
+                    line(
+                        &mut content,
+                        (low_x + tiling.overlap, low_y),
+                        (low_x + tiling.overlap, high_y),
+                    );
+                }
+                if column + 1 < tiling.columns {
+                    line(
+                        &mut content,
+                        (high_x - tiling.overlap, low_y),
+                        (high_x - tiling.overlap, high_y),
+                    );
+                }
+                if row > 0 {
+                    line(
+                        &mut content,
+                        (low_x, high_y - tiling.overlap),
+                        (high_x, high_y - tiling.overlap),
+                    );
+                }
+                if row + 1 < tiling.rows {
+                    line(
+                        &mut content,
+                        (low_x, low_y + tiling.overlap),
+                        (high_x, low_y + tiling.overlap),
+                    );
+                }
+
+                // Trim marks out in the margin, in line with the corners of the map
+                let _ = writeln!(content, "0 G 0.5 w [] 0 d");
+                let (gap, length) = ((margin / 4.0).min(3.0), (margin / 2.0).min(12.0));
+                for (x, y, dx, dy) in [
+                    (low_x, low_y, -1.0, -1.0),
+                    (high_x, low_y, 1.0, -1.0),
+                    (low_x, high_y, -1.0, 1.0),
+                    (high_x, high_y, 1.0, 1.0),
+                ] {
+                    line(
+                        &mut content,
+                        (x + dx * gap, y),
+                        (x + dx * (gap + length), y),
+                    );
+                    line(
+                        &mut content,
+                        (x, y + dy * gap),
+                        (x, y + dy * (gap + length)),
+                    );
+                }
+
+                // Where this page goes, and which pages go next to it
+                let _ = writeln!(content, "0 g");
+                let page = row * tiling.columns + column + 1;
+                text(
+                    &mut content,
+                    low_x,
+                    low_y / 2.0 - 3.0,
+                    &format!(
+                        "Page {} ({} of {}); 1 cell = {} mm",
+                        page_name(column, row),
+                        page,
+                        tiling.columns * tiling.rows,
+                        layout.square
+                    ),
+                );
+                let middle = ((low_x + high_x) / 2.0, (low_y + high_y) / 2.0);
+                if column > 0 {
+                    text(&mut content, 2.0, middle.1, &page_name(column - 1, row));
+                }
+                if column + 1 < tiling.columns {
+                    text(
+                        &mut content,
+                        high_x + 2.0,
+                        middle.1,
+                        &page_name(column + 1, row),
+                    );
+                }
+                if row > 0 {
+                    text(
+                        &mut content,
+                        middle.0,
+                        high_y + margin / 2.0 - 3.0,
+                        &page_name(column, row - 1),
+                    );
+                }
+                if row + 1 < tiling.rows {
+                    text(
+                        &mut content,
+                        middle.0,
+                        low_y / 2.0 - 3.0,
+                        &page_name(column, row + 1),
+                    );
+                }
+
+                add_page(&mut pdf, content, image)?;
+            }
+        }
+
+        Ok(pdf.finish())
+    }
+
+    /// Draw the cells from `min` up to (but not including) `max`, just as they look in a picture
+    /// of the whole map
+    ///
+    /// A border of one cell is drawn around them and then cut off, so that walls, wall faces and
+    /// features on the neighbouring cells that reach over the edge are still drawn.
+    fn render_cells(&self, min: (u32, u32), max: (u32, u32)) -> RgbaImage {
+        let (xmax, ymax) = self.map.get_limits();
+        let outer_min = (min.0.saturating_sub(1), min.1.saturating_sub(1));
+        let outer_max = ((max.0 + 1).min(xmax as u32), (max.1 + 1).min(ymax as u32));
+        let image = self
+            .window(outer_min, outer_max, self.scale)
+            .render_to_image();
+        crop_imm(
+            &image,
+            (min.0 - outer_min.0) * self.scale,
+            (min.1 - outer_min.1) * self.scale,
+            (max.0 - min.0) * self.scale,
+            (max.1 - min.1) * self.scale,
+        )
+        .to_image()
+    }
+
+    /// Draw the map to a PDF file, as with `to_pdf`
+    pub fn draw_to_pdf(&self, filename: &str, layout: &PrintLayout) -> Result<(), Error> {
+        std::fs::write(filename, self.to_pdf(layout)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::map::cell::{Point as CornerPoint, Side, Wall};
+    use crate::map::{Connectivity, GridMap, Label, RouteMethod};
+
+    #[test]
+    fn pages_cover_the_map() {
+        let layout = PrintLayout::default();
+        let square = 25.4 * POINTS_PER_MM;
+        let size = (50.0 * square, 50.0 * square);
+        let tiling = Tiling::new(size, &layout).unwrap();
+
+        assert_eq!((7, 5), (tiling.columns, tiling.rows));
+        let (width, height) = tiling.printable();
+        let (step_x, step_y) = tiling.step();
+        assert!((tiling.columns - 1) as f64 * step_x + width >= size.0);
+        assert!((tiling.rows - 1) as f64 * step_y + height >= size.1);
+        assert!((tiling.columns - 2) as f64 * step_x + width < size.0);
+
+        // A wide map turns the paper on its side
+        let wide = Tiling::new((50.0 * square, 5.0 * square), &layout).unwrap();
+        assert_eq!((5, 1), (wide.columns, wide.rows));
+        assert!(wide.page.0 > wide.page.1);
+    }
+
+    #[test]
+    fn overlap_must_fit() {
+        let layout = PrintLayout {
+            overlap: 200.0,
+            ..Default::default()
+        };
+        assert!(Tiling::new((1000.0, 1000.0), &layout).is_err());
+    }
+
+    #[test]
+    fn page_names() {
+        assert_eq!("A1", page_name(0, 0));
+        assert_eq!("Z3", page_name(25, 2));
+        assert_eq!("AA1", page_name(26, 0));
+        assert_eq!("AB2", page_name(27, 1));
+    }
+
+    #[test]
+    fn pdf_has_every_page() {
+        let mut map = GridMap::new(12, 4);
+        map.place_room((1, 1), (10, 2)).unwrap();
+        let layout = PrintLayout {
+            paper: Paper::Letter,
+            cover: true,
+            ..Default::default()
+        };
+        let pdf = Renderer::new(&map, 10).to_pdf(&layout).unwrap();
+
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        let text = String::from_utf8_lossy(&pdf);
+        // Two pages side by side, and the cover
+        assert!(text.contains("/Count 3"));
+        assert_eq!(3, text.matches("/Type /Page ").count());
+    }
+
+    #[test]
+    fn pages_match_the_whole_picture() {
+        let mut map = GridMap::new(16, 12);
+        let mut rng = StdRng::seed_from_u64(2);
+        map.generate_dungeon(
+            6,
+            5,
+            true,
+            RouteMethod::Manhattan,
+            1,
+            Connectivity::Nearby,
+            &mut rng,
+        );
+        map.place_room((6, 4), (9, 7)).unwrap();
+        map.set_wall((6, 5), Side::West, Wall::SecretDoor).unwrap();
+        map.set_wall((8, 4), Side::North, Wall::Door).unwrap();
+        map.set_corner((7, 5), CornerPoint::Statue).unwrap();
+        let renderer = Renderer::new(&map, 12)
+            .with_seed(5)
+            .with_labels([Label::new("Long label", (7, 6))]);
+
+        let whole = renderer.render_to_image();
+        for (min, max) in [
+            ((0, 0), (16, 12)),
+            ((7, 5), (9, 7)),
+            ((3, 2), (16, 9)),
+            ((0, 6), (5, 12)),
+        ] {
+            let part = renderer.render_cells(min, max);
+            let expected = crop_imm(
+                &whole,
+                min.0 * 12,
+                min.1 * 12,
+                (max.0 - min.0) * 12,
+                (max.1 - min.1) * 12,
+            )
+            .to_image();
+            assert!(part == expected, "cells {:?} to {:?}", min, max);
+        }
+    }
+
+    #[test]
+    fn huge_prints_are_refused() {
+        assert!(PrintLayout::check_size(50, 50, 300).is_ok());
+        let error = PrintLayout::check_size(50, 50, 150_000).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, error.kind());
+        assert!(PrintLayout::check_size(usize::MAX, usize::MAX, usize::MAX).is_err());
+
+        let map = GridMap::new(50, 50);
+        let error = Renderer::new(&map, 400)
+            .to_pdf(&PrintLayout::default())
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, error.kind());
+    }
+}
@@ -6,8 +6,10 @@ use std::ops::{Add, Neg, Sub};
 /// This struct represents a specific point in a grid that may include cells at negative integers
 /// (i.e. the origin may not be at the edge of the map).
 ///
-/// Note that this type is just an index and therefore implements both Clone and Copy.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// Note that this type is just an index and therefore implements both Clone and Copy. Points are
+/// ordered by x and then by y.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Point {
     pub x: i64,
     pub y: i64,
@@ -289,4 +291,12 @@ mod tests {
         // Simplified threshold detection
         assert!(Point::new(3, 5).distance(&Point::new(4, 4)) - 2.0f64.sqrt() < 0.000001);
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let point = Point::new(-3, 42);
+        let json = serde_json::to_string(&point).unwrap();
+        assert_eq!(point, serde_json::from_str(&json).unwrap());
+    }
 }
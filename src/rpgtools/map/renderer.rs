@@ -1,24 +1,112 @@
 //! For rendering
-use std::io::{Error, ErrorKind};
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io::Error;
 
+use ab_glyph::Font;
 use image::{imageops::rotate90, Rgba, RgbaImage};
 
-use rand::prelude::*;
-
+use super::autotile::{blob, hatch_lines, neighbour_mask, wall_faces, Line};
+use super::cell::{Point as CornerPoint, Wall};
+use super::label::{font, layout, Label, LABEL_HEIGHT};
+use super::theme::{Theme, ThemeSlot};
 use super::Area;
-use super::GridMap;
+use super::{GridMap, Point};
+
+/// A shape, given as a test of whether an offset (dx, dy) from its centre is inside of it when
+/// it has radius r
+type Shape = fn(i64, i64, i64) -> bool;
+
+/// What a hash picks a sprite for, so that the sprites of a cell's floor, edges and corner are
+/// picked independently
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum SpriteUse {
+    Floor,
+    Overlay,
+    WestEdge,
+    NorthEdge,
+    Corner,
+    Hatching,
+}
+
+/// Who the rendered map is for
+///
+/// The two views of a map differ only in its secrets: with the same map, seed and theme, every
+/// other pixel of a player's copy is the same as the GM's.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum View {
+    /// Everything is drawn, including secret doors
+    #[default]
+    Gm,
+    /// Secrets are hidden; secret doors are drawn as plain walls
+    Player,
+}
+
+impl View {
+    /// Whether secrets are drawn
+    pub fn shows_secrets(&self) -> bool {
+        *self == View::Gm
+    }
+
+    /// The wall as it is seen in this view
+    pub(super) fn wall<'a>(&self, wall: &'a Wall) -> &'a Wall {
+        match wall {
+            Wall::SecretDoor if !self.shows_secrets() => &Wall::Wall,
+            _ => wall,
+        }
+    }
+}
 
-// Assets
-const FLOOR_STONE: &str = include_str!("assets/floor-stone.svg");
-const FLOOR_STONE_2: &str = include_str!("assets/floor-stone-2.svg");
+/// The theme slot that a cell's floor is drawn from. Stairs are drawn over a room floor.
+pub(super) fn floor_slot(area: &Area) -> ThemeSlot {
+    match area {
+        Area::Nothing => ThemeSlot::Nothing,
+        Area::Entrance => ThemeSlot::Entrance,
+        Area::Room | Area::StairsUp | Area::StairsDown => ThemeSlot::Room,
+        Area::Corridor => ThemeSlot::Corridor,
+    }
+}
+
+/// The theme slot of a wall, if it is drawn at all
+pub(super) fn wall_slot(wall: &Wall) -> Option<ThemeSlot> {
+    match wall {
+        Wall::Nothing => None,
+        Wall::Wall => Some(ThemeSlot::Wall),
+        Wall::Door => Some(ThemeSlot::Door),
+        Wall::SecretDoor => Some(ThemeSlot::SecretDoor),
+    }
+}
+
+/// The theme slot of a corner feature, if there is one
+pub(super) fn feature_slot(feature: &CornerPoint) -> Option<ThemeSlot> {
+    match feature {
+        CornerPoint::Nothing => None,
+        CornerPoint::Pillar => Some(ThemeSlot::Pillar),
+        CornerPoint::Column => Some(ThemeSlot::Column),
+        CornerPoint::Statue => Some(ThemeSlot::Statue),
+        CornerPoint::Brazier => Some(ThemeSlot::Brazier),
+    }
+}
 
 /// A renderer that can take a map and draw it to a file
 pub struct Renderer {
-    map: GridMap,
-    scale: u32,
+    pub(super) map: GridMap,
+    pub(super) scale: u32,
+    /// Seed for the random choice of sprites
+    pub(super) seed: u64,
+    /// Whether hidden features are drawn
+    pub(super) view: View,
+    /// The sprites and colours to draw with
+    pub(super) theme: Theme,
+    /// Whether wall faces and hatching are worked out from the shape of the floor
+    pub(super) autotile: bool,
+    /// Text written over the map
+    pub(super) labels: Vec<Label>,
+    /// Where `map` starts within the whole map, in cells, when only part of it is drawn
+    pub(super) origin: (u32, u32),
 
-    /// Rendered assets
-    assets: Vec<RgbaImage>,
+    /// The theme's sprites, drawn at the size of a cell
+    sprites: BTreeMap<ThemeSlot, Vec<RgbaImage>>,
 }
 
 impl Renderer {
@@ -26,17 +114,73 @@ impl Renderer {
         let mut new = Renderer {
             map: map.to_owned(),
             scale: scale as u32,
-            assets: vec![],
+            seed: 0,
+            view: View::default(),
+            theme: Theme::default(),
+            autotile: true,
+            labels: Vec::new(),
+            origin: (0, 0),
+            sprites: BTreeMap::new(),
         };
 
-        new.render_sprites(scale)
-            .expect("unable to render sprites, aborting");
+        new.render_sprites();
         new
     }
 
+    /// Set the theme that the map is drawn with
+    pub fn with_theme(mut self, theme: Theme) -> Renderer {
+        self.theme = theme;
+        self.render_sprites();
+        self
+    }
+
+    /// Set the seed used to pick and rotate sprites
+    ///
+    /// Each cell's sprite depends only on the seed and the cell's coordinate, so rendering the
+    /// same map with the same seed always produces the same image, and changing one part of a
+    /// map leaves the floor of the rest alone.
+    pub fn with_seed(mut self, seed: u64) -> Renderer {
+        self.seed = seed;
+        self
+    }
+
+    /// Set whether the map is autotiled (the default)
+    ///
+    /// Autotiled maps have a wall face wherever the floor meets rock, joined up at inside and
+    /// outside corners, and the rock along the floor is hatched. Without it, rock is plain and
+    /// only the walls set on cell edges are drawn.
+    pub fn with_autotile(mut self, autotile: bool) -> Renderer {
+        self.autotile = autotile;
+        self
+    }
+
+    /// Add labels, such as room numbers from `GridMap::number_rooms`, to write over the map
+    ///
+    /// Labels that are only for the GM are left out of the player view.
+    pub fn with_labels(mut self, labels: impl IntoIterator<Item = Label>) -> Renderer {
+        self.labels.extend(labels);
+        self
+    }
+
+    /// Set who the map is being drawn for
+    pub fn with_view(mut self, view: View) -> Renderer {
+        self.view = view;
+        self
+    }
+
     pub fn draw_to_file(&self, filename: &str) -> Result<(), std::io::Error> {
-        const GRID_SEP_COLOUR: Rgba<u8> = Rgba([190, 190, 190, 255]);
+        let img = self.render_to_image();
+
+        // Now we have filled out the entire pixel array, we pass it to the
+        // encode() method.
+        match img.save(filename) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::other("failed to encode image")),
+        }
+    }
 
+    /// Draw the map to an image in memory, with `scale` pixels for each cell
+    pub fn render_to_image(&self) -> RgbaImage {
         let (xmax, ymax) = self.map.get_limits();
         // Apparently not possible to simultaneously cast these
         let xmax = xmax as u32;
@@ -47,118 +191,465 @@ impl Renderer {
         // Loop through all of our cells
         for x in 0..xmax {
             for y in 0..ymax {
-                // Set the colour of this cell. All pixels within the cell
-                // will have this value.
-                let color = match self.map.get_cell_ref((x, y)).area {
-                    Area::Room => Rgba([200, 200, 200, 255]),
-                    Area::Entrance => Rgba([255, 119, 0, 255]),
-                    _ => Rgba([25, 25, 25, 255]),
-                };
-
-                if self.map.get_cell_ref((x, y)).area == Area::Room {
-                    let mut sprite = self.get_floor_sprite().expect("failed to open file");
-                    let mut rng = thread_rng();
-                    let dist = rand::distributions::Uniform::new_inclusive(0, 3);
-                    for _ in 0..rng.sample(dist) {
-                        sprite = rotate90(&sprite);
-                    }
-                    self.draw_sprite_at(x, y, &mut img, &sprite);
-                } else {
-                    // Loop through all of the pixels in the cell.
-                    for x_pixel in x * self.scale..x * self.scale + self.scale {
-                        for y_pixel in y * self.scale..y * self.scale + self.scale {
-                            img.put_pixel(x_pixel, y_pixel, color);
+                // The floor is a sprite if the theme has any for it, or else a solid colour
+                let area = &self.map.get_cell_ref((x, y)).area;
+                let slot = floor_slot(area);
+                match self.pick_sprite(slot, SpriteUse::Floor, x, y) {
+                    Some((index, turns)) => {
+                        let mut sprite = self.sprites[&slot][index].clone();
+                        for _ in 0..turns {
+                            sprite = rotate90(&sprite);
                         }
+                        self.draw_sprite_at(x, y, &mut img, &sprite);
                     }
+                    None => {
+                        let (left, top) = ((x * self.scale) as i64, (y * self.scale) as i64);
+                        let size = self.scale as i64;
+                        fill_rect(&mut img, (left, top), (left + size, top + size), self.theme.colour(slot));
+                    }
+                }
+                if let Some(tint) = self.theme.tint(slot) {
+                    self.tint_cell(x, y, &mut img, tint);
+                }
+                if matches!(area, Area::StairsUp | Area::StairsDown) {
+                    self.draw_stairs(x, y, area == &Area::StairsDown, &mut img);
+                }
+                if self.autotile && *area == Area::Nothing && neighbour_mask(&self.map, x, y) != 0 {
+                    self.draw_hatching(x, y, &mut img);
                 }
 
-                // Now check whether we need to draw the borders of the cell
-                if x < xmax - 1
-                    && self.map.get_cell_ref((x, y)).area == Area::Room
-                    && self.map.get_cell_ref((x + 1, y)).area == Area::Room
-                {
+                // Now check whether we need to draw the borders of the cell. Grid lines are
+                // only drawn between cells of the same kind of floor.
+                let grid_colour = self.theme.colour(ThemeSlot::Grid);
+                let same_floor = |other: (u32, u32)| {
+                    matches!(area, Area::Room | Area::Corridor)
+                        && self.map.get_cell_ref(other).area == *area
+                };
+                if x < xmax - 1 && same_floor((x + 1, y)) {
                     let x_pixel = (x + 1) * self.scale - 1;
                     for y_pixel in y * self.scale..(y + 1) * self.scale {
-                        img.put_pixel(x_pixel, y_pixel, GRID_SEP_COLOUR);
+                        img.put_pixel(x_pixel, y_pixel, grid_colour);
                     }
                 }
-                if x > 0
-                    && self.map.get_cell_ref((x, y)).area == Area::Room
-                    && self.map.get_cell_ref((x - 1, y)).area == Area::Room
-                {
+                if x > 0 && same_floor((x - 1, y)) {
                     // Explanation is the same as above but now it's the first
                     // pixel in our box
                     let x_pixel = x * self.scale;
                     for y_pixel in y * self.scale..(y + 1) * self.scale {
-                        img.put_pixel(x_pixel, y_pixel, GRID_SEP_COLOUR);
+                        img.put_pixel(x_pixel, y_pixel, grid_colour);
                     }
                 }
-                if y < ymax - 1
-                    && self.map.get_cell_ref((x, y)).area == Area::Room
-                    && self.map.get_cell_ref((x, y + 1)).area == Area::Room
-                {
+                if y < ymax - 1 && same_floor((x, y + 1)) {
                     let y_pixel = (y + 1) * self.scale - 1;
                     for x_pixel in x * self.scale..(x + 1) * self.scale {
-                        img.put_pixel(x_pixel, y_pixel, GRID_SEP_COLOUR);
+                        img.put_pixel(x_pixel, y_pixel, grid_colour);
                     }
                 }
-                if y > 0
-                    && self.map.get_cell_ref((x, y)).area == Area::Room
-                    && self.map.get_cell_ref((x, y - 1)).area == Area::Room
-                {
+                if y > 0 && same_floor((x, y - 1)) {
                     // Explanation is the same as above.
                     let y_pixel = y * self.scale;
                     for x_pixel in x * self.scale..(x + 1) * self.scale {
-                        img.put_pixel(x_pixel, y_pixel, GRID_SEP_COLOUR);
+                        img.put_pixel(x_pixel, y_pixel, grid_colour);
                     }
                 }
             }
         }
 
-        // Now we have filled out the entire pixel array, we pass it to the
-        // encode() method. At the moment, we just need a grayscale image.
-        match img.save(filename) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(Error::new(ErrorKind::Other, "failed to encode image")),
+        // Wall faces run over the edge of the rock, so they go on once all of the cells are drawn
+        if self.autotile {
+            let colour = self.theme.colour(ThemeSlot::Wall);
+            let thickness = self.wall_thickness() as f64 / self.scale as f64;
+            let scale = self.scale as f64;
+            let to_pixel = |base: u32, v: f64| (base as f64 * scale + v * scale + 0.5).floor() as i64;
+            for x in 0..xmax {
+                for y in 0..ymax {
+                    if self.map.get_cell_ref((x, y)).area == Area::Nothing {
+                        continue;
+                    }
+                    for [x0, y0, x1, y1] in wall_faces(blob(neighbour_mask(&self.map, x, y)), thickness) {
+                        let min = (to_pixel(x, x0), to_pixel(y, y0));
+                        let max = (to_pixel(x, x1), to_pixel(y, y1));
+                        fill_rect(&mut img, min, max, colour);
+                    }
+                }
+            }
+        }
+
+        // Walls go on top of the cells so that the neighbouring cells don't
+        // paint over them.
+        for x in 0..xmax {
+            for y in 0..ymax {
+                let cell = self.map.get_cell_ref((x, y));
+                self.draw_wall(x, y, true, cell.vert_wall(), &mut img);
+                self.draw_wall(x, y, false, cell.horiz_wall(), &mut img);
+            }
+        }
+
+        // Corner features sit on top of the walls
+        for x in 0..xmax {
+            for y in 0..ymax {
+                self.draw_corner(x, y, self.map.get_cell_ref((x, y)).point(), &mut img);
+            }
+        }
+
+        // Labels are written over everything else
+        for label in self.visible_labels() {
+            self.draw_label(label, &mut img);
+        }
+
+        img
+    }
+
+    /// Pick one of a slot's sprites for a cell and the number of quarter turns to rotate it by,
+    /// or `None` if the theme has no sprites for the slot
+    ///
+    /// The choice is a hash of the seed, the coordinate and what the sprite is for, so every
+    /// backend draws the same sprites. Only floors are meant to be turned.
+    pub(super) fn pick_sprite(&self, slot: ThemeSlot, usage: SpriteUse, x: u32, y: u32) -> Option<(usize, u32)> {
+        let (x, y) = (x + self.origin.0, y + self.origin.1);
+        let hash = cell_hash(self.seed ^ (usage as u64) << 56, x, y);
+        let index = self.theme.pick(slot, hash)?;
+        let turns = ((hash >> 32) % 4) as u32;
+        Some((index, turns))
+    }
+
+    /// A renderer for part of the map, the cells from `min` up to (but not including) `max`,
+    /// drawn with `scale` pixels for each cell
+    ///
+    /// Sprites and hatching are still picked by each cell's place in the whole map and labels
+    /// keep their places, so the part looks the same as it does in a picture of the whole map.
+    pub(super) fn window(&self, min: (u32, u32), max: (u32, u32), scale: u32) -> Renderer {
+        let mut map = GridMap::new((max.0 - min.0) as usize, (max.1 - min.1) as usize);
+        for x in min.0..max.0 {
+            for y in min.1..max.1 {
+                *map.get_cell_mut((x - min.0, y - min.1)) = self.map.get_cell_ref((x, y)).clone();
+            }
+        }
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| Label {
+                at: label.at - Point::new(min.0 as i64, min.1 as i64),
+                ..label.clone()
+            })
+            .collect();
+
+        let mut window = Renderer {
+            map,
+            scale,
+            seed: self.seed,
+            view: self.view,
+            theme: self.theme.clone(),
+            autotile: self.autotile,
+            labels,
+            origin: (self.origin.0 + min.0, self.origin.1 + min.1),
+            sprites: BTreeMap::new(),
+        };
+        if scale == self.scale {
+            window.sprites = self.sprites.clone();
+        } else {
+            window.render_sprites();
+        }
+        window
+    }
+
+    /// Draw every sprite of the theme at the size of a cell
+    fn render_sprites(&mut self) {
+        self.sprites = ThemeSlot::ALL
+            .into_iter()
+            .map(|slot| {
+                let sprites = self.theme.sprites(slot);
+                (slot, sprites.iter().map(|sprite| sprite.rasterise(self.scale)).collect())
+            })
+            .collect();
+    }
+
+    /// Draw one of a slot's sprites centred on a pixel, blended over what is already there.
+    /// Returns false, without drawing, if the theme has no sprites for the slot.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_overlay(
+        &self,
+        slot: ThemeSlot,
+        usage: SpriteUse,
+        x: u32,
+        y: u32,
+        centre: (i64, i64),
+        turned: bool,
+        image: &mut RgbaImage,
+    ) -> bool {
+        let Some((index, _)) = self.pick_sprite(slot, usage, x, y) else {
+            return false;
+        };
+        let sprite = &self.sprites[&slot][index];
+        let half = self.scale as i64 / 2;
+        let origin = (centre.0 - half, centre.1 - half);
+        if turned {
+            blend_sprite(image, &rotate90(sprite), origin);
+        } else {
+            blend_sprite(image, sprite, origin);
         }
+        true
     }
 
-    /// Get a floor sprite as an RGBA image
-    fn get_floor_sprite(&self) -> Result<RgbaImage, std::io::Error> {
-        let mut rng = thread_rng();
-        let dist = rand::distributions::Uniform::new_inclusive(0, 1);
-        let sample = rng.sample(dist);
+    /// Draw the wall on the west (vertical) or north (horizontal) edge of a cell
+    ///
+    /// Walls are drawn as thick lines along the edge. Doors are drawn as a wall with a box across
+    /// the middle of the edge.
+    fn draw_wall(&self, x: u32, y: u32, vertical: bool, wall: &Wall, image: &mut RgbaImage) {
+        let wall = self.view.wall(wall);
+        let Some(slot) = wall_slot(wall) else {
+            return;
+        };
+
+        // Sprites are drawn for north edges and turned for west edges
+        let scale = self.scale as i64;
+        let centre = if vertical {
+            (x as i64 * scale, y as i64 * scale + scale / 2)
+        } else {
+            (x as i64 * scale + scale / 2, y as i64 * scale)
+        };
+        let usage = if vertical { SpriteUse::WestEdge } else { SpriteUse::NorthEdge };
+        if self.draw_overlay(slot, usage, x, y, centre, vertical, image) {
+            return;
+        }
+
+        let wall_colour = self.theme.colour(ThemeSlot::Wall);
+        let door_colour = match wall {
+            Wall::Door | Wall::SecretDoor => Some(self.theme.colour(slot)),
+            _ => None,
+        };
+
+        let thickness = self.wall_thickness() as i64;
+        // Position of the edge across the line and the start of the cell along it
+        let (across, along) = if vertical {
+            (x as i64 * scale, y as i64 * scale)
+        } else {
+            (y as i64 * scale, x as i64 * scale)
+        };
+
+        let mut rects = vec![(
+            across - thickness / 2,
+            along,
+            across - thickness / 2 + thickness,
+            along + scale,
+            wall_colour,
+        )];
+
+        if let Some(colour) = door_colour {
+            let width = self.door_width() as i64;
+            let (a0, a1) = (across - width / 2, across - width / 2 + width);
+            let (l0, l1) = (along + scale / 4, along + scale - scale / 4);
+            // Outline first and then the door itself
+            rects.push((a0, l0, a1, l1, wall_colour));
+            rects.push((a0 + 1, l0 + 1, a1 - 1, l1 - 1, colour));
+        }
 
-        if sample < self.assets.len() {
-            return Ok(self.assets[sample].clone());
+        for (a0, l0, a1, l1, colour) in rects {
+            if vertical {
+                fill_rect(image, (a0, l0), (a1, l1), colour);
+            } else {
+                fill_rect(image, (l0, a0), (l1, a1), colour);
+            }
         }
+    }
+
+    /// The thickness of walls and wall faces in pixels, which the SVG output matches
+    pub(super) fn wall_thickness(&self) -> u32 {
+        cmp::max(2, self.scale / 8)
+    }
 
-        Err(std::io::Error::new(std::io::ErrorKind::NotFound, ":-("))
+    /// The width of the box drawn across a door in pixels
+    pub(super) fn door_width(&self) -> u32 {
+        cmp::max(self.wall_thickness() + 2, self.scale / 4)
     }
 
-    fn render_sprites(&mut self, size: usize) -> Result<(), std::io::Error> {
-        let sprites_raw = [FLOOR_STONE, FLOOR_STONE_2];
+    /// Draw a flight of stairs over a cell
+    ///
+    /// Without a sprite from the theme, the steps get wider towards the bottom of the cell for
+    /// stairs going up, and towards the top for stairs going down.
+    fn draw_stairs(&self, x: u32, y: u32, down: bool, image: &mut RgbaImage) {
+        const STEPS: i64 = 4;
 
-        for sprite in sprites_raw {
-            let options = usvg::Options {
-                resources_dir: std::fs::canonicalize("src/floor-stone.svg")
-                    .ok()
-                    .and_then(|p| p.parent().map(|p| p.to_path_buf())),
-                ..Default::default()
-            };
+        let slot = if down { ThemeSlot::StairsDown } else { ThemeSlot::StairsUp };
+        let scale = self.scale as i64;
+        let centre = (x as i64 * scale + scale / 2, y as i64 * scale + scale / 2);
+        if self.draw_overlay(slot, SpriteUse::Overlay, x, y, centre, false, image) {
+            return;
+        }
+        let colour = self.theme.colour(slot);
 
-            let rtree = usvg::Tree::from_str(sprite, &options).unwrap();
-            let mut pixmap = tiny_skia::Pixmap::new(size as u32, size as u32).unwrap();
-            resvg::render(
-                &rtree,
-                tiny_skia::Transform::identity(),
-                &mut pixmap.as_mut(),
-            );
+        let (base_x, base_y) = (x as i64 * scale, y as i64 * scale);
+        let step_height = cmp::max(1, scale / (2 * STEPS + 1));
 
-            let image = RgbaImage::from_vec(size as u32, size as u32, pixmap.take()).unwrap();
-            self.assets.push(image);
+        for step in 0..STEPS {
+            let size = if down { STEPS - step } else { step + 1 };
+            let width = scale * size / STEPS - scale / 8;
+            let top = base_y + (2 * step + 1) * step_height;
+            let left = base_x + (scale - width) / 2;
+            fill_rect(image, (left, top), (left + width, top + step_height), colour);
+        }
+    }
+
+    /// The labels that are drawn in this view
+    pub(super) fn visible_labels(&self) -> impl Iterator<Item = &Label> {
+        self.labels
+            .iter()
+            .filter(|label| self.view.shows_secrets() || !label.gm_only)
+    }
+
+    /// Write a label over the middle of its cell, outlined in the room colour so that it can be
+    /// read over anything
+    fn draw_label(&self, label: &Label, image: &mut RgbaImage) {
+        let font = font();
+        let scale = self.scale as f32;
+        let centre = ((label.at.x as f32 + 0.5) * scale, (label.at.y as f32 + 0.5) * scale);
+        let halo = cmp::max(1, self.scale / 12) as i64;
+
+        // How much of each pixel the text covers, over a box around the text with room for the
+        // outline
+        let glyphs: Vec<_> = layout(&label.text, LABEL_HEIGHT * scale)
+            .into_iter()
+            .filter_map(|mut glyph| {
+                glyph.position.x += centre.0;
+                glyph.position.y += centre.1;
+                font.outline_glyph(glyph)
+            })
+            .collect();
+        let Some(bounds) = glyphs.iter().map(|glyph| glyph.px_bounds()).reduce(|a, b| {
+            ab_glyph::Rect {
+                min: ab_glyph::point(a.min.x.min(b.min.x), a.min.y.min(b.min.y)),
+                max: ab_glyph::point(a.max.x.max(b.max.x), a.max.y.max(b.max.y)),
+            }
+        }) else {
+            return;
+        };
+        let origin = (bounds.min.x.floor() as i64 - halo, bounds.min.y.floor() as i64 - halo);
+        let width = (bounds.max.x.ceil() as i64 - origin.0 + halo) as usize;
+        let height = (bounds.max.y.ceil() as i64 - origin.1 + halo) as usize;
+        let mut coverage = vec![0.0f32; width * height];
+        for glyph in &glyphs {
+            let min = glyph.px_bounds().min;
+            let (left, top) = (min.x as i64 - origin.0, min.y as i64 - origin.1);
+            glyph.draw(|x, y, amount| {
+                let index = (top + y as i64) as usize * width + (left + x as i64) as usize;
+                coverage[index] = coverage[index].max(amount);
+            });
+        }
+
+        let (colour, outline) = (self.theme.colour(ThemeSlot::Label), self.theme.colour(ThemeSlot::Room));
+        let (image_width, image_height) = image.dimensions();
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let (px, py) = (origin.0 + x, origin.1 + y);
+                if px < 0 || py < 0 || px >= image_width as i64 || py >= image_height as i64 {
+                    continue;
+                }
+                // The outline covers anything within `halo` pixels of the text
+                let mut around: f32 = 0.0;
+                for dy in -halo..=halo {
+                    for dx in -halo..=halo {
+                        let (nx, ny) = (x + dx, y + dy);
+                        if nx >= 0 && ny >= 0 && nx < width as i64 && ny < height as i64 {
+                            around = around.max(coverage[ny as usize * width + nx as usize]);
+                        }
+                    }
+                }
+                let text = coverage[y as usize * width + x as usize];
+                let pixel = image.get_pixel_mut(px as u32, py as u32);
+                for (amount, paint) in [(around, outline), (text, colour)] {
+                    for channel in 0..3 {
+                        pixel[channel] =
+                            (paint[channel] as f32 * amount + pixel[channel] as f32 * (1.0 - amount)).round() as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draw the feature on the north-west corner of a cell
+    ///
+    /// Pillars are squares, columns are circles, statues are diamonds and braziers are glowing
+    /// circles, all centred on the grid intersection.
+    fn draw_corner(&self, x: u32, y: u32, feature: &CornerPoint, image: &mut RgbaImage) {
+        let centre = ((x * self.scale) as i64, (y * self.scale) as i64);
+        let radius = cmp::max(2, self.scale as i64 / 4);
+
+        let Some(slot) = feature_slot(feature) else {
+            return;
+        };
+        if self.draw_overlay(slot, SpriteUse::Corner, x, y, centre, false, image) {
+            return;
+        }
+        let inside: Shape = match feature {
+            CornerPoint::Pillar => |_, _, _| true,
+            CornerPoint::Statue => |dx, dy, r| dx.abs() + dy.abs() <= r,
+            _ => |dx, dy, r| dx * dx + dy * dy <= r * r,
+        };
+        let (colour, outline) = (self.theme.colour(slot), self.theme.colour(ThemeSlot::Wall));
+
+        let (width, height) = image.dimensions();
+        for dx in -radius..=radius {
+            for dy in -radius..=radius {
+                let (px, py) = (centre.0 + dx, centre.1 + dy);
+                if px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+                    continue;
+                }
+                if !inside(dx, dy, radius) {
+                    continue;
+                }
+                // Outline the shape so that it stands out against the floor
+                let edge = !inside(dx.abs() + 1, dy.abs(), radius)
+                    || !inside(dx.abs(), dy.abs() + 1, radius)
+                    || dx.abs() == radius
+                    || dy.abs() == radius;
+                let pixel = if edge { outline } else { colour };
+                image.put_pixel(px as u32, py as u32, pixel);
+            }
+        }
+    }
+
+    /// Hatch a rock cell, with one of the theme's hatching sprites or else with strokes
+    fn draw_hatching(&self, x: u32, y: u32, image: &mut RgbaImage) {
+        let origin = ((x * self.scale) as i64, (y * self.scale) as i64);
+        if let Some((index, turns)) = self.pick_sprite(ThemeSlot::Hatching, SpriteUse::Hatching, x, y) {
+            let mut sprite = self.sprites[&ThemeSlot::Hatching][index].clone();
+            for _ in 0..turns {
+                sprite = rotate90(&sprite);
+            }
+            blend_sprite(image, &sprite, origin);
+            return;
+        }
+
+        let lines = hatch_lines(self.hatch_hash(x, y));
+        let colour = self.theme.colour(ThemeSlot::Hatching);
+        let scale = self.scale as f64;
+        let half_width = (scale / 20.0).max(1.0) / 2.0 / scale;
+        for px in 0..self.scale {
+            for py in 0..self.scale {
+                let point = ((px as f64 + 0.5) / scale, (py as f64 + 0.5) / scale);
+                if lines.iter().any(|line| distance_to_line(point, line) <= half_width) {
+                    image.put_pixel(origin.0 as u32 + px, origin.1 as u32 + py, colour);
+                }
+            }
+        }
+    }
+
+    /// The hash that a rock cell's hatching strokes are picked with
+    pub(super) fn hatch_hash(&self, x: u32, y: u32) -> u64 {
+        let (x, y) = (x + self.origin.0, y + self.origin.1);
+        cell_hash(self.seed ^ (SpriteUse::Hatching as u64) << 56, x, y)
+    }
+
+    /// Blend every pixel of a cell part of the way towards a colour
+    fn tint_cell(&self, x: u32, y: u32, image: &mut RgbaImage, colour: Rgba<u8>) {
+        for x_pixel in x * self.scale..(x + 1) * self.scale {
+            for y_pixel in y * self.scale..(y + 1) * self.scale {
+                let pixel = image.get_pixel_mut(x_pixel, y_pixel);
+                for channel in 0..3 {
+                    pixel[channel] = ((pixel[channel] as u32 * 3 + colour[channel] as u32 * 2) / 5) as u8;
+                }
+            }
         }
-        Ok(())
     }
 
     /// Draw a sprite into a location in the image
@@ -171,3 +662,223 @@ impl Renderer {
         }
     }
 }
+
+/// Mix a seed and a cell coordinate into a well spread hash (SplitMix64), which is the same on
+/// every platform and Rust version
+fn cell_hash(seed: u64, x: u32, y: u32) -> u64 {
+    let mut hash = seed ^ ((x as u64) << 32 | y as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..2 {
+        hash = hash.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+    }
+    hash
+}
+
+/// Draw a sprite with its top left corner at a pixel, blending it over the image by its alpha and
+/// clipping it to the image
+fn blend_sprite(image: &mut RgbaImage, sprite: &RgbaImage, origin: (i64, i64)) {
+    let (width, height) = image.dimensions();
+    for (px, py, pixel) in sprite.enumerate_pixels() {
+        let (x, y) = (origin.0 + px as i64, origin.1 + py as i64);
+        if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+            continue;
+        }
+        let alpha = pixel[3] as u32;
+        let under = image.get_pixel_mut(x as u32, y as u32);
+        for channel in 0..3 {
+            under[channel] = ((pixel[channel] as u32 * alpha + under[channel] as u32 * (255 - alpha)) / 255) as u8;
+        }
+        under[3] = under[3].max(pixel[3]);
+    }
+}
+
+/// The distance from a point to the nearest point of a line
+fn distance_to_line(point: (f64, f64), line: &Line) -> f64 {
+    let [x0, y0, x1, y1] = *line;
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    let length = dx * dx + dy * dy;
+    let t = if length > 0.0 {
+        (((point.0 - x0) * dx + (point.1 - y0) * dy) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (nx, ny) = (x0 + t * dx - point.0, y0 + t * dy - point.1);
+    (nx * nx + ny * ny).sqrt()
+}
+
+/// Fill a rectangle of pixels from `min` up to (but not including) `max`, clipped to the image
+fn fill_rect(image: &mut RgbaImage, min: (i64, i64), max: (i64, i64), colour: Rgba<u8>) {
+    let (width, height) = image.dimensions();
+    let x0 = min.0.clamp(0, width as i64) as u32;
+    let y0 = min.1.clamp(0, height as i64) as u32;
+    let x1 = max.0.clamp(0, width as i64) as u32;
+    let y1 = max.1.clamp(0, height as i64) as u32;
+
+    for x in x0..x1 {
+        for y in y0..y1 {
+            image.put_pixel(x, y, colour);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::map::cell::Side;
+    use crate::map::{RouteMethod, Sprite};
+
+    fn two_rooms() -> GridMap {
+        let mut map = GridMap::new(20, 10);
+        map.place_room((1, 1), (6, 6)).unwrap();
+        map.place_room((12, 2), (17, 7)).unwrap();
+        map
+    }
+
+    #[test]
+    fn renders_are_repeatable() {
+        let map = two_rooms();
+        let first = Renderer::new(&map, 10).with_seed(3).render_to_image();
+        let second = Renderer::new(&map, 10).with_seed(3).render_to_image();
+        let other_seed = Renderer::new(&map, 10).with_seed(4).render_to_image();
+
+        assert_eq!(first, second);
+        assert_ne!(first, other_seed);
+    }
+
+    /// FNV-1a, which unlike `DefaultHasher` is fixed for good
+    fn checksum(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    }
+
+    /// A small map with one of most things on it always renders to exactly the same pixels.
+    /// If a change to the renderer is meant to change how maps look, update the checksum.
+    #[test]
+    fn render_matches_the_stored_checksum() {
+        let mut map = two_rooms();
+        map.place_hallway((6, 4), (12, 4), RouteMethod::HorizontalFirst, 1, &mut StdRng::seed_from_u64(0))
+            .unwrap();
+        map.set_wall((3, 3), Side::West, Wall::SecretDoor).unwrap();
+        map.set_corner((3, 2), CornerPoint::Pillar).unwrap();
+        map.set_corner((15, 5), CornerPoint::Statue).unwrap();
+        map.get_cell_mut((2, 2)).set_area(Area::Entrance);
+        map.get_cell_mut((16, 6)).set_area(Area::StairsDown);
+
+        let image = Renderer::new(&map, 8).with_seed(1).render_to_image();
+        assert_eq!((160, 80), image.dimensions());
+        assert_eq!(0x65e1_f3dc_92fe_21aa, checksum(image.as_raw()));
+    }
+
+    #[test]
+    fn sprites_only_depend_on_the_cell() {
+        let map = two_rooms();
+        let mut changed = map.clone();
+        changed.get_cell_mut((14, 4)).set_area(Area::Nothing);
+
+        let before = Renderer::new(&map, 10).render_to_image();
+        let after = Renderer::new(&changed, 10).render_to_image();
+
+        // The left room is far from the change and looks exactly the same
+        for x in 10..70 {
+            for y in 10..70 {
+                assert_eq!(before.get_pixel(x, y), after.get_pixel(x, y));
+            }
+        }
+        assert_ne!(before, after);
+    }
+
+    #[test]
+    fn themes_change_the_look() {
+        let mut map = GridMap::new(4, 3);
+        map.place_room((0, 0), (3, 2)).unwrap();
+        map.set_wall((2, 1), Side::West, Wall::Door).unwrap();
+        map.get_cell_mut((0, 0)).set_area(Area::Entrance);
+
+        let red = r##"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="4">
+            <rect width="4" height="4" fill="#ff0000"/></svg>"##;
+        let mut theme = Theme::default();
+        theme.add_sprite(ThemeSlot::Door, Sprite::svg(red, 1).unwrap());
+        theme.set_colour(ThemeSlot::Entrance, Rgba([0, 0, 255, 255]));
+        let image = Renderer::new(&map, 10).with_theme(theme).render_to_image();
+
+        // The door sprite is a cell in size, centred on the middle of the edge
+        assert_eq!(&Rgba([255, 0, 0, 255]), image.get_pixel(16, 11));
+        assert_eq!(&Rgba([255, 0, 0, 255]), image.get_pixel(24, 19));
+        assert_eq!(&Rgba([0, 0, 255, 255]), image.get_pixel(5, 5));
+    }
+
+    #[test]
+    fn autotiling_draws_faces_and_hatching() {
+        let mut map = GridMap::new(5, 5);
+        map.place_room((0, 0), (1, 1)).unwrap();
+        let theme = Theme::default();
+        let (rock, hatching, wall) = (
+            theme.colour(ThemeSlot::Nothing),
+            theme.colour(ThemeSlot::Hatching),
+            theme.colour(ThemeSlot::Wall),
+        );
+        let cell_pixels = |image: &RgbaImage, x: u32, y: u32| -> Vec<Rgba<u8>> {
+            (x * 20..(x + 1) * 20)
+                .flat_map(|px| (y * 20..(y + 1) * 20).map(move |py| (px, py)))
+                .map(|(px, py)| *image.get_pixel(px, py))
+                .collect()
+        };
+
+        let image = Renderer::new(&map, 20).render_to_image();
+        // A face along the east side of the room, running round the corner into the rock
+        assert_eq!(&wall, image.get_pixel(40, 10));
+        assert_eq!(&wall, image.get_pixel(40, 40));
+        assert!(cell_pixels(&image, 2, 0).contains(&hatching));
+        assert!(cell_pixels(&image, 2, 2).contains(&hatching));
+        assert!(cell_pixels(&image, 4, 4).iter().all(|pixel| *pixel == rock));
+
+        let flat = Renderer::new(&map, 20).with_autotile(false).render_to_image();
+        assert!(cell_pixels(&flat, 2, 0).iter().all(|pixel| *pixel == rock));
+    }
+
+    #[test]
+    fn views_only_differ_in_secrets() {
+        let mut map = two_rooms();
+        map.set_wall((4, 2), Side::West, Wall::SecretDoor).unwrap();
+
+        let gm = Renderer::new(&map, 20).with_seed(3).render_to_image();
+        let player = Renderer::new(&map, 20).with_seed(3).with_view(View::Player).render_to_image();
+        let changed: Vec<(u32, u32)> = gm
+            .enumerate_pixels()
+            .filter(|(x, y, pixel)| player.get_pixel(*x, *y) != *pixel)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+
+        // Only the door itself, across the middle of the edge, is left out
+        assert!(!changed.is_empty());
+        for (x, y) in changed {
+            assert!((75..85).contains(&x) && (45..55).contains(&y), "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn notes_are_only_for_the_gm() {
+        let map = two_rooms();
+        let labels = [Label::new("A", (3, 3)), Label::note("B", (14, 4))];
+        let plain = Renderer::new(&map, 20).render_to_image();
+        let has_text = |image: &RgbaImage, x: u32, y: u32| {
+            (x * 20..(x + 1) * 20)
+                .any(|px| (y * 20..(y + 1) * 20).any(|py| image.get_pixel(px, py) != plain.get_pixel(px, py)))
+        };
+
+        let gm = Renderer::new(&map, 20).with_labels(labels.clone()).render_to_image();
+        let player = Renderer::new(&map, 20)
+            .with_labels(labels)
+            .with_view(View::Player)
+            .render_to_image();
+        assert!(has_text(&gm, 3, 3) && has_text(&player, 3, 3));
+        assert!(has_text(&gm, 14, 4) && !has_text(&player, 14, 4));
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 
 use super::point::Point;
 use crate::error::{Result, RpgError};
@@ -9,10 +9,12 @@ use crate::error::{Result, RpgError};
 /// form a shape on the map that may or may not be connected to other rooms or
 /// features.
 ///
-/// Currently the GridRoom represents the cells by a set of indexes.
+/// Currently the GridRoom represents the cells by an ordered set of indexes, so that iterating
+/// over the cells always visits them in the same order.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Room {
-    cells: HashSet<Point>,
+    cells: BTreeSet<Point>,
     connected: bool,
 }
 
@@ -20,7 +22,7 @@ impl Room {
     /// Make a new GridRoom
     pub fn new() -> Room {
         Room {
-            cells: HashSet::new(),
+            cells: BTreeSet::new(),
             connected: false,
         }
     }
@@ -120,4 +122,16 @@ mod tests {
             r1.nearest_cells(&r2).unwrap()
         )
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut room = Room::new();
+        room.add_cell((0, 0)).unwrap();
+        room.add_cell((0, 1)).unwrap();
+        room.add_cell((5, -2)).unwrap();
+
+        let json = serde_json::to_string(&room).unwrap();
+        assert_eq!(room, serde_json::from_str(&json).unwrap());
+    }
 }
@@ -1,14 +1,330 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use super::cell::Side;
+use super::{Area, GridMap, Point};
+use crate::error::{Result, RpgError};
+
 /// How paths are placed
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RouteMethod {
     /// Manhattan routing; horizontal direction first.
     HorizontalFirst,
     /// Manhattan routing; vertical direction first.
     VerticalFirst,
-    // Straight-line/diagonal routing
-    //Direct,
+    /// Straight-line/diagonal routing.
+    Direct,
     /// Split route into horizontal and vertical components and do them one at a time.
     Manhattan,
-    // Subway-map style: allows routes at 0 degrees, 90 degrees and 45 degrees.
-    //Subway,
+    /// Subway-map style: allows routes at 0 degrees, 90 degrees and 45 degrees.
+    Subway,
+    /// Find the cheapest route with A*, which goes around rooms rather than through them.
+    Pathfind(PathCosts),
+}
+
+/// The cost of each step of a `RouteMethod::Pathfind` route
+///
+/// Routes never cross walls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PathCosts {
+    /// Digging through an empty cell
+    pub dig: u32,
+    /// Following a cell that is already open, such as a corridor or the edge of a room
+    pub corridor: u32,
+    /// Crossing the middle of a room
+    pub room: u32,
+    /// Extra cost for digging next to an open cell or a wall, which runs alongside it
+    pub beside_wall: u32,
+}
+
+impl Default for PathCosts {
+    fn default() -> Self {
+        Self {
+            dig: 4,
+            corridor: 1,
+            room: 20,
+            beside_wall: 6,
+        }
+    }
+}
+
+const SIDES: [Side; 4] = [Side::North, Side::East, Side::South, Side::West];
+
+/// Step from the end of a path towards a target, one cell at a time
+///
+/// Each step moves horizontally and then vertically, so diagonals become an even staircase and
+/// consecutive cells are always neighbours.
+fn walk_to(path: &mut Vec<Point>, target: Point) {
+    let mut current = *path.last().expect("bug: path has no start");
+    while current != target {
+        if current.x != target.x {
+            current.x += (target.x - current.x).signum();
+            path.push(current);
+        }
+        if current.y != target.y {
+            current.y += (target.y - current.y).signum();
+            path.push(current);
+        }
+    }
+}
+
+/// An L-shaped path that turns once, at the corner
+pub(crate) fn manhattan_path(start: Point, end: Point, horizontal_first: bool) -> Vec<Point> {
+    let corner = if horizontal_first {
+        Point::new(end.x, start.y)
+    } else {
+        Point::new(start.x, end.y)
+    };
+
+    let mut path = vec![start];
+    walk_to(&mut path, corner);
+    walk_to(&mut path, end);
+    path
+}
+
+/// A straight line, rasterised with Bresenham's algorithm
+pub(crate) fn direct_path(start: Point, end: Point) -> Vec<Point> {
+    let dx = (end.x - start.x).abs();
+    let dy = -(end.y - start.y).abs();
+    let step = Point::new((end.x - start.x).signum(), (end.y - start.y).signum());
+    let mut error = dx + dy;
+    let mut current = start;
+
+    let mut path = vec![start];
+    while current != end {
+        let double = 2 * error;
+        if double >= dy {
+            error += dy;
+            current.x += step.x;
+        }
+        if double <= dx {
+            error += dx;
+            current.y += step.y;
+        }
+        // Fill in the corner of diagonal steps so that the line stays connected
+        walk_to(&mut path, current);
+    }
+    path
+}
+
+/// A path made of one straight run and one 45 degree run
+pub(crate) fn subway_path(start: Point, end: Point, diagonal_first: bool) -> Vec<Point> {
+    let diagonal = (end.x - start.x).abs().min((end.y - start.y).abs());
+    let step = Point::new(
+        (end.x - start.x).signum() * diagonal,
+        (end.y - start.y).signum() * diagonal,
+    );
+    let corner = if diagonal_first { start + step } else { end - step };
+
+    let mut path = vec![start];
+    walk_to(&mut path, corner);
+    walk_to(&mut path, end);
+    path
+}
+
+impl GridMap {
+    /// Find the cheapest path between two points with A*
+    ///
+    /// The path is returned in order from `start` to `end`.
+    pub(crate) fn find_path(&self, start: Point, end: Point, costs: &PathCosts) -> Result<Vec<Point>> {
+        let (xmax, ymax) = self.get_limits();
+        let index = |point: Point| point.y as usize * xmax + point.x as usize;
+
+        // Never overestimate, so that the first path to reach the end is the cheapest
+        let min_step = costs.dig.min(costs.corridor).min(costs.room) as u64;
+        let estimate = |point: Point| ((point.x - end.x).abs() + (point.y - end.y).abs()) as u64 * min_step;
+
+        let mut best = vec![u64::MAX; xmax * ymax];
+        let mut came_from: Vec<Option<Point>> = vec![None; xmax * ymax];
+        let mut open = BinaryHeap::new();
+        best[index(start)] = 0;
+        open.push(Reverse((estimate(start), 0, start)));
+
+        while let Some(Reverse((_, cost, point))) = open.pop() {
+            if point == end {
+                let mut path = vec![end];
+                while let Some(previous) = came_from[index(*path.last().unwrap())] {
+                    path.push(previous);
+                }
+                path.reverse();
+                return Ok(path);
+            }
+            if cost > best[index(point)] {
+                continue;
+            }
+
+            for side in SIDES {
+                let next = point + side.offset().into();
+                if !self.in_bounds(next) || self.get_wall(point, side)?.blocks_movement() {
+                    continue;
+                }
+
+                let next_cost = cost + self.step_cost(next, costs) as u64;
+                if next_cost < best[index(next)] {
+                    best[index(next)] = next_cost;
+                    came_from[index(next)] = Some(point);
+                    open.push(Reverse((next_cost + estimate(next), next_cost, next)));
+                }
+            }
+        }
+
+        Err(RpgError::Empty(format!(
+            "no route from ({}, {}) to ({}, {})",
+            start.x, start.y, end.x, end.y
+        )))
+    }
+
+    /// The cost of stepping into a cell
+    fn step_cost(&self, point: Point, costs: &PathCosts) -> u32 {
+        let is_open = |point: Point| self.in_bounds(point) && self[point].area != Area::Nothing;
+
+        if self.in_bounds(point) && self[point].area == Area::Corridor {
+            return costs.corridor;
+        }
+        if is_open(point) {
+            // Room interiors are open all of the way around
+            let interior = (-1..=1)
+                .flat_map(|dx| (-1..=1).map(move |dy| Point::new(point.x + dx, point.y + dy)))
+                .all(is_open);
+            return if interior { costs.room } else { costs.corridor };
+        }
+
+        let beside = SIDES.iter().any(|side| {
+            is_open(point + side.offset().into())
+                || self
+                    .get_wall(point, *side)
+                    .is_ok_and(|wall| wall.blocks_movement())
+        });
+        if beside {
+            costs.dig + costs.beside_wall
+        } else {
+            costs.dig
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::map::cell::Wall;
+
+    fn assert_connected(path: &[Point]) {
+        for pair in path.windows(2) {
+            assert_eq!(1, (pair[0].x - pair[1].x).abs() + (pair[0].y - pair[1].y).abs());
+        }
+    }
+
+    #[test]
+    fn direct_follows_the_line() {
+        let (start, end) = (Point::new(1, 2), Point::new(17, 9));
+        let path = direct_path(start, end);
+
+        assert_eq!(start, path[0]);
+        assert_eq!(end, *path.last().unwrap());
+        assert_connected(&path);
+
+        // Every cell is close to the real line
+        let (dx, dy) = ((end.x - start.x) as f64, (end.y - start.y) as f64);
+        for point in &path {
+            let cross = (point.x - start.x) as f64 * dy - (point.y - start.y) as f64 * dx;
+            assert!(cross.abs() / dx.hypot(dy) < 1.5);
+        }
+    }
+
+    #[test]
+    fn subway_uses_octilinear_runs() {
+        for diagonal_first in [true, false] {
+            let (start, end) = (Point::new(2, 12), Point::new(15, 3));
+            let path = subway_path(start, end, diagonal_first);
+
+            assert_eq!(start, path[0]);
+            assert_eq!(end, *path.last().unwrap());
+            assert_connected(&path);
+
+            // 9 diagonal steps take 18 cells and the 4 straight steps take 4
+            assert_eq!(1 + 18 + 4, path.len());
+        }
+    }
+
+    #[test]
+    fn routes_join_rooms() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for (route, width) in [(RouteMethod::Direct, 1), (RouteMethod::Direct, 3), (RouteMethod::Subway, 1)] {
+            let mut map = GridMap::new(30, 20);
+            map.place_room((1, 1), (4, 4)).unwrap();
+            map.place_room((22, 13), (27, 17)).unwrap();
+            map.place_hallway((3, 3), (24, 15), route, width, &mut rng).unwrap();
+
+            assert_eq!(1, map.partition_rooms().len(), "{:?}", route);
+        }
+    }
+
+    #[test]
+    fn wide_direct_hallway() {
+        let mut map = GridMap::new(20, 20);
+        map.place_hallway((2, 10), (17, 10), RouteMethod::Direct, 3, &mut StdRng::seed_from_u64(0))
+            .unwrap();
+
+        for x in 2..=17 {
+            for y in 9..=11 {
+                assert_eq!(&Area::Corridor, map[Point::new(x, y)].area());
+            }
+            assert!(!map[Point::new(x, 8)].is_room());
+            assert!(!map[Point::new(x, 12)].is_room());
+        }
+    }
+
+    #[test]
+    fn pathfind_goes_around_rooms() {
+        let mut map = GridMap::new(20, 12);
+        map.place_room((5, 2), (14, 9)).unwrap();
+
+        let path = map
+            .route_hallway(
+                (2, 5),
+                (17, 5),
+                RouteMethod::Pathfind(PathCosts::default()),
+                &mut StdRng::seed_from_u64(0),
+            )
+            .unwrap();
+
+        assert_eq!(Point::new(2, 5), path[0]);
+        assert_eq!(Point::new(17, 5), *path.last().unwrap());
+        assert_connected(&path);
+        for point in &path {
+            assert!(!point.is_in_bounds(Point::new(6, 3), Point::new(13, 8)));
+        }
+    }
+
+    #[test]
+    fn pathfind_prefers_corridors() {
+        // A corridor that loops up and over is cheaper than digging straight across
+        let mut map = GridMap::new(20, 10);
+        map.place_room((2, 1), (2, 8)).unwrap();
+        map.place_room((2, 1), (17, 1)).unwrap();
+        map.place_room((17, 1), (17, 8)).unwrap();
+
+        let path = map.find_path((2, 8).into(), (17, 8).into(), &PathCosts::default()).unwrap();
+        assert_connected(&path);
+        assert!(path.iter().all(|point| map[*point].is_room()));
+    }
+
+    #[test]
+    fn pathfind_respects_walls() {
+        let mut map = GridMap::new(10, 5);
+        for y in 0..5 {
+            map.set_wall((5, y), Side::West, Wall::Wall).unwrap();
+        }
+        assert!(map.find_path((1, 1).into(), (8, 1).into(), &PathCosts::default()).is_err());
+
+        map.set_wall((5, 3), Side::West, Wall::Door).unwrap();
+        let path = map.find_path((1, 1).into(), (8, 1).into(), &PathCosts::default()).unwrap();
+        assert!(path.contains(&Point::new(4, 3)) && path.contains(&Point::new(5, 3)));
+    }
 }
@@ -0,0 +1,257 @@
+//! Field of view and line of sight
+//!
+//! Sight is worked out with shadowcasting. The area around the viewer is split into eight
+//! octants and each one is scanned outwards a row at a time, keeping track of the ranges of
+//! slopes that are hidden behind something. A cell is seen if the line to its centre isn't in
+//! shadow, and empty (`Area::Nothing`) cells are seen if any part of them is lit, so that the
+//! edges of rooms show up.
+//!
+//! Empty cells and every wall, door and secret door on the edges of cells block sight. Doors are
+//! treated as being closed.
+use std::collections::BTreeSet;
+
+use super::cell::Side;
+use super::{Area, GridMap, Point};
+
+/// Turn (row, column) within an octant into (x, y) offsets, as (x per column, x per row,
+/// y per column, y per row)
+const OCTANTS: [(i64, i64, i64, i64); 8] = [
+    (1, 0, 0, -1),
+    (-1, 0, 0, -1),
+    (1, 0, 0, 1),
+    (-1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, 1, -1, 0),
+    (0, -1, 1, 0),
+    (0, -1, -1, 0),
+];
+
+/// How far the shadows of walls are stretched, so that lines that only touch the end of a wall
+/// are blocked by it. This stops sight slipping through the corners where walls meet.
+const WALL_OVERLAP: f64 = 1e-9;
+
+/// Ranges of slopes (column / row) within an octant that are hidden, kept sorted and merged
+struct Shadows(Vec<(f64, f64)>);
+
+impl Shadows {
+    fn add(&mut self, mut low: f64, mut high: f64) {
+        // Swallow every shadow that touches the new one
+        self.0.retain(|&(l, h)| {
+            if h < low || l > high {
+                return true;
+            }
+            low = low.min(l);
+            high = high.max(h);
+            false
+        });
+        let index = self.0.partition_point(|&(l, _)| l < low);
+        self.0.insert(index, (low, high));
+    }
+
+    /// Whether a slope is hidden. Slopes on the very edge of a shadow can still be seen.
+    fn hides(&self, slope: f64) -> bool {
+        self.0.iter().any(|&(l, h)| l < slope && slope < h)
+    }
+
+    /// Whether a whole range of slopes is hidden
+    fn hides_all(&self, low: f64, high: f64) -> bool {
+        self.0.iter().any(|&(l, h)| l <= low && high <= h)
+    }
+}
+
+impl GridMap {
+    /// Find every cell that can be seen from a point
+    ///
+    /// Empty cells block sight, as do walls, doors and secret doors on the edges of cells. The
+    /// empty cells themselves are seen if any part of them is lit, so that the edges of rooms
+    /// show up. With a `radius`, only cells whose centres are at most that many cells away are
+    /// seen, as if the viewer were carrying a light. The point itself is always seen, as long as
+    /// it is on the map.
+    pub fn field_of_view(&self, origin: impl Into<Point>, radius: Option<usize>) -> BTreeSet<Point> {
+        let origin = origin.into();
+        let mut seen = BTreeSet::new();
+        if !self.in_bounds(origin) {
+            return seen;
+        }
+        seen.insert(origin);
+
+        let (xmax, ymax) = self.get_limits();
+        let max_row = radius.unwrap_or(usize::MAX).min(xmax.max(ymax)) as i64;
+        let in_range = |row: i64, col: i64| match radius {
+            Some(radius) => {
+                (row * row + col * col) as u64 <= (radius as u64).saturating_mul(radius as u64)
+            }
+            None => true,
+        };
+
+        for (x_col, x_row, y_col, y_row) in OCTANTS {
+            let point_at = |row: i64, col: i64| {
+                Point::new(origin.x + col * x_col + row * x_row, origin.y + col * y_col + row * y_row)
+            };
+            let blocks = |point: Point, offset: (i64, i64)| {
+                Side::from_offset(offset)
+                    .and_then(|side| self.get_wall(point, side).ok())
+                    .is_some_and(|wall| wall.blocks_sight())
+            };
+            let forward = (x_row, y_row);
+            let (right, left) = ((x_col, y_col), (-x_col, -y_col));
+
+            let mut shadows = Shadows(vec![]);
+            // A wall straight in front of the viewer hides the whole octant
+            if blocks(origin, forward) {
+                continue;
+            }
+
+            for row in 1..=max_row {
+                let depth = row as f64;
+                let mut pending = vec![];
+
+                for col in 0..=row {
+                    let point = point_at(row, col);
+                    if !self.in_bounds(point) {
+                        continue;
+                    }
+
+                    let centre = col as f64;
+                    let opaque = self[point].area() == &Area::Nothing;
+                    let visible = if opaque {
+                        !shadows.hides_all((centre - 0.5) / depth, (centre + 0.5) / depth)
+                    } else {
+                        !shadows.hides(centre / depth)
+                    };
+                    if visible && in_range(row, col) {
+                        seen.insert(point);
+                    }
+
+                    // Whatever blocks sight here hides the cells behind it, from the next row on.
+                    // Lines that just touch the corner of an empty cell can still see past it.
+                    if opaque {
+                        pending.push(((centre - 0.5) / depth, (centre + 0.5) / depth, 0.0));
+                    }
+                    let (near, far) = (depth - 0.5, depth + 0.5);
+                    if blocks(point, forward) {
+                        pending.push(((centre - 0.5) / far, (centre + 0.5) / far, WALL_OVERLAP));
+                    }
+                    if blocks(point, right) {
+                        pending.push(((centre + 0.5) / far, (centre + 0.5) / near, WALL_OVERLAP));
+                    }
+                    if blocks(point, left) {
+                        pending.push(((centre - 0.5) / near, (centre - 0.5) / far, WALL_OVERLAP));
+                    }
+                }
+
+                for (a, b, overlap) in pending {
+                    shadows.add(a.min(b) - overlap, a.max(b) + overlap);
+                }
+                if shadows.hides_all(0.0, 1.0) {
+                    break;
+                }
+            }
+        }
+
+        seen
+    }
+
+    /// Whether one point can be seen from another
+    ///
+    /// This follows the same rules as `field_of_view`, without a limit on how far away the
+    /// points can be.
+    pub fn line_of_sight(&self, from: impl Into<Point>, to: impl Into<Point>) -> bool {
+        let (from, to) = (from.into(), to.into());
+        let radius = (from.distance(&to).ceil() as usize).max(1);
+        self.field_of_view(from, Some(radius)).contains(&to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::map::cell::Wall;
+
+    /// A room that fills all but the outer ring of the map
+    fn open_room() -> GridMap {
+        let mut map = GridMap::new(13, 13);
+        map.place_room((1, 1), (11, 11)).unwrap();
+        map
+    }
+
+    #[test]
+    fn open_room_is_all_seen() {
+        let map = open_room();
+        let seen = map.field_of_view((6, 6), None);
+
+        // Every cell of the room and the rock around it
+        assert_eq!(13 * 13, seen.len());
+    }
+
+    #[test]
+    fn radius_limits_sight() {
+        let map = open_room();
+        let seen = map.field_of_view((6, 6), Some(3));
+
+        assert!(seen.contains(&Point::new(9, 6)));
+        assert!(seen.contains(&Point::new(8, 8)));
+        assert!(!seen.contains(&Point::new(10, 6)));
+        assert!(!seen.contains(&Point::new(9, 9)));
+        assert!(seen.iter().all(|point| point.distance(&Point::new(6, 6)) <= 3.0));
+    }
+
+    #[test]
+    fn huge_radius_sees_everything() {
+        let map = open_room();
+        assert_eq!(
+            map.field_of_view((6, 6), None),
+            map.field_of_view((6, 6), Some(usize::MAX))
+        );
+    }
+
+    #[test]
+    fn rock_casts_shadows() {
+        let mut map = open_room();
+        map.get_cell_mut((6, 4)).set_area(Area::Nothing);
+        let seen = map.field_of_view((6, 6), None);
+
+        // The rock itself is seen, but not what is straight behind it
+        assert!(seen.contains(&Point::new(6, 4)));
+        assert!(!seen.contains(&Point::new(6, 3)));
+        assert!(!seen.contains(&Point::new(6, 1)));
+        assert!(seen.contains(&Point::new(4, 1)));
+        assert!(!map.line_of_sight((6, 6), (6, 2)));
+        assert!(map.line_of_sight((6, 6), (9, 2)));
+    }
+
+    #[test]
+    fn walls_and_doors_block_sight() {
+        let mut map = open_room();
+        for y in 1..=11 {
+            map.set_wall((6, y), Side::West, Wall::Wall).unwrap();
+        }
+        map.set_wall((6, 6), Side::West, Wall::Door).unwrap();
+
+        let seen = map.field_of_view((3, 6), None);
+        assert!(seen.contains(&Point::new(5, 2)));
+        assert!(seen.contains(&Point::new(5, 10)));
+        for x in 6..=11 {
+            for y in 1..=11 {
+                assert!(!seen.contains(&Point::new(x, y)), "({}, {})", x, y);
+            }
+        }
+
+        assert!(map.line_of_sight((3, 6), (5, 1)));
+        assert!(!map.line_of_sight((3, 6), (7, 6)));
+        assert!(!map.line_of_sight((7, 6), (3, 6)));
+    }
+
+    #[test]
+    fn wall_next_to_viewer() {
+        let mut map = open_room();
+        map.set_wall((6, 6), Side::North, Wall::Wall).unwrap();
+        let seen = map.field_of_view((6, 6), None);
+
+        assert!(!seen.contains(&Point::new(6, 5)));
+        assert!(!seen.contains(&Point::new(6, 1)));
+        assert!(seen.contains(&Point::new(6, 7)));
+        assert!(seen.contains(&Point::new(1, 6)));
+    }
+}
@@ -0,0 +1,569 @@
+//! Vector output for the renderer
+//!
+//! The SVG is drawn in cell units: the point (x, y) of the document is the north-west corner of
+//! cell (x, y), and the `width` and `height` of the document give every cell `scale` pixels. Each
+//! part of the map is put in its own group so that it can be hidden or restyled in an editor.
+use std::fmt::Write;
+
+use base64::Engine;
+use image::Rgba;
+
+use super::autotile::{blob, hatch_lines, neighbour_mask, wall_faces};
+use super::cell::{Point as CornerPoint, Wall};
+use super::label::{path_data, LABEL_HEIGHT};
+use super::renderer::{feature_slot, floor_slot, wall_slot, Renderer, SpriteUse};
+use super::theme::{Sprite, SpriteImage, ThemeSlot};
+use super::Area;
+
+/// The layers of the document, bottom first, as (id, label)
+const LAYERS: [(&str, &str); 7] = [
+    ("floor", "Floor"),
+    ("hatching", "Hatching"),
+    ("grid", "Grid"),
+    ("walls", "Walls"),
+    ("doors", "Doors"),
+    ("features", "Features"),
+    ("labels", "Labels"),
+];
+
+/// Write a colour the way SVG expects it
+fn hex(colour: Rgba<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", colour[0], colour[1], colour[2])
+}
+
+/// Write text so that it can go in an attribute
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The id of the symbol for one of a slot's sprites
+fn sprite_id(slot: ThemeSlot, index: usize) -> String {
+    format!("sprite-{}-{}", slot.name(), index)
+}
+
+/// Turn a sprite into a symbol that can be placed with `<use>`
+fn sprite_symbol(id: &str, sprite: &Sprite) -> String {
+    let tree = match &sprite.image {
+        SpriteImage::Png(bytes) => {
+            // Pictures are kept inside of the document so that it stands on its own
+            return format!(
+                "<symbol id=\"{}\" viewBox=\"0 0 1 1\" preserveAspectRatio=\"none\">\
+                 <image width=\"1\" height=\"1\" preserveAspectRatio=\"none\" \
+                 xlink:href=\"data:image/png;base64,{}\"/></symbol>\n",
+                id,
+                base64::engine::general_purpose::STANDARD.encode(bytes)
+            );
+        }
+        SpriteImage::Svg(..) => sprite
+            .svg_tree()
+            .expect("sprites are checked when they are made"),
+    };
+    let options = usvg::WriteOptions {
+        id_prefix: Some(format!("{}-", id)),
+        ..Default::default()
+    };
+    let text = tree.to_string(&options);
+
+    // Keep what is inside of the outer <svg> element
+    let start = text
+        .find("<svg")
+        .and_then(|svg| text[svg..].find('>').map(|end| svg + end + 1));
+    let end = text.rfind("</svg>");
+    let inner = match (start, end) {
+        (Some(start), Some(end)) if start <= end => &text[start..end],
+        _ => "",
+    };
+
+    let size = tree.size();
+    format!(
+        "<symbol id=\"{}\" viewBox=\"0 0 {} {}\" preserveAspectRatio=\"none\">{}</symbol>\n",
+        id,
+        size.width(),
+        size.height(),
+        inner
+    )
+}
+
+impl Renderer {
+    /// Draw the map as an SVG document
+    ///
+    /// The floor, hatching, grid, walls, doors, features and labels are each drawn in their own
+    /// group (which Inkscape shows as layers). Floor sprites are picked the same way as for
+    /// `render_to_image`, so both show the same floor for the same seed.
+    pub fn to_svg(&self) -> String {
+        let (xmax, ymax) = self.map.get_limits();
+        let (xmax, ymax) = (xmax as u32, ymax as u32);
+        let scale = self.scale as f64;
+        // Sizes of the features, the same as in the raster output
+        let thickness = self.wall_thickness() as f64 / scale;
+        let door_width = self.door_width() as f64 / scale;
+
+        let mut layers: Vec<String> = vec![String::new(); LAYERS.len()];
+
+        // Rock is the background and everything else is drawn over it
+        let [floor, hatching, grid, walls, doors, features, labels] = &mut layers[..] else {
+            unreachable!();
+        };
+        let _ = writeln!(
+            floor,
+            "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            xmax,
+            ymax,
+            hex(self.theme.colour(ThemeSlot::Nothing))
+        );
+
+        for x in 0..xmax {
+            for y in 0..ymax {
+                let area = &self.map.get_cell_ref((x, y)).area;
+                let slot = floor_slot(area);
+                match self.pick_sprite(slot, SpriteUse::Floor, x, y) {
+                    Some((index, turns)) => {
+                        let _ = writeln!(
+                            floor,
+                            "<use xlink:href=\"#{}\" x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" transform=\"rotate({} {} {})\"/>",
+                            sprite_id(slot, index),
+                            x,
+                            y,
+                            turns * 90,
+                            x as f64 + 0.5,
+                            y as f64 + 0.5
+                        );
+                    }
+                    // Rock is already drawn by the background
+                    None if slot == ThemeSlot::Nothing => {}
+                    None => {
+                        let _ = writeln!(
+                            floor,
+                            "<rect x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" fill=\"{}\"/>",
+                            x,
+                            y,
+                            hex(self.theme.colour(slot))
+                        );
+                    }
+                }
+                if let Some(tint) = self.theme.tint(slot) {
+                    let _ = writeln!(
+                        floor,
+                        "<rect x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" fill=\"{}\" fill-opacity=\"0.4\"/>",
+                        x,
+                        y,
+                        hex(tint)
+                    );
+                }
+                if matches!(area, Area::StairsUp | Area::StairsDown) {
+                    self.svg_stairs(x, y, area == &Area::StairsDown, features);
+                }
+                if self.autotile {
+                    let mask = neighbour_mask(&self.map, x, y);
+                    if *area != Area::Nothing {
+                        for [x0, y0, x1, y1] in wall_faces(blob(mask), thickness) {
+                            let _ = writeln!(
+                                walls,
+                                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                                x as f64 + x0,
+                                y as f64 + y0,
+                                x1 - x0,
+                                y1 - y0,
+                                hex(self.theme.colour(ThemeSlot::Wall))
+                            );
+                        }
+                    } else if mask != 0 {
+                        self.svg_hatching(x, y, hatching);
+                    }
+                }
+
+                // Grid lines are only drawn between cells of the same kind of floor, once for
+                // each edge
+                let same_floor = |other: (u32, u32)| {
+                    matches!(area, Area::Room | Area::Corridor)
+                        && self.map.get_cell_ref(other).area == *area
+                };
+                if x + 1 < xmax && same_floor((x + 1, y)) {
+                    let _ = writeln!(
+                        grid,
+                        "<line x1=\"{0}\" y1=\"{1}\" x2=\"{0}\" y2=\"{2}\"/>",
+                        x + 1,
+                        y,
+                        y + 1
+                    );
+                }
+                if y + 1 < ymax && same_floor((x, y + 1)) {
+                    let _ = writeln!(
+                        grid,
+                        "<line x1=\"{0}\" y1=\"{2}\" x2=\"{1}\" y2=\"{2}\"/>",
+                        x,
+                        x + 1,
+                        y + 1
+                    );
+                }
+
+                let cell = self.map.get_cell_ref((x, y));
+                for (vertical, wall) in [(true, cell.vert_wall()), (false, cell.horiz_wall())] {
+                    self.svg_wall(x, y, vertical, wall, thickness, door_width, walls, doors);
+                }
+                self.svg_corner(x, y, cell.point(), thickness, features);
+            }
+        }
+
+        for label in self.visible_labels() {
+            let centre = (label.at.x as f64 + 0.5, label.at.y as f64 + 0.5);
+            let _ = writeln!(
+                labels,
+                "<path aria-label=\"{}\" d=\"{}\"/>",
+                escape(&label.text),
+                path_data(&label.text, LABEL_HEIGHT, centre)
+            );
+        }
+
+        let mut svg = String::new();
+        let _ = writeln!(svg, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+        let _ = writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+             xmlns:inkscape=\"http://www.inkscape.org/namespaces/inkscape\" \
+             width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">",
+            xmax * self.scale,
+            ymax * self.scale,
+            xmax,
+            ymax
+        );
+        let _ = writeln!(svg, "<defs>");
+        for slot in ThemeSlot::ALL {
+            for (index, sprite) in self.theme.sprites(slot).iter().enumerate() {
+                svg.push_str(&sprite_symbol(&sprite_id(slot, index), sprite));
+            }
+        }
+        let _ = writeln!(svg, "</defs>");
+
+        for ((id, label), content) in LAYERS.iter().zip(layers) {
+            let style = match *id {
+                "grid" => format!(
+                    " stroke=\"{}\" stroke-width=\"{}\"",
+                    hex(self.theme.colour(ThemeSlot::Grid)),
+                    2.0 / scale
+                ),
+                "labels" => format!(
+                    " fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\" stroke-linejoin=\"round\" paint-order=\"stroke\"",
+                    hex(self.theme.colour(ThemeSlot::Label)),
+                    hex(self.theme.colour(ThemeSlot::Room)),
+                    2.0 * (self.scale / 12).max(1) as f64 / scale
+                ),
+                "hatching" => format!(
+                    " stroke=\"{}\" stroke-width=\"{}\"",
+                    hex(self.theme.colour(ThemeSlot::Hatching)),
+                    (scale / 20.0).max(1.0) / scale
+                ),
+                _ => String::new(),
+            };
+            let _ = writeln!(
+                svg,
+                "<g id=\"{}\" inkscape:groupmode=\"layer\" inkscape:label=\"{}\"{}>",
+                id, label, style
+            );
+            svg.push_str(&content);
+            let _ = writeln!(svg, "</g>");
+        }
+        let _ = writeln!(svg, "</svg>");
+
+        svg
+    }
+
+    /// Draw the map to an SVG file
+    pub fn draw_to_svg(&self, filename: &str) -> Result<(), std::io::Error> {
+        std::fs::write(filename, self.to_svg())
+    }
+
+    /// Place one of a slot's sprites centred on a point, turned a quarter turn if `turned`.
+    /// Returns false, without drawing, if the theme has no sprites for the slot.
+    #[allow(clippy::too_many_arguments)]
+    fn svg_overlay(
+        &self,
+        slot: ThemeSlot,
+        usage: SpriteUse,
+        x: u32,
+        y: u32,
+        centre: (f64, f64),
+        turned: bool,
+        layer: &mut String,
+    ) -> bool {
+        let Some((index, _)) = self.pick_sprite(slot, usage, x, y) else {
+            return false;
+        };
+        let _ = writeln!(
+            layer,
+            "<use xlink:href=\"#{}\" x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" transform=\"rotate({} {} {})\"/>",
+            sprite_id(slot, index),
+            centre.0 - 0.5,
+            centre.1 - 0.5,
+            if turned { 90 } else { 0 },
+            centre.0,
+            centre.1
+        );
+        true
+    }
+
+    /// Draw the wall on the west (vertical) or north (horizontal) edge of a cell
+    #[allow(clippy::too_many_arguments)]
+    fn svg_wall(
+        &self,
+        x: u32,
+        y: u32,
+        vertical: bool,
+        wall: &Wall,
+        thickness: f64,
+        door_width: f64,
+        walls: &mut String,
+        doors: &mut String,
+    ) {
+        let wall = self.view.wall(wall);
+        let Some(slot) = wall_slot(wall) else {
+            return;
+        };
+
+        // Sprites are drawn for north edges and turned for west edges
+        let (centre, usage) = if vertical {
+            ((x as f64, y as f64 + 0.5), SpriteUse::WestEdge)
+        } else {
+            ((x as f64 + 0.5, y as f64), SpriteUse::NorthEdge)
+        };
+        let layer = if slot == ThemeSlot::Wall {
+            &mut *walls
+        } else {
+            &mut *doors
+        };
+        if self.svg_overlay(slot, usage, x, y, centre, vertical, layer) {
+            return;
+        }
+
+        let wall_colour = hex(self.theme.colour(ThemeSlot::Wall));
+        let door_colour = match wall {
+            Wall::Door | Wall::SecretDoor => Some(self.theme.colour(slot)),
+            _ => None,
+        };
+
+        // Rectangles as (across, along, size across, size along), turned the right way round
+        let (across, along) = if vertical { (x, y) } else { (y, x) };
+        let rect = |a: f64, l: f64, size_a: f64, size_l: f64| {
+            if vertical {
+                (a, l, size_a, size_l)
+            } else {
+                (l, a, size_l, size_a)
+            }
+        };
+
+        let (rx, ry, width, height) = rect(
+            across as f64 - thickness / 2.0,
+            along as f64,
+            thickness,
+            1.0,
+        );
+        let _ = writeln!(
+            walls,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+            rx, ry, width, height, wall_colour
+        );
+
+        if let Some(colour) = door_colour {
+            let (rx, ry, width, height) = rect(
+                across as f64 - door_width / 2.0,
+                along as f64 + 0.25,
+                door_width,
+                0.5,
+            );
+            let _ = writeln!(
+                doors,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\"/>",
+                rx,
+                ry,
+                width,
+                height,
+                hex(colour),
+                wall_colour,
+                1.0 / self.scale as f64
+            );
+        }
+    }
+
+    /// Hatch a rock cell, like the raster hatching
+    fn svg_hatching(&self, x: u32, y: u32, hatching: &mut String) {
+        if let Some((index, turns)) = self.pick_sprite(ThemeSlot::Hatching, SpriteUse::Hatching, x, y) {
+            let _ = writeln!(
+                hatching,
+                "<use xlink:href=\"#{}\" x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" transform=\"rotate({} {} {})\"/>",
+                sprite_id(ThemeSlot::Hatching, index),
+                x,
+                y,
+                turns * 90,
+                x as f64 + 0.5,
+                y as f64 + 0.5
+            );
+            return;
+        }
+        for [x0, y0, x1, y1] in hatch_lines(self.hatch_hash(x, y)) {
+            let _ = writeln!(
+                hatching,
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"/>",
+                x as f64 + x0,
+                y as f64 + y0,
+                x as f64 + x1,
+                y as f64 + y1
+            );
+        }
+    }
+
+    /// Draw a flight of stairs over a cell, like the raster stairs
+    fn svg_stairs(&self, x: u32, y: u32, down: bool, features: &mut String) {
+        const STEPS: usize = 4;
+
+        let slot = if down {
+            ThemeSlot::StairsDown
+        } else {
+            ThemeSlot::StairsUp
+        };
+        let centre = (x as f64 + 0.5, y as f64 + 0.5);
+        if self.svg_overlay(slot, SpriteUse::Overlay, x, y, centre, false, features) {
+            return;
+        }
+        let colour = hex(self.theme.colour(slot));
+
+        let step_height = 1.0 / (2 * STEPS + 1) as f64;
+        for step in 0..STEPS {
+            let size = if down { STEPS - step } else { step + 1 };
+            let width = size as f64 / STEPS as f64 - 0.125;
+            let _ = writeln!(
+                features,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                x as f64 + (1.0 - width) / 2.0,
+                y as f64 + (2 * step + 1) as f64 * step_height,
+                width,
+                step_height,
+                colour
+            );
+        }
+    }
+
+    /// Draw the feature on the north-west corner of a cell
+    fn svg_corner(
+        &self,
+        x: u32,
+        y: u32,
+        feature: &CornerPoint,
+        thickness: f64,
+        features: &mut String,
+    ) {
+        let Some(slot) = feature_slot(feature) else {
+            return;
+        };
+        if self.svg_overlay(
+            slot,
+            SpriteUse::Corner,
+            x,
+            y,
+            (x as f64, y as f64),
+            false,
+            features,
+        ) {
+            return;
+        }
+
+        let radius = (2.0 / self.scale as f64).max(0.25);
+        let colour = hex(self.theme.colour(slot));
+        let outline = format!(
+            "stroke=\"{}\" stroke-width=\"{}\"",
+            hex(self.theme.colour(ThemeSlot::Wall)),
+            thickness / 2.0
+        );
+
+        let _ = match feature {
+            CornerPoint::Nothing => return,
+            CornerPoint::Pillar => writeln!(
+                features,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" {}/>",
+                x as f64 - radius,
+                y as f64 - radius,
+                radius * 2.0,
+                radius * 2.0,
+                colour,
+                outline
+            ),
+            CornerPoint::Column | CornerPoint::Brazier => writeln!(
+                features,
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" {}/>",
+                x, y, radius, colour, outline
+            ),
+            CornerPoint::Statue => writeln!(
+                features,
+                "<polygon points=\"{},{} {},{} {},{} {},{}\" fill=\"{}\" {}/>",
+                x as f64,
+                y as f64 - radius,
+                x as f64 + radius,
+                y as f64,
+                x as f64,
+                y as f64 + radius,
+                x as f64 - radius,
+                y as f64,
+                colour,
+                outline
+            ),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::map::cell::Side;
+    use crate::map::{GridMap, Label, Theme, View};
+
+    fn small_map() -> GridMap {
+        let mut map = GridMap::new(6, 5);
+        map.place_room((1, 1), (4, 3)).unwrap();
+        map.set_wall((3, 1), Side::West, Wall::SecretDoor).unwrap();
+        map
+    }
+
+    #[test]
+    fn svg_is_valid_and_layered() {
+        let map = small_map();
+        let svg = Renderer::new(&map, 20).to_svg();
+
+        let tree = usvg::Tree::from_str(&svg, &usvg::Options::default()).unwrap();
+        assert_eq!(120.0, tree.size().width());
+        assert_eq!(100.0, tree.size().height());
+        for (id, _) in LAYERS {
+            assert!(svg.contains(&format!("<g id=\"{}\"", id)), "{}", id);
+        }
+        // One sprite for each of the 12 floor cells
+        assert_eq!(12, svg.matches("<use ").count());
+    }
+
+    #[test]
+    fn players_do_not_see_secret_doors() {
+        let map = small_map();
+        let secret = hex(Theme::default().colour(ThemeSlot::SecretDoor));
+
+        let gm = Renderer::new(&map, 20).to_svg();
+        let player = Renderer::new(&map, 20).with_view(View::Player).to_svg();
+        assert!(gm.contains(&secret));
+        assert!(!player.contains(&secret));
+    }
+
+    #[test]
+    fn labels_are_paths() {
+        let map = small_map();
+        let labels = [Label::new("Crypt & Tomb", (2, 2)), Label::note("7", (3, 2))];
+
+        let gm = Renderer::new(&map, 20).with_labels(labels.clone()).to_svg();
+        assert!(usvg::Tree::from_str(&gm, &usvg::Options::default()).is_ok());
+        assert!(gm.contains("aria-label=\"Crypt &amp; Tomb\""));
+        assert!(gm.contains("aria-label=\"7\""));
+        assert!(!gm.contains("<text"));
+
+        let player = Renderer::new(&map, 20).with_labels(labels).with_view(View::Player).to_svg();
+        assert!(!player.contains("aria-label=\"7\""));
+    }
+}
@@ -0,0 +1,534 @@
+//! Text pictures of maps, for pasting into chat, issues and terminals
+//!
+//! A map of `w` by `h` cells is drawn as `2h + 1` lines of `2w + 1` characters. Cells are on the
+//! odd lines and columns, the edges between them are in between, and the corners where four
+//! cells meet are on the even lines and columns:
+//!
+//! ```text
+//! #########
+//! #.......#
+//! #.......#
+//! #....<..#
+//! ###+#####
+//! ###,#####
+//! #########
+//! ```
+//!
+//! Here a room of 4 by 2 cells has stairs going up, and a door in its south wall opens on to a
+//! corridor. Edges and corners without a wall or a feature are filled in to match the cells
+//! around them, and are read back as nothing at all, so the picture can be edited freely in a
+//! text editor. Every cell, wall and corner feature is kept when a map is written and read back.
+use super::cell::{Point as CornerPoint, Wall};
+use super::{Area, GridMap, View};
+use crate::error::{Result, RpgError};
+
+/// Box drawing junctions, indexed by the lines that leave a corner: 1 for north, 2 for east, 4
+/// for south and 8 for west
+const LIGHT_JUNCTIONS: [char; 16] = [
+    ' ', '╵', '╶', '└', '╷', '│', '┌', '├', '╴', '┘', '─', '┴', '┐', '┤', '┬', '┼',
+];
+const HEAVY_JUNCTIONS: [char; 16] = [
+    ' ', '╹', '╺', '┗', '╻', '┃', '┏', '┣', '╸', '┛', '━', '┻', '┓', '┫', '┳', '╋',
+];
+
+/// The characters that a map is drawn with
+///
+/// Things drawn on edges have a character for vertical edges (between cells side by side) and
+/// one for horizontal edges (between cells one above the other).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glyphs {
+    pub nothing: char,
+    pub entrance: char,
+    pub room: char,
+    pub corridor: char,
+    pub stairs_up: char,
+    pub stairs_down: char,
+    pub wall: (char, char),
+    pub door: (char, char),
+    pub secret_door: (char, char),
+    /// Lines drawn where the floor meets rock without a wall, if any
+    pub outline: Option<(char, char)>,
+    pub pillar: char,
+    pub column: char,
+    pub statue: char,
+    pub brazier: char,
+    /// Whether corners where lines meet are drawn with box drawing junctions. Otherwise they are
+    /// drawn like the walls that run through them.
+    pub box_corners: bool,
+}
+
+impl Default for Glyphs {
+    fn default() -> Self {
+        Glyphs::ascii()
+    }
+}
+
+impl Glyphs {
+    /// Plain ASCII: `#` for rock, `.` for rooms, `+` for doors and `<` and `>` for stairs
+    pub fn ascii() -> Glyphs {
+        Glyphs {
+            nothing: '#',
+            entrance: 'E',
+            room: '.',
+            corridor: ',',
+            stairs_up: '<',
+            stairs_down: '>',
+            wall: ('|', '-'),
+            door: ('+', '+'),
+            secret_door: ('S', 'S'),
+            outline: None,
+            pillar: 'O',
+            column: 'o',
+            statue: '&',
+            brazier: '*',
+            box_corners: false,
+        }
+    }
+
+    /// Box drawing characters, with light lines round the floor and heavy lines for walls
+    ///
+    /// Rock is left blank, so outlines also frame the whole map to keep its size when spaces are
+    /// stripped from the ends of lines.
+    pub fn unicode() -> Glyphs {
+        Glyphs {
+            nothing: ' ',
+            entrance: '◊',
+            room: '·',
+            corridor: '░',
+            stairs_up: '▲',
+            stairs_down: '▼',
+            wall: ('┃', '━'),
+            door: ('╫', '╪'),
+            secret_door: ('┆', '┄'),
+            outline: Some(('│', '─')),
+            pillar: '■',
+            column: '●',
+            statue: '♦',
+            brazier: '☼',
+            box_corners: true,
+        }
+    }
+
+    /// The characters for a view of the map. The player view draws secret doors as plain walls,
+    /// so they read back as walls too.
+    pub fn for_view(mut self, view: View) -> Glyphs {
+        if !view.shows_secrets() {
+            self.secret_door = self.wall;
+        }
+        self
+    }
+
+    fn area(&self, area: &Area) -> char {
+        match area {
+            Area::Nothing => self.nothing,
+            Area::Entrance => self.entrance,
+            Area::Room => self.room,
+            Area::Corridor => self.corridor,
+            Area::StairsUp => self.stairs_up,
+            Area::StairsDown => self.stairs_down,
+        }
+    }
+
+    fn read_area(&self, c: char) -> Option<Area> {
+        [
+            Area::Nothing,
+            Area::Entrance,
+            Area::Room,
+            Area::Corridor,
+            Area::StairsUp,
+            Area::StairsDown,
+        ]
+        .into_iter()
+        .find(|area| self.area(area) == c)
+    }
+
+    fn feature(&self, feature: &CornerPoint) -> Option<char> {
+        match feature {
+            CornerPoint::Nothing => None,
+            CornerPoint::Pillar => Some(self.pillar),
+            CornerPoint::Column => Some(self.column),
+            CornerPoint::Statue => Some(self.statue),
+            CornerPoint::Brazier => Some(self.brazier),
+        }
+    }
+
+    fn read_feature(&self, c: char) -> CornerPoint {
+        [
+            CornerPoint::Pillar,
+            CornerPoint::Column,
+            CornerPoint::Statue,
+            CornerPoint::Brazier,
+        ]
+        .into_iter()
+        .find(|feature| self.feature(feature) == Some(c))
+        .unwrap_or(CornerPoint::Nothing)
+    }
+
+    fn read_wall(&self, c: char) -> Wall {
+        let is = |(vertical, horizontal): (char, char)| c == vertical || c == horizontal;
+        if is(self.wall) {
+            Wall::Wall
+        } else if is(self.door) {
+            Wall::Door
+        } else if is(self.secret_door) {
+            Wall::SecretDoor
+        } else {
+            Wall::Nothing
+        }
+    }
+
+    /// The line that a character on an edge draws: 0 for none, 1 for an outline, 2 for a door
+    /// and 3 for a wall. Box drawing junctions are only heavy for walls.
+    fn line(&self, c: char) -> u8 {
+        match (self.read_wall(c), self.outline) {
+            (Wall::Nothing, Some((vertical, horizontal))) if c == vertical || c == horizontal => 1,
+            (Wall::Nothing, _) => 0,
+            (Wall::Door | Wall::SecretDoor, _) => 2,
+            (Wall::Wall, _) => 3,
+        }
+    }
+
+    /// The character for a space between cells with nothing in it, given the cells around it
+    fn fill(&self, areas: &[Option<&Area>]) -> char {
+        let open: Vec<&Area> = areas
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|area| **area != Area::Nothing)
+            .collect();
+        if open.len() < areas.len() {
+            self.nothing
+        } else if open.iter().all(|area| **area == Area::Corridor) {
+            self.corridor
+        } else {
+            self.room
+        }
+    }
+}
+
+impl GridMap {
+    /// Draw the map as text with the given characters
+    ///
+    /// Blanks at the ends of lines are left off.
+    pub fn to_text(&self, glyphs: &Glyphs) -> String {
+        let (xmax, ymax) = self.get_limits();
+        // The area of a cell, or `None` off the map. Cells before the first row or column are
+        // asked for with wrapping_sub, which puts them off the map too.
+        let area = |x: usize, y: usize| -> Option<&Area> {
+            (x < xmax && y < ymax).then(|| self.get_cell_ref((x as u32, y as u32)).area())
+        };
+        let mut rows = vec![vec![glyphs.nothing; 2 * xmax + 1]; 2 * ymax + 1];
+
+        for y in 0..=ymax {
+            for x in 0..=xmax {
+                if x < xmax && y < ymax {
+                    let cell = self.get_cell_ref((x as u32, y as u32));
+                    rows[2 * y + 1][2 * x + 1] = glyphs.area(cell.area());
+                }
+
+                // The west edge of (x, y) and the north edge, which the cell owns if it is on the
+                // map
+                let owned = (x < xmax && y < ymax).then(|| self.get_cell_ref((x as u32, y as u32)));
+                for vertical in [true, false] {
+                    let (row, column, before) = if vertical {
+                        if y == ymax {
+                            continue;
+                        }
+                        (2 * y + 1, 2 * x, area(x.wrapping_sub(1), y))
+                    } else {
+                        if x == xmax {
+                            continue;
+                        }
+                        (2 * y, 2 * x + 1, area(x, y.wrapping_sub(1)))
+                    };
+                    let wall = owned.map_or(&Wall::Nothing, |cell| {
+                        if vertical {
+                            cell.vert_wall()
+                        } else {
+                            cell.horiz_wall()
+                        }
+                    });
+                    let pick = |(v, h): (char, char)| if vertical { v } else { h };
+                    let after = area(x, y);
+                    let border = before.is_none() || after.is_none();
+                    let open =
+                        |area: Option<&Area>| area.is_some_and(|area| *area != Area::Nothing);
+                    rows[row][column] = match (wall, glyphs.outline) {
+                        (Wall::Wall, _) => pick(glyphs.wall),
+                        (Wall::Door, _) => pick(glyphs.door),
+                        (Wall::SecretDoor, _) => pick(glyphs.secret_door),
+                        (Wall::Nothing, Some(outline)) if open(before) != open(after) || border => {
+                            pick(outline)
+                        }
+                        (Wall::Nothing, _) => glyphs.fill(&[before, after]),
+                    };
+                }
+            }
+        }
+
+        // Corners go last since they are drawn to match the edges around them
+        for y in 0..=ymax {
+            for x in 0..=xmax {
+                let (row, column) = (2 * y, 2 * x);
+                let feature = (x < xmax && y < ymax)
+                    .then(|| glyphs.feature(self.get_cell_ref((x as u32, y as u32)).point()))
+                    .flatten();
+                if let Some(c) = feature {
+                    rows[row][column] = c;
+                    continue;
+                }
+
+                let north = if y > 0 {
+                    glyphs.line(rows[row - 1][column])
+                } else {
+                    0
+                };
+                let east = if x < xmax {
+                    glyphs.line(rows[row][column + 1])
+                } else {
+                    0
+                };
+                let south = if y < ymax {
+                    glyphs.line(rows[row + 1][column])
+                } else {
+                    0
+                };
+                let west = if x > 0 {
+                    glyphs.line(rows[row][column - 1])
+                } else {
+                    0
+                };
+                let mut lines = [north, east, south, west];
+                if !glyphs.box_corners {
+                    // Doors only have box drawing lines through them
+                    lines = lines.map(|line| if line == 2 { 0 } else { line });
+                }
+                let heaviest = lines.iter().copied().max().unwrap_or(0);
+
+                rows[row][column] = if heaviest == 0 {
+                    let around = [
+                        area(x.wrapping_sub(1), y.wrapping_sub(1)),
+                        area(x, y.wrapping_sub(1)),
+                        area(x.wrapping_sub(1), y),
+                        area(x, y),
+                    ];
+                    glyphs.fill(&around)
+                } else if glyphs.box_corners {
+                    let index = lines
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, line)| **line > 0)
+                        .fold(0, |index, (bit, _)| index | 1 << bit);
+                    if heaviest == 3 {
+                        HEAVY_JUNCTIONS[index]
+                    } else {
+                        LIGHT_JUNCTIONS[index]
+                    }
+                } else {
+                    // Drawn as the heaviest line through the corner, across before up and down
+                    let kind = if heaviest == 3 {
+                        glyphs.wall
+                    } else {
+                        glyphs.outline.unwrap_or(glyphs.wall)
+                    };
+                    if lines[1] == heaviest || lines[3] == heaviest {
+                        kind.1
+                    } else {
+                        kind.0
+                    }
+                };
+            }
+        }
+
+        let mut text = String::new();
+        for row in rows {
+            let line: String = row.into_iter().collect();
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Read a map drawn as text with the given characters
+    ///
+    /// Short lines are taken to be filled out with rock, since editors often strip spaces from
+    /// the ends of lines, and blank lines at the end are left off. Any character on an edge or
+    /// corner that isn't a wall, door or feature is read as nothing, but every cell must be one
+    /// of the area characters.
+    pub fn from_text(text: &str, glyphs: &Glyphs) -> Result<GridMap> {
+        let mut rows: Vec<Vec<char>> = text
+            .lines()
+            .map(|line| line.trim_end_matches('\r').chars().collect())
+            .collect();
+        while rows.last().is_some_and(|row| row.iter().all(|c| c.is_whitespace())) {
+            rows.pop();
+        }
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let (xmax, ymax) = (width / 2, rows.len() / 2);
+        if xmax == 0 || ymax == 0 {
+            return Err(RpgError::Parse(
+                1,
+                "a map needs at least 3 lines of 3 characters".to_string(),
+            ));
+        }
+        let at = |row: usize, column: usize| -> char {
+            rows.get(row)
+                .and_then(|row| row.get(column))
+                .copied()
+                .unwrap_or(glyphs.nothing)
+        };
+
+        let mut map = GridMap::new(xmax, ymax);
+        for y in 0..ymax {
+            for x in 0..xmax {
+                let c = at(2 * y + 1, 2 * x + 1);
+                let area = glyphs.read_area(c).ok_or_else(|| {
+                    RpgError::Parse(
+                        2 * y + 2,
+                        format!("unknown area '{}' in column {}", c, 2 * x + 2),
+                    )
+                })?;
+                let cell = map.get_cell_mut((x as u32, y as u32));
+                cell.set_area(area);
+                cell.set_vert_wall(glyphs.read_wall(at(2 * y + 1, 2 * x)));
+                cell.set_horiz_wall(glyphs.read_wall(at(2 * y, 2 * x + 1)));
+                cell.set_point(glyphs.read_feature(at(2 * y, 2 * x)));
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::map::cell::Side;
+    use crate::map::{Connectivity, RouteMethod};
+
+    /// The map drawn in the module documentation
+    const ROOM: &str = "\
+#########
+#.......#
+#.......#
+#....<..#
+###+#####
+###,#####
+#########
+";
+
+    #[test]
+    fn draws_ascii() {
+        let mut map = GridMap::from_text(ROOM, &Glyphs::ascii()).unwrap();
+        assert_eq!(&Area::StairsUp, map.get_cell_ref((2, 1)).area());
+        assert_eq!(&Area::Room, map.get_cell_ref((1, 1)).area());
+        assert_eq!(&Wall::Door, map.get_wall((1, 1), Side::South).unwrap());
+        assert_eq!(&Area::Corridor, map.get_cell_ref((1, 2)).area());
+        assert_eq!(ROOM, map.to_text(&Glyphs::ascii()));
+
+        map.set_wall((2, 1), Side::East, Wall::Wall).unwrap();
+        map.get_cell_mut((2, 1)).set_point(CornerPoint::Column);
+        assert_eq!(
+            "\
+#########
+#.......#
+#...o.|.#
+#....<|.#
+###+##|##
+###,#####
+#########
+",
+            map.to_text(&Glyphs::ascii())
+        );
+    }
+
+    #[test]
+    fn draws_box_lines() {
+        let mut map = GridMap::from_text(ROOM, &Glyphs::ascii()).unwrap();
+        map.set_wall((0, 0), Side::West, Wall::Wall).unwrap();
+        assert_eq!(
+            "\
+┏───────┐
+┃·······│
+┃·······│
+│····▲··│
+├─┬╪┬───┤
+│ │░│   │
+└─┴─┴───┘
+",
+            map.to_text(&Glyphs::unicode())
+        );
+    }
+
+    #[test]
+    fn maps_survive_a_round_trip() {
+        let mut map = GridMap::new(40, 30);
+        let mut rng = StdRng::seed_from_u64(11);
+        map.generate_dungeon(
+            20,
+            6,
+            true,
+            RouteMethod::Pathfind(Default::default()),
+            1,
+            Connectivity::Nearby,
+            &mut rng,
+        );
+        map.place_entrance((20, 15)).unwrap();
+        map.set_wall((10, 10), Side::North, Wall::SecretDoor)
+            .unwrap();
+        map.get_cell_mut((5, 5)).set_point(CornerPoint::Statue);
+        map.get_cell_mut((6, 5)).set_point(CornerPoint::Brazier);
+
+        for glyphs in [Glyphs::ascii(), Glyphs::unicode()] {
+            let text = map.to_text(&glyphs);
+            assert_eq!(map, GridMap::from_text(&text, &glyphs).unwrap());
+
+            // Editors strip spaces from the ends of lines
+            let trimmed: String = text
+                .lines()
+                .map(|line| format!("{}\n", line.trim_end()))
+                .collect();
+            assert_eq!(map, GridMap::from_text(&trimmed, &glyphs).unwrap());
+        }
+    }
+
+    #[test]
+    fn trailing_blank_lines_are_left_off() {
+        let text = format!("{}\n  \r\n\n", ROOM);
+        let map = GridMap::from_text(&text, &Glyphs::ascii()).unwrap();
+        assert_eq!((4, 3), map.get_limits());
+        assert_eq!(ROOM, map.to_text(&Glyphs::ascii()));
+    }
+
+    #[test]
+    fn player_view_hides_secret_doors() {
+        let mut map = GridMap::from_text(ROOM, &Glyphs::ascii()).unwrap();
+        map.set_wall((1, 1), Side::West, Wall::SecretDoor).unwrap();
+        let mut walled = map.clone();
+        walled.set_wall((1, 1), Side::West, Wall::Wall).unwrap();
+
+        for glyphs in [Glyphs::ascii(), Glyphs::unicode()] {
+            let gm = glyphs.clone().for_view(View::Gm);
+            assert_eq!(map, GridMap::from_text(&map.to_text(&gm), &gm).unwrap());
+
+            let player = glyphs.clone().for_view(View::Player);
+            let text = map.to_text(&player);
+            assert_eq!(walled.to_text(&glyphs), text);
+            assert_eq!(walled, GridMap::from_text(&text, &glyphs).unwrap());
+        }
+    }
+
+    #[test]
+    fn unknown_cells_are_errors() {
+        let text = "###\n#?#\n###\n";
+        assert!(matches!(
+            GridMap::from_text(text, &Glyphs::ascii()),
+            Err(RpgError::Parse(2, _))
+        ));
+        assert!(GridMap::from_text("", &Glyphs::ascii()).is_err());
+    }
+}
@@ -0,0 +1,566 @@
+//! Themes: the sprites and colours that the renderer draws a map with
+//!
+//! A theme has a slot for each kind of area, wall and corner feature. Each slot has a colour and
+//! any number of sprites, which are SVG or PNG images with a weight; the renderer picks one of a
+//! slot's sprites for each cell, more often the higher its weight. Themes are usually written as a
+//! plain text manifest in a directory next to their sprites:
+//!
+//! ```text
+//! rpgtheme 1
+//! # slot   weight  file
+//! sprite room     3  flagstones.svg
+//! sprite room     1  cracked.png
+//! sprite door     1  door.svg
+//! # slot      colour
+//! colour nothing  #2b2118
+//! colour wall     #000000
+//! tint corridor   #967846
+//! clear stairs-up
+//! ```
+//!
+//! A manifest starts from the default theme. The first sprite given for a slot replaces the
+//! slot's default sprites, `tint` blends the slot's cells towards a colour and `clear` removes a
+//! slot's sprites and tint so that it is drawn in its colour. Sprite files are found relative to
+//! the manifest. Lines starting with `#` are comments.
+//!
+//! Floor sprites (nothing, entrance, room and corridor) fill their cell and are turned at random.
+//! The rest are drawn over the floor without turning: stairs fill their cell, wall and door
+//! sprites are one cell in size and centred on the middle of the edge, drawn for a north edge and
+//! turned for west edges, and feature sprites are one cell in size and centred on the corner.
+//! Hatching sprites fill the rock cells that touch the floor, in place of the drawn strokes.
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+
+use crate::error::{Result, RpgError};
+
+/// Name at the start of every theme manifest
+const FORMAT_NAME: &str = "rpgtheme";
+
+/// Newest manifest format version that can be read
+const THEME_VERSION: u32 = 1;
+
+/// The manifest that `Theme::load` looks for in a theme directory
+pub const MANIFEST_NAME: &str = "theme.txt";
+
+// The default floor
+const FLOOR_STONE: &str = include_str!("assets/floor-stone.svg");
+const FLOOR_STONE_2: &str = include_str!("assets/floor-stone-2.svg");
+
+/// The parts of a map that a theme can give sprites and colours to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThemeSlot {
+    Nothing,
+    Entrance,
+    Room,
+    Corridor,
+    StairsUp,
+    StairsDown,
+    Wall,
+    Door,
+    SecretDoor,
+    Pillar,
+    Column,
+    Statue,
+    Brazier,
+    /// The lines between floor cells
+    Grid,
+    /// The strokes drawn over rock next to the floor
+    Hatching,
+    /// The text of labels, which is outlined in the room colour
+    Label,
+}
+
+impl ThemeSlot {
+    /// Every slot, in order
+    pub const ALL: [ThemeSlot; 16] = [
+        ThemeSlot::Nothing,
+        ThemeSlot::Entrance,
+        ThemeSlot::Room,
+        ThemeSlot::Corridor,
+        ThemeSlot::StairsUp,
+        ThemeSlot::StairsDown,
+        ThemeSlot::Wall,
+        ThemeSlot::Door,
+        ThemeSlot::SecretDoor,
+        ThemeSlot::Pillar,
+        ThemeSlot::Column,
+        ThemeSlot::Statue,
+        ThemeSlot::Brazier,
+        ThemeSlot::Grid,
+        ThemeSlot::Hatching,
+        ThemeSlot::Label,
+    ];
+
+    /// The name of the slot in theme manifests
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThemeSlot::Nothing => "nothing",
+            ThemeSlot::Entrance => "entrance",
+            ThemeSlot::Room => "room",
+            ThemeSlot::Corridor => "corridor",
+            ThemeSlot::StairsUp => "stairs-up",
+            ThemeSlot::StairsDown => "stairs-down",
+            ThemeSlot::Wall => "wall",
+            ThemeSlot::Door => "door",
+            ThemeSlot::SecretDoor => "secret-door",
+            ThemeSlot::Pillar => "pillar",
+            ThemeSlot::Column => "column",
+            ThemeSlot::Statue => "statue",
+            ThemeSlot::Brazier => "brazier",
+            ThemeSlot::Grid => "grid",
+            ThemeSlot::Hatching => "hatching",
+            ThemeSlot::Label => "label",
+        }
+    }
+
+    fn from_name(name: &str, line: usize) -> Result<ThemeSlot> {
+        ThemeSlot::ALL
+            .into_iter()
+            .find(|slot| slot.name() == name)
+            .ok_or_else(|| RpgError::Parse(line, format!("unknown slot '{}'", name)))
+    }
+}
+
+/// The picture in a sprite
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum SpriteImage {
+    /// SVG text, with the directory that images inside of it are found relative to
+    Svg(String, Option<PathBuf>),
+    /// The bytes of a PNG file
+    Png(Vec<u8>),
+}
+
+/// One picture that a theme can draw in a slot, with its weight among the slot's sprites
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sprite {
+    pub(super) image: SpriteImage,
+    pub(super) weight: u32,
+}
+
+impl Sprite {
+    /// Make a sprite from SVG text
+    pub fn svg(text: &str, weight: u32) -> Result<Sprite> {
+        Sprite::checked(SpriteImage::Svg(text.to_string(), None), weight)
+    }
+
+    /// Make a sprite from the bytes of a PNG file
+    pub fn png(bytes: Vec<u8>, weight: u32) -> Result<Sprite> {
+        Sprite::checked(SpriteImage::Png(bytes), weight)
+    }
+
+    /// Load an SVG or PNG sprite from a file, picked by its extension
+    pub fn load(path: impl AsRef<Path>, weight: u32) -> Result<Sprite> {
+        let path = path.as_ref();
+        let is_svg = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"));
+        let image = if is_svg {
+            let resources = path.parent().map(Path::to_path_buf);
+            SpriteImage::Svg(std::fs::read_to_string(path)?, resources)
+        } else {
+            SpriteImage::Png(std::fs::read(path)?)
+        };
+        Sprite::checked(image, weight).map_err(|e| match e {
+            RpgError::Sprite(reason) => RpgError::Sprite(format!("{}: {}", path.display(), reason)),
+            e => e,
+        })
+    }
+
+    /// Make sure that a sprite can be drawn before keeping it
+    fn checked(image: SpriteImage, weight: u32) -> Result<Sprite> {
+        if weight == 0 {
+            return Err(RpgError::Sprite("weight must be at least 1".to_string()));
+        }
+        let sprite = Sprite { image, weight };
+        match &sprite.image {
+            SpriteImage::Svg(..) => {
+                sprite.svg_tree()?;
+            }
+            SpriteImage::Png(bytes) => {
+                image::load_from_memory(bytes).map_err(|e| RpgError::Sprite(e.to_string()))?;
+            }
+        }
+        Ok(sprite)
+    }
+
+    /// Parse an SVG sprite
+    pub(super) fn svg_tree(&self) -> Result<usvg::Tree> {
+        let SpriteImage::Svg(text, resources) = &self.image else {
+            return Err(RpgError::Sprite("not an SVG sprite".to_string()));
+        };
+        let options = usvg::Options {
+            resources_dir: resources.clone(),
+            ..Default::default()
+        };
+        usvg::Tree::from_str(text, &options).map_err(|e| RpgError::Sprite(e.to_string()))
+    }
+
+    /// Draw the sprite as a square image `size` pixels across
+    pub(super) fn rasterise(&self, size: u32) -> RgbaImage {
+        let size = size.max(1);
+        match &self.image {
+            SpriteImage::Svg(..) => {
+                let mut pixmap =
+                    tiny_skia::Pixmap::new(size, size).expect("sprite size is not zero");
+                if let Ok(tree) = self.svg_tree() {
+                    let (width, height) = (tree.size().width(), tree.size().height());
+                    let transform =
+                        tiny_skia::Transform::from_scale(size as f32 / width, size as f32 / height);
+                    resvg::render(&tree, transform, &mut pixmap.as_mut());
+                }
+                // tiny-skia premultiplies alpha, while images don't
+                let pixels = pixmap
+                    .pixels()
+                    .iter()
+                    .flat_map(|pixel| {
+                        let pixel = pixel.demultiply();
+                        [pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()]
+                    })
+                    .collect();
+                RgbaImage::from_vec(size, size, pixels).expect("pixmap matches the image size")
+            }
+            SpriteImage::Png(bytes) => match image::load_from_memory(bytes) {
+                Ok(image) => image::imageops::resize(
+                    &image.to_rgba8(),
+                    size,
+                    size,
+                    image::imageops::FilterType::Triangle,
+                ),
+                Err(_) => RgbaImage::new(size, size),
+            },
+        }
+    }
+}
+
+/// The sprites and colours that a map is drawn with
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    sprites: BTreeMap<ThemeSlot, Vec<Sprite>>,
+    colours: BTreeMap<ThemeSlot, Rgba<u8>>,
+    tints: BTreeMap<ThemeSlot, Rgba<u8>>,
+}
+
+impl Default for Theme {
+    /// Stone floors, dark rock and plain walls
+    fn default() -> Self {
+        let mut theme = Theme {
+            sprites: BTreeMap::new(),
+            colours: BTreeMap::new(),
+            tints: BTreeMap::new(),
+        };
+
+        for slot in [ThemeSlot::Room, ThemeSlot::Corridor] {
+            for sprite in [FLOOR_STONE, FLOOR_STONE_2] {
+                theme.add_sprite(
+                    slot,
+                    Sprite::svg(sprite, 1).expect("built in sprites are valid"),
+                );
+            }
+        }
+        theme.set_tint(ThemeSlot::Corridor, Some(Rgba([150, 120, 70, 255])));
+
+        let colours = [
+            (ThemeSlot::Nothing, [25, 25, 25]),
+            (ThemeSlot::Entrance, [255, 119, 0]),
+            (ThemeSlot::Room, [200, 200, 200]),
+            (ThemeSlot::Corridor, [200, 200, 200]),
+            (ThemeSlot::StairsUp, [20, 20, 20]),
+            (ThemeSlot::StairsDown, [20, 20, 20]),
+            (ThemeSlot::Wall, [20, 20, 20]),
+            (ThemeSlot::Door, [139, 90, 43]),
+            (ThemeSlot::SecretDoor, [128, 64, 160]),
+            (ThemeSlot::Pillar, [70, 70, 70]),
+            (ThemeSlot::Column, [70, 70, 70]),
+            (ThemeSlot::Statue, [150, 150, 170]),
+            (ThemeSlot::Brazier, [255, 140, 0]),
+            (ThemeSlot::Grid, [190, 190, 190]),
+            (ThemeSlot::Hatching, [75, 75, 75]),
+            (ThemeSlot::Label, [20, 20, 20]),
+        ];
+        for (slot, [red, green, blue]) in colours {
+            theme.set_colour(slot, Rgba([red, green, blue, 255]));
+        }
+
+        theme
+    }
+}
+
+impl Theme {
+    /// The sprites of a slot
+    pub fn sprites(&self, slot: ThemeSlot) -> &[Sprite] {
+        self.sprites.get(&slot).map_or(&[], Vec::as_slice)
+    }
+
+    /// Add a sprite to a slot
+    pub fn add_sprite(&mut self, slot: ThemeSlot, sprite: Sprite) {
+        self.sprites.entry(slot).or_default().push(sprite);
+    }
+
+    /// Remove every sprite and the tint from a slot, so that it is drawn in its colour
+    pub fn clear(&mut self, slot: ThemeSlot) {
+        self.sprites.remove(&slot);
+        self.tints.remove(&slot);
+    }
+
+    /// The colour of a slot
+    pub fn colour(&self, slot: ThemeSlot) -> Rgba<u8> {
+        self.colours
+            .get(&slot)
+            .copied()
+            .unwrap_or(Rgba([0, 0, 0, 255]))
+    }
+
+    /// Set the colour of a slot
+    pub fn set_colour(&mut self, slot: ThemeSlot, colour: Rgba<u8>) {
+        self.colours.insert(slot, colour);
+    }
+
+    /// The colour that a slot's cells are blended towards, if any
+    pub fn tint(&self, slot: ThemeSlot) -> Option<Rgba<u8>> {
+        self.tints.get(&slot).copied()
+    }
+
+    /// Set or remove the colour that a slot's cells are blended towards
+    pub fn set_tint(&mut self, slot: ThemeSlot, tint: Option<Rgba<u8>>) {
+        match tint {
+            Some(tint) => self.tints.insert(slot, tint),
+            None => self.tints.remove(&slot),
+        };
+    }
+
+    /// Pick one of a slot's sprites from a hash, by weight
+    pub(super) fn pick(&self, slot: ThemeSlot, hash: u64) -> Option<usize> {
+        let sprites = self.sprites(slot);
+        let total: u64 = sprites.iter().map(|sprite| sprite.weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut choice = hash % total;
+        sprites.iter().position(|sprite| {
+            if choice < sprite.weight as u64 {
+                return true;
+            }
+            choice -= sprite.weight as u64;
+            false
+        })
+    }
+
+    /// Load a theme from a directory holding a `theme.txt` manifest, or from a manifest file
+    pub fn load(path: impl AsRef<Path>) -> Result<Theme> {
+        let path = path.as_ref();
+        let manifest = if path.is_dir() {
+            path.join(MANIFEST_NAME)
+        } else {
+            path.to_path_buf()
+        };
+        let directory = manifest.parent().map(Path::to_path_buf).unwrap_or_default();
+        let file = File::open(&manifest)?;
+        Theme::read_from(BufReader::new(file), directory)
+    }
+
+    /// Read a theme manifest from any buffered reader, finding sprites relative to `directory`
+    pub fn read_from<R: BufRead>(reader: R, directory: impl AsRef<Path>) -> Result<Theme> {
+        let directory = directory.as_ref();
+        let mut theme = Theme::default();
+        let mut replaced = vec![];
+        let mut header = false;
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let number = index + 1;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() || fields[0].starts_with('#') {
+                continue;
+            }
+
+            if !header {
+                if fields[0] != FORMAT_NAME {
+                    return Err(RpgError::Parse(number, "not an rpgtheme file".to_string()));
+                }
+                let version: u32 = fields
+                    .get(1)
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| RpgError::Parse(number, "missing format version".to_string()))?;
+                if version == 0 || version > THEME_VERSION {
+                    return Err(RpgError::UnsupportedVersion(version));
+                }
+                header = true;
+                continue;
+            }
+
+            match fields[..] {
+                ["sprite", slot, weight, file] => {
+                    let slot = ThemeSlot::from_name(slot, number)?;
+                    let weight = weight
+                        .parse()
+                        .ok()
+                        .filter(|weight| *weight > 0)
+                        .ok_or_else(|| {
+                            RpgError::Parse(number, "weight must be at least 1".to_string())
+                        })?;
+                    let sprite = Sprite::load(directory.join(file), weight)?;
+                    // The theme's own sprites replace the defaults
+                    if !replaced.contains(&slot) {
+                        theme.sprites.remove(&slot);
+                        replaced.push(slot);
+                    }
+                    theme.add_sprite(slot, sprite);
+                }
+                ["colour" | "color", slot, colour] => {
+                    let slot = ThemeSlot::from_name(slot, number)?;
+                    theme.set_colour(slot, parse_colour(colour, number)?);
+                }
+                ["tint", slot, "none"] => theme.set_tint(ThemeSlot::from_name(slot, number)?, None),
+                ["tint", slot, colour] => {
+                    let slot = ThemeSlot::from_name(slot, number)?;
+                    theme.set_tint(slot, Some(parse_colour(colour, number)?));
+                }
+                ["clear", slot] => theme.clear(ThemeSlot::from_name(slot, number)?),
+                _ => {
+                    return Err(RpgError::Parse(
+                        number,
+                        "expected a sprite, colour, tint or clear".to_string(),
+                    ))
+                }
+            }
+        }
+
+        if !header {
+            return Err(RpgError::Parse(1, "missing header".to_string()));
+        }
+        Ok(theme)
+    }
+}
+
+/// Read a colour written as #rrggbb or #rrggbbaa
+fn parse_colour(text: &str, line: usize) -> Result<Rgba<u8>> {
+    let bad = || RpgError::Parse(line, format!("bad colour '{}'", text));
+    let hex = text.strip_prefix('#').ok_or_else(bad)?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(bad());
+    }
+    let mut channels = [255; 4];
+    for (channel, index) in channels.iter_mut().zip((0..hex.len()).step_by(2)) {
+        *channel = u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| bad())?;
+    }
+    Ok(Rgba(channels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A red square
+    const SQUARE: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10">
+        <rect width="10" height="10" fill="#ff0000"/></svg>"##;
+
+    #[test]
+    fn read_manifest() {
+        let directory =
+            std::env::temp_dir().join(format!("rpgtools-theme-test-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("square.svg"), SQUARE).unwrap();
+
+        let manifest = "rpgtheme 1\n\
+                        # A theme\n\
+                        sprite room 3 square.svg\n\
+                        sprite room 1 square.svg\n\
+                        colour nothing #102030\n\
+                        color wall #10203080\n\
+                        tint room #ffffff\n\
+                        clear corridor\n";
+        let theme = Theme::read_from(manifest.as_bytes(), &directory).unwrap();
+
+        assert_eq!(2, theme.sprites(ThemeSlot::Room).len());
+        assert!(theme.sprites(ThemeSlot::Corridor).is_empty());
+        assert_eq!(None, theme.tint(ThemeSlot::Corridor));
+        assert_eq!(
+            Some(Rgba([255, 255, 255, 255])),
+            theme.tint(ThemeSlot::Room)
+        );
+        assert_eq!(Rgba([16, 32, 48, 255]), theme.colour(ThemeSlot::Nothing));
+        assert_eq!(Rgba([16, 32, 48, 128]), theme.colour(ThemeSlot::Wall));
+        // Untouched slots keep their defaults
+        assert_eq!(
+            Theme::default().colour(ThemeSlot::Door),
+            theme.colour(ThemeSlot::Door)
+        );
+
+        let loaded = Theme::load(&directory);
+        assert!(matches!(loaded, Err(RpgError::Io(_))));
+        std::fs::write(directory.join(MANIFEST_NAME), manifest).unwrap();
+        assert_eq!(theme, Theme::load(&directory).unwrap());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn bundled_themes_load() {
+        let themes = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/rpgtools/map/themes");
+        let parchment = Theme::load(themes.join("parchment")).unwrap();
+        assert!(parchment.sprites(ThemeSlot::Room).is_empty());
+        assert_eq!(Rgba([0xf1, 0xe4, 0xc3, 255]), parchment.colour(ThemeSlot::Room));
+    }
+
+    #[test]
+    fn bad_manifests() {
+        let read = |text: &str| Theme::read_from(text.as_bytes(), ".");
+
+        assert!(matches!(read("rpgtiles 1\n"), Err(RpgError::Parse(1, _))));
+        assert!(matches!(
+            read("rpgtheme 9\n"),
+            Err(RpgError::UnsupportedVersion(9))
+        ));
+        assert!(matches!(
+            read("rpgtheme 0\n"),
+            Err(RpgError::UnsupportedVersion(0))
+        ));
+        assert!(matches!(
+            read("rpgtheme 1\ncolour floor #000000\n"),
+            Err(RpgError::Parse(2, _))
+        ));
+        assert!(matches!(
+            read("rpgtheme 1\ncolour room red\n"),
+            Err(RpgError::Parse(2, _))
+        ));
+        assert!(matches!(
+            read("rpgtheme 1\nsprite room 0 a.svg\n"),
+            Err(RpgError::Parse(2, _))
+        ));
+        assert!(matches!(
+            read("rpgtheme 1\nsprite room 1 missing.svg\n"),
+            Err(RpgError::Io(_))
+        ));
+        assert!(matches!(Sprite::svg("<svg", 1), Err(RpgError::Sprite(_))));
+        assert!(matches!(
+            Sprite::png(vec![1, 2, 3], 1),
+            Err(RpgError::Sprite(_))
+        ));
+    }
+
+    #[test]
+    fn weighted_picks() {
+        let mut theme = Theme::default();
+        theme.clear(ThemeSlot::Room);
+        assert_eq!(None, theme.pick(ThemeSlot::Room, 0));
+
+        theme.add_sprite(ThemeSlot::Room, Sprite::svg(SQUARE, 3).unwrap());
+        theme.add_sprite(ThemeSlot::Room, Sprite::svg(SQUARE, 1).unwrap());
+        let picks: Vec<_> = (0..8)
+            .map(|hash| theme.pick(ThemeSlot::Room, hash).unwrap())
+            .collect();
+        assert_eq!(vec![0, 0, 0, 1, 0, 0, 0, 1], picks);
+    }
+
+    #[test]
+    fn sprites_fill_their_size() {
+        let sprite = Sprite::svg(SQUARE, 1).unwrap();
+        let image = sprite.rasterise(16);
+        assert_eq!((16, 16), image.dimensions());
+        assert_eq!(&Rgba([255, 0, 0, 255]), image.get_pixel(15, 15));
+    }
+}
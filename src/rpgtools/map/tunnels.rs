@@ -0,0 +1,172 @@
+//! Random-walker ("drunkard's walk") tunnel generator
+use rand::prelude::*;
+
+use super::{Area, GridMap, Point};
+
+/// The most walkers that can be digging at the same time
+const MAX_WALKERS: usize = 32;
+
+/// The four directions that a walker can step in
+const DIRECTIONS: [(i64, i64); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+/// A walker that digs out the cells that it walks over
+struct Walker {
+    position: Point,
+    direction: Point,
+}
+
+/// Clamp a share to between 0 and 1, using `default` if it isn't a number at all
+fn fraction(value: f64, default: f64) -> f64 {
+    if value.is_nan() {
+        default
+    } else {
+        value.clamp(0.0, 1.0)
+    }
+}
+
+impl GridMap {
+    /// Generate winding tunnels and mines by letting random walkers dig through solid rock
+    ///
+    /// Every walker starts in the middle of the map and digs out each cell that it steps on. On
+    /// every step a walker turns in a random direction with probability `turn_chance` and spawns
+    /// a new walker in its place with probability `spawn_chance`. Digging stops once `coverage`
+    /// (between 0 and 1) of the map has been dug out. Low turn chances make long, straight
+    /// tunnels, while high ones make open, cave-like mines. Values outside of 0 to 1 are clamped,
+    /// and NaNs are replaced by the defaults of `rpgmap`: 0.35, 0.2 and 0.02.
+    ///
+    /// Since every walker starts from the same place all of the tunnels are connected. The
+    /// outermost ring of cells is never dug out. This method will replace the existing map.
+    pub fn generate_tunnels(
+        &mut self,
+        walkers: usize,
+        coverage: f64,
+        turn_chance: f64,
+        spawn_chance: f64,
+        rng: &mut impl Rng,
+    ) {
+        self.clear();
+
+        let (xmax, ymax) = self.get_limits();
+        if xmax < 3 || ymax < 3 {
+            return;
+        }
+
+        // Only the inside of the map can be dug out
+        let diggable = (xmax - 2) * (ymax - 2);
+        let target = ((fraction(coverage, 0.35) * diggable as f64) as usize).max(1);
+        let turn_chance = fraction(turn_chance, 0.2);
+        let spawn_chance = fraction(spawn_chance, 0.02);
+        let lower = Point::new(1, 1);
+        let upper = Point::new(xmax as i64 - 2, ymax as i64 - 2);
+
+        let start = Point::new(xmax as i64 / 2, ymax as i64 / 2);
+        let mut walkers: Vec<Walker> = (0..walkers.clamp(1, MAX_WALKERS))
+            .map(|_| Walker {
+                position: start,
+                direction: (*DIRECTIONS.choose(rng).unwrap()).into(),
+            })
+            .collect();
+
+        let mut dug = 0;
+        // Give up eventually, in case the walkers get very unlucky
+        let mut steps_left = diggable * 1000;
+
+        while dug < target && steps_left > 0 {
+            steps_left -= 1;
+            let mut spawned = vec![];
+
+            for walker in walkers.iter_mut() {
+                let cell = self.get_cell_mut(walker.position);
+                if cell.area == Area::Nothing {
+                    cell.area = Area::Room;
+                    dug += 1;
+                }
+
+                if rng.gen_bool(spawn_chance) {
+                    spawned.push(Walker {
+                        position: walker.position,
+                        direction: (*DIRECTIONS.choose(rng).unwrap()).into(),
+                    });
+                }
+
+                if rng.gen_bool(turn_chance) {
+                    walker.direction = (*DIRECTIONS.choose(rng).unwrap()).into();
+                }
+
+                // Turn around at the edges of the map rather than leaving it
+                let mut next = walker.position + walker.direction;
+                if !next.is_in_bounds(lower, upper) {
+                    walker.direction = -walker.direction;
+                    next = walker.position + walker.direction;
+                }
+                if next.is_in_bounds(lower, upper) {
+                    walker.position = next;
+                }
+            }
+
+            let room = MAX_WALKERS.saturating_sub(walkers.len());
+            walkers.extend(spawned.into_iter().take(room));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+
+    fn count_rooms(map: &GridMap) -> usize {
+        let (xmax, ymax) = map.get_limits();
+        (0..xmax)
+            .flat_map(|x| (0..ymax).map(move |y| (x as i64, y as i64)))
+            .filter(|p| map.get_cell_ref(*p).is_room())
+            .count()
+    }
+
+    #[test]
+    fn tunnels_reach_coverage() {
+        let mut map = GridMap::new(40, 30);
+        map.generate_tunnels(3, 0.4, 0.2, 0.05, &mut StdRng::seed_from_u64(3));
+
+        let target = (0.4 * (38 * 28) as f64) as usize;
+        assert!(count_rooms(&map) >= target);
+
+        // The border is never dug out
+        for x in 0..40 {
+            assert!(!map.get_cell_ref((x, 0)).is_room());
+            assert!(!map.get_cell_ref((x, 29)).is_room());
+        }
+        for y in 0..30 {
+            assert!(!map.get_cell_ref((0, y)).is_room());
+            assert!(!map.get_cell_ref((39, y)).is_room());
+        }
+    }
+
+    #[test]
+    fn tunnels_are_reproducible() {
+        let mut map0 = GridMap::new(30, 30);
+        let mut map1 = GridMap::new(30, 30);
+        map0.generate_tunnels(2, 0.3, 0.5, 0.1, &mut StdRng::seed_from_u64(9));
+        map1.generate_tunnels(2, 0.3, 0.5, 0.1, &mut StdRng::seed_from_u64(9));
+
+        assert_eq!(map0, map1);
+    }
+
+    #[test]
+    fn nan_is_the_default() {
+        let mut map0 = GridMap::new(30, 30);
+        let mut map1 = GridMap::new(30, 30);
+        map0.generate_tunnels(2, f64::NAN, f64::NAN, f64::NAN, &mut StdRng::seed_from_u64(4));
+        map1.generate_tunnels(2, 0.35, 0.2, 0.02, &mut StdRng::seed_from_u64(4));
+
+        assert_eq!(map0, map1);
+    }
+
+    #[test]
+    fn tiny_map() {
+        let mut map = GridMap::new(2, 2);
+        map.generate_tunnels(1, 1.0, 0.5, 0.5, &mut StdRng::seed_from_u64(0));
+        assert_eq!(0, count_rooms(&map));
+    }
+}
@@ -0,0 +1,665 @@
+//! Wave Function Collapse (WFC) generator driven by tile rules
+//!
+//! Every cell of the map is filled with one tile from a [`TileSet`]. Each tile has a socket on
+//! each of its four sides, and two tiles can only sit next to each other if the sockets that face
+//! each other fit. Sockets with the same name fit, and the rules can add or remove pairs. Tile
+//! sets are usually written in a plain text rule file:
+//!
+//! ```text
+//! rpgtiles 1
+//! # Sockets that face the edge of the map
+//! border r
+//! # The closed sides of corridors fit against rock, but not against each other
+//! fit w r
+//! nofit w w
+//!
+//! # name  area weight  N E S W  walls
+//! rock    .    10      r r r r
+//! ns      C    2       c w c w
+//! ew      C    2       w c w c
+//! gate    C    1       c w c w  .D.D
+//! ```
+//!
+//! The area uses the same codes as the native map format. The optional walls are four codes, in
+//! north, east, south, west order, that are placed on the edges of the cell. Lines starting with
+//! `#` are comments.
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use rand::prelude::*;
+
+use super::cell::{Point as CornerPoint, Side, Wall};
+use super::file::{decode_area, decode_wall};
+use super::{Area, GridMap, Point};
+use crate::error::{Result, RpgError};
+
+/// Name at the start of every rule file
+const FORMAT_NAME: &str = "rpgtiles";
+
+/// Current version of the rule file format
+pub const RULES_VERSION: u32 = 1;
+
+/// File extension used for rule files
+pub const RULES_EXTENSION: &str = "rules";
+
+/// The most tiles that a tile set can hold
+pub const MAX_TILES: usize = 64;
+
+/// Sides in the order that they are written in a rule file
+const SIDES: [Side; 4] = [Side::North, Side::East, Side::South, Side::West];
+
+/// One kind of tile that can fill a cell
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tile {
+    pub name: String,
+    /// The area that the tile paints on the map
+    pub area: Area,
+    /// How likely the tile is to be picked, relative to the other tiles
+    pub weight: u32,
+    /// Sockets on the north, east, south and west sides
+    pub sockets: [String; 4],
+    /// Walls on the north, east, south and west sides
+    pub walls: [Wall; 4],
+}
+
+impl Tile {
+    fn socket(&self, side: Side) -> &str {
+        &self.sockets[side_index(side)]
+    }
+}
+
+/// A set of tiles and the rules for placing them next to each other
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TileSet {
+    tiles: Vec<Tile>,
+    /// The socket that tiles must fit on sides that face the edge of the filled area
+    border: Option<String>,
+    /// Pairs of different sockets that fit together
+    fits: Vec<(String, String)>,
+    /// Pairs of sockets that don't fit together, even if they have the same name
+    no_fits: Vec<(String, String)>,
+}
+
+impl TileSet {
+    /// Make an empty tile set
+    pub fn new() -> TileSet {
+        TileSet::default()
+    }
+
+    /// The tile set for winding corridors through rock that is built into the library
+    pub fn corridors() -> TileSet {
+        TileSet::read_from(include_str!("rules/corridors.rules").as_bytes())
+            .expect("bug: built-in corridor rules are broken")
+    }
+
+    /// Add a tile to the set
+    pub fn add_tile(&mut self, tile: Tile) -> Result<()> {
+        if self.tiles.len() >= MAX_TILES {
+            return Err(RpgError::TooManyTiles(MAX_TILES));
+        }
+        self.tiles.push(tile);
+        Ok(())
+    }
+
+    /// Set the socket that tiles must fit on sides that face the edge of the filled area
+    pub fn set_border(&mut self, socket: Option<String>) {
+        self.border = socket;
+    }
+
+    /// Let two sockets fit together
+    pub fn add_fit(&mut self, socket0: &str, socket1: &str) {
+        self.fits.push((socket0.to_string(), socket1.to_string()));
+    }
+
+    /// Stop two sockets from fitting together, even if they have the same name
+    pub fn add_no_fit(&mut self, socket0: &str, socket1: &str) {
+        self.no_fits.push((socket0.to_string(), socket1.to_string()));
+    }
+
+    /// Whether two sockets fit together
+    pub fn fits(&self, socket0: &str, socket1: &str) -> bool {
+        let is_pair = |(a, b): &(String, String)| {
+            (a == socket0 && b == socket1) || (a == socket1 && b == socket0)
+        };
+        if self.no_fits.iter().any(is_pair) {
+            return false;
+        }
+        socket0 == socket1 || self.fits.iter().any(is_pair)
+    }
+
+    /// The tiles in the set
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    /// Whether any of the tiles in the set paints `area`
+    pub fn has_area(&self, area: &Area) -> bool {
+        self.tiles.iter().any(|tile| &tile.area == area)
+    }
+
+    /// Load a tile set from a rule file
+    pub fn load(path: impl AsRef<Path>) -> Result<TileSet> {
+        let file = File::open(path)?;
+        TileSet::read_from(BufReader::new(file))
+    }
+
+    /// Read a tile set in the rule file format from any buffered reader
+    pub fn read_from<R: BufRead>(reader: R) -> Result<TileSet> {
+        let mut tiles = TileSet::new();
+        let mut header = false;
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let number = index + 1;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() || fields[0].starts_with('#') {
+                continue;
+            }
+
+            if !header {
+                if fields[0] != FORMAT_NAME {
+                    return Err(RpgError::Parse(number, "not an rpgtiles file".to_string()));
+                }
+                let version: u32 = fields
+                    .get(1)
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| RpgError::Parse(number, "missing format version".to_string()))?;
+                if version == 0 || version > RULES_VERSION {
+                    return Err(RpgError::UnsupportedVersion(version));
+                }
+                header = true;
+                continue;
+            }
+
+            match fields[..] {
+                ["border", socket] => tiles.border = Some(socket.to_string()),
+                ["fit", socket0, socket1] => tiles.add_fit(socket0, socket1),
+                ["nofit", socket0, socket1] => tiles.add_no_fit(socket0, socket1),
+                [name, area, weight, north, east, south, west, ref walls @ ..] => {
+                    let area = match area.chars().collect::<Vec<_>>()[..] {
+                        [area] => decode_area(area, number)?,
+                        _ => return Err(RpgError::Parse(number, format!("bad area '{}'", area))),
+                    };
+                    let weight = weight
+                        .parse()
+                        .ok()
+                        .filter(|weight| *weight > 0)
+                        .ok_or_else(|| RpgError::Parse(number, "weight must be at least 1".to_string()))?;
+
+                    let walls = match walls {
+                        [] => [Wall::Nothing; 4],
+                        [walls] if walls.chars().count() == 4 => {
+                            let mut decoded = [Wall::Nothing; 4];
+                            for (slot, wall) in decoded.iter_mut().zip(walls.chars()) {
+                                *slot = decode_wall(wall, number)?;
+                            }
+                            decoded
+                        }
+                        _ => return Err(RpgError::Parse(number, "walls must be four codes".to_string())),
+                    };
+
+                    tiles
+                        .add_tile(Tile {
+                            name: name.to_string(),
+                            area,
+                            weight,
+                            sockets: [north, east, south, west].map(str::to_string),
+                            walls,
+                        })
+                        .map_err(|e| RpgError::Parse(number, e.to_string()))?;
+                }
+                _ => return Err(RpgError::Parse(number, "expected a tile, border, fit or nofit".to_string())),
+            }
+        }
+
+        if !header {
+            return Err(RpgError::Parse(1, "missing header".to_string()));
+        }
+        Ok(tiles)
+    }
+
+    /// For each side of each tile, the tiles that may sit on that side
+    fn compatible(&self) -> [Vec<u64>; 4] {
+        SIDES.map(|side| {
+            self.tiles
+                .iter()
+                .map(|tile| {
+                    self.tiles
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, other)| self.fits(tile.socket(side), other.socket(side.opposite())))
+                        .fold(0, |mask, (index, _)| mask | 1 << index)
+                })
+                .collect()
+        })
+    }
+
+    /// The tiles that may sit against the edge of the filled area on one side
+    fn border_mask(&self, side: Side) -> u64 {
+        let Some(border) = &self.border else {
+            return self.full_mask();
+        };
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| self.fits(tile.socket(side), border))
+            .fold(0, |mask, (index, _)| mask | 1 << index)
+    }
+
+    fn full_mask(&self) -> u64 {
+        match self.tiles.len() {
+            MAX_TILES => u64::MAX,
+            len => (1 << len) - 1,
+        }
+    }
+}
+
+fn side_index(side: Side) -> usize {
+    match side {
+        Side::North => 0,
+        Side::East => 1,
+        Side::South => 2,
+        Side::West => 3,
+    }
+}
+
+/// The state of the region that is being collapsed
+///
+/// Every cell holds a bit mask of the tiles that it could still be. Changes to the masks are
+/// recorded on a trail so that they can be undone when a guess leads to a contradiction.
+struct Solver {
+    width: usize,
+    height: usize,
+    domains: Vec<u64>,
+    compatible: [Vec<u64>; 4],
+    trail: Vec<(usize, u64)>,
+}
+
+impl Solver {
+    fn neighbour(&self, index: usize, side: Side) -> Option<usize> {
+        let (x, y) = (index % self.width, index / self.width);
+        let (dx, dy) = side.offset();
+        let x = x.checked_add_signed(dx as isize)?;
+        let y = y.checked_add_signed(dy as isize)?;
+        (x < self.width && y < self.height).then_some(y * self.width + x)
+    }
+
+    fn set(&mut self, index: usize, mask: u64) {
+        if self.domains[index] != mask {
+            self.trail.push((index, self.domains[index]));
+            self.domains[index] = mask;
+        }
+    }
+
+    fn undo_to(&mut self, mark: usize) {
+        while self.trail.len() > mark {
+            let (index, mask) = self.trail.pop().unwrap();
+            self.domains[index] = mask;
+        }
+    }
+
+    /// Remove tiles that no longer fit next to their neighbours. Returns false if a cell runs
+    /// out of tiles.
+    fn propagate(&mut self, start: impl IntoIterator<Item = usize>) -> bool {
+        let mut queue: VecDeque<usize> = start.into_iter().collect();
+
+        while let Some(index) = queue.pop_front() {
+            let domain = self.domains[index];
+            if domain == 0 {
+                return false;
+            }
+
+            for side in SIDES {
+                let Some(next) = self.neighbour(index, side) else {
+                    continue;
+                };
+                let allowed = (0..64)
+                    .filter(|tile| domain & 1 << tile != 0)
+                    .fold(0, |mask, tile| mask | self.compatible[side_index(side)][tile]);
+                let narrowed = self.domains[next] & allowed;
+                if narrowed != self.domains[next] {
+                    if narrowed == 0 {
+                        return false;
+                    }
+                    self.set(next, narrowed);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Pick one of the undecided cells with the fewest tiles left
+    fn lowest_entropy(&self, rng: &mut impl Rng) -> Option<usize> {
+        let counts = self.domains.iter().map(|domain| domain.count_ones());
+        let lowest = counts.clone().filter(|count| *count > 1).min()?;
+        let candidates: Vec<usize> = counts
+            .enumerate()
+            .filter(|(_, count)| *count == lowest)
+            .map(|(index, _)| index)
+            .collect();
+        candidates.choose(rng).copied()
+    }
+}
+
+impl GridMap {
+    /// Fill the whole map with tiles from a tile set by Wave Function Collapse
+    ///
+    /// This method will replace the existing map. See [`GridMap::fill_wfc`].
+    pub fn generate_wfc(&mut self, tiles: &TileSet, rng: &mut impl Rng) -> Result<()> {
+        self.clear();
+        let (xmax, ymax) = self.get_limits();
+        if xmax == 0 || ymax == 0 {
+            return Ok(());
+        }
+        self.fill_wfc(tiles, (0, 0), (xmax as i64 - 1, ymax as i64 - 1), &[], rng)
+    }
+
+    /// Fill a rectangle of the map with tiles from a tile set by Wave Function Collapse
+    ///
+    /// The rectangle runs from `point0` to `point1`, inclusive. Cells in `pinned` keep their
+    /// current contents, and the tiles around them must fit a tile with the same area. Sides of
+    /// the rectangle must fit the tile set's border socket; the cells outside the rectangle are not
+    /// otherwise looked at. Walls and corner features that the filled cells share with cells
+    /// outside of the rectangle or with pinned cells are kept.
+    ///
+    /// When a guess leads to a cell that no tile fits, the guess is undone and another tile is
+    /// tried. An error is returned if the rules can't be satisfied, in which case the map is left
+    /// unchanged.
+    pub fn fill_wfc(
+        &mut self,
+        tiles: &TileSet,
+        point0: impl Into<Point>,
+        point1: impl Into<Point>,
+        pinned: &[Point],
+        rng: &mut impl Rng,
+    ) -> Result<()> {
+        let (point0, point1) = (point0.into(), point1.into());
+        let min = Point::new(point0.x.min(point1.x), point0.y.min(point1.y));
+        let max = Point::new(point0.x.max(point1.x), point0.y.max(point1.y));
+        if !self.in_bounds(min) || !self.in_bounds(max) {
+            return Err(RpgError::OutOfBounds);
+        }
+        if tiles.tiles.is_empty() {
+            return Err(RpgError::Empty("tile set has no tiles".to_string()));
+        }
+
+        let width = (max.x - min.x + 1) as usize;
+        let height = (max.y - min.y + 1) as usize;
+        let mut solver = Solver {
+            width,
+            height,
+            domains: vec![tiles.full_mask(); width * height],
+            compatible: tiles.compatible(),
+            trail: vec![],
+        };
+
+        // Apply the border and the pinned cells before guessing anything
+        for index in 0..width * height {
+            let mut domain = solver.domains[index];
+            for side in SIDES {
+                if solver.neighbour(index, side).is_none() {
+                    domain &= tiles.border_mask(side);
+                }
+            }
+            solver.domains[index] = domain;
+        }
+        for point in pinned {
+            if !point.is_in_bounds(min, max) {
+                continue;
+            }
+            let index = (point.y - min.y) as usize * width + (point.x - min.x) as usize;
+            let area = self[*point].area();
+            let matching = tiles
+                .tiles
+                .iter()
+                .enumerate()
+                .filter(|(_, tile)| &tile.area == area)
+                .fold(0, |mask, (index, _)| mask | 1 << index);
+            solver.domains[index] &= matching;
+        }
+        if !solver.propagate(0..width * height) {
+            return Err(RpgError::Contradiction(
+                "the pinned cells and border don't fit any tiles".to_string(),
+            ));
+        }
+
+        // Collapse one cell at a time, backing up when a guess doesn't work out
+        let mut guesses: Vec<(usize, usize, usize)> = vec![];
+        let mut backtracks_left = 16 * width * height;
+        while let Some(index) = solver.lowest_entropy(rng) {
+            let domain = solver.domains[index];
+            let options: Vec<usize> = (0..tiles.tiles.len()).filter(|tile| domain & 1 << tile != 0).collect();
+            let tile = *options
+                .choose_weighted(rng, |tile| tiles.tiles[*tile].weight)
+                .expect("bug: cell has no tiles left");
+
+            guesses.push((solver.trail.len(), index, tile));
+            solver.set(index, 1 << tile);
+            if solver.propagate([index]) {
+                continue;
+            }
+
+            // Undo guesses until one can be ruled out without running out of tiles
+            loop {
+                let Some((mark, index, tile)) = guesses.pop() else {
+                    return Err(RpgError::Contradiction("every choice of tiles was tried".to_string()));
+                };
+                if backtracks_left == 0 {
+                    return Err(RpgError::Contradiction("gave up after too many retries".to_string()));
+                }
+                backtracks_left -= 1;
+
+                solver.undo_to(mark);
+                let remaining = solver.domains[index] & !(1 << tile);
+                if remaining == 0 {
+                    continue;
+                }
+                solver.set(index, remaining);
+                if solver.propagate([index]) {
+                    break;
+                }
+            }
+        }
+
+        // Paint the tiles onto the map. A cell's west and north edges and its north-west corner
+        // are shared with the cells beyond them, so they are only cleared if those cells are
+        // being filled too. The tiles' walls go on once all of the cells have been cleared.
+        let filled = |point: Point| point.is_in_bounds(min, max) && !pinned.contains(&point);
+        let placed: Vec<(Point, &Tile)> = solver
+            .domains
+            .iter()
+            .enumerate()
+            .map(|(index, domain)| {
+                let point = Point::new(min.x + (index % width) as i64, min.y + (index / width) as i64);
+                (point, &tiles.tiles[domain.trailing_zeros() as usize])
+            })
+            .filter(|(point, _)| filled(*point))
+            .collect();
+
+        for (point, tile) in &placed {
+            let west = filled(*point + Point::new(-1, 0));
+            let north = filled(*point + Point::new(0, -1));
+            let north_west = filled(*point + Point::new(-1, -1));
+
+            let cell = self.get_cell_mut(*point);
+            cell.set_area(tile.area.clone());
+            if west {
+                cell.set_vert_wall(Wall::Nothing);
+            }
+            if north {
+                cell.set_horiz_wall(Wall::Nothing);
+            }
+            if west && north && north_west {
+                cell.set_point(CornerPoint::Nothing);
+            }
+        }
+        for (point, tile) in &placed {
+            for (side, wall) in SIDES.into_iter().zip(tile.walls) {
+                if wall != Wall::Nothing {
+                    // Walls on the far edges of the map have nowhere to go
+                    self.set_wall(*point, side, wall).ok();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn corridors_join_on_open_sides() {
+        let tiles = TileSet::corridors();
+        for seed in 0..5 {
+            let mut map = GridMap::new(20, 15);
+            map.generate_wfc(&tiles, &mut StdRng::seed_from_u64(seed)).unwrap();
+
+            // Every corridor tile has an open side that leads to another corridor
+            for x in 0..20 {
+                for y in 0..15 {
+                    let point = Point::new(x, y);
+                    if !map[point].is_room() {
+                        continue;
+                    }
+                    assert!(SIDES.iter().any(|side| {
+                        let next = point + side.offset().into();
+                        map.in_bounds(next) && map[next].is_room()
+                    }));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pinned_cells_are_kept() {
+        let tiles = TileSet::corridors();
+        let mut map = GridMap::new(12, 12);
+        let pinned: Vec<Point> = vec![(5, 5).into(), (6, 5).into(), (5, 6).into(), (6, 6).into()];
+        for point in &pinned {
+            map.get_cell_mut(*point).set_area(Area::Corridor);
+        }
+        map.set_wall((5, 5), Side::North, Wall::Door).unwrap();
+
+        map.fill_wfc(&tiles, (0, 0), (11, 11), &pinned, &mut StdRng::seed_from_u64(1))
+            .unwrap();
+
+        for point in &pinned {
+            assert_eq!(&Area::Corridor, map[*point].area());
+        }
+        assert_eq!(&Wall::Door, map.get_wall((5, 5), Side::North).unwrap());
+    }
+
+    #[test]
+    fn fill_keeps_shared_edges() {
+        let tiles = TileSet::corridors();
+        let mut map = GridMap::new(12, 12);
+        map.place_room((0, 0), (11, 11)).unwrap();
+        let pinned = [Point::new(5, 5)];
+        // Edges that the filled cells share with a pinned cell and with cells outside
+        map.set_wall((5, 5), Side::East, Wall::Door).unwrap();
+        map.set_wall((5, 5), Side::South, Wall::Wall).unwrap();
+        map.set_wall((3, 4), Side::West, Wall::SecretDoor).unwrap();
+        map.set_wall((4, 3), Side::North, Wall::Wall).unwrap();
+        map.set_corner((3, 3), CornerPoint::Statue).unwrap();
+        map.get_cell_mut((5, 5)).set_area(Area::Nothing);
+
+        map.fill_wfc(&tiles, (3, 3), (8, 8), &pinned, &mut StdRng::seed_from_u64(2))
+            .unwrap();
+
+        assert_eq!(&Wall::Door, map.get_wall((5, 5), Side::East).unwrap());
+        assert_eq!(&Wall::Wall, map.get_wall((5, 5), Side::South).unwrap());
+        assert_eq!(&Wall::SecretDoor, map.get_wall((3, 4), Side::West).unwrap());
+        assert_eq!(&Wall::Wall, map.get_wall((4, 3), Side::North).unwrap());
+        assert_eq!(&CornerPoint::Statue, map.get_corner((3, 3)).unwrap());
+    }
+
+    #[test]
+    fn fill_only_touches_region() {
+        let tiles = TileSet::corridors();
+        let mut map = GridMap::new(12, 12);
+        map.place_room((0, 0), (11, 11)).unwrap();
+        map.fill_wfc(&tiles, (3, 3), (8, 8), &[], &mut StdRng::seed_from_u64(2))
+            .unwrap();
+
+        assert!(map[Point::new(2, 2)].is_room());
+        assert!(map[Point::new(9, 9)].is_room());
+    }
+
+    #[test]
+    fn impossible_rules() {
+        // The only tile can't sit above or below itself
+        let text = "rpgtiles 1\nodd R 1 a x b x\n";
+        let tiles = TileSet::read_from(text.as_bytes()).unwrap();
+
+        let mut map = GridMap::new(4, 4);
+        let result = map.generate_wfc(&tiles, &mut StdRng::seed_from_u64(0));
+        assert!(matches!(result, Err(RpgError::Contradiction(_))));
+
+        // A single row works, since nothing is above or below
+        let mut map = GridMap::new(4, 1);
+        map.generate_wfc(&tiles, &mut StdRng::seed_from_u64(0)).unwrap();
+    }
+
+    #[test]
+    fn tiles_come_in_pairs() {
+        // A "left" tile always has a "right" tile to its east, and the other way around
+        let text = "rpgtiles 1\nborder e\n\
+                    left R 1 e p e e\n\
+                    right R 1 e e e p\n\
+                    rock . 1 e e e e\n";
+        let tiles = TileSet::read_from(text.as_bytes()).unwrap();
+        for seed in 0..10 {
+            let mut map = GridMap::new(7, 3);
+            map.generate_wfc(&tiles, &mut StdRng::seed_from_u64(seed)).unwrap();
+
+            for y in 0..3 {
+                let rooms = (0..7).filter(|x| map[Point::new(*x, y)].is_room()).count();
+                assert_eq!(0, rooms % 2);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_rules() {
+        let text = "# A comment\nrpgtiles 1\nborder r\nfit w r\nnofit w w\n\n\
+                    rock . 3 r r r r\ngate R 1 c w c w .D.D\n";
+        let tiles = TileSet::read_from(text.as_bytes()).unwrap();
+
+        assert_eq!(2, tiles.tiles().len());
+        assert!(tiles.has_area(&Area::Room));
+        assert!(!tiles.has_area(&Area::Corridor));
+        assert_eq!(Some("r".to_string()), tiles.border);
+        assert_eq!(Area::Room, tiles.tiles()[1].area);
+        assert_eq!([Wall::Nothing, Wall::Door, Wall::Nothing, Wall::Door], tiles.tiles()[1].walls);
+        assert!(tiles.fits("r", "r"));
+        assert!(tiles.fits("r", "w"));
+        assert!(!tiles.fits("w", "w"));
+        assert!(!tiles.fits("c", "r"));
+
+        assert!(matches!(
+            TileSet::read_from("rpgtiles 1\nrock . 0 r r r r\n".as_bytes()),
+            Err(RpgError::Parse(2, _))
+        ));
+        assert!(matches!(
+            TileSet::read_from("rpgtiles 1\nrock . 1 r r r\n".as_bytes()),
+            Err(RpgError::Parse(2, _))
+        ));
+        assert!(matches!(
+            TileSet::read_from("rpgtiles 7\n".as_bytes()),
+            Err(RpgError::UnsupportedVersion(7))
+        ));
+        assert!(matches!(
+            TileSet::read_from("rpgtiles 0\n".as_bytes()),
+            Err(RpgError::UnsupportedVersion(0))
+        ));
+    }
+}